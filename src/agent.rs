@@ -1,54 +1,119 @@
-use ndarray::{Array1, Array2};
+use std::collections::VecDeque;
+
+use ndarray::{Array1, Array2, s};
 use rand::Rng;
 use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    brain::{Brain, HIDDEN_SIZE, INPUT_SIZE, OUTPUT_SIZE},
-    world::{AgentId, CHILD_INIT_ENERGY, INIT_ENERGY, LIFESPAN_RANGE, MAX_ENERGY, Position},
+    brain::{Brain, HIDDEN_SIZE, OutputLayout},
+    params::{
+        ArmorParams, MutationRate, OutputBiasInit, Params, ReserveParams, RoleParams,
+    },
+    world::{AgentId, CHILD_INIT_ENERGY, LIFESPAN_RANGE, MAX_ENERGY, Position},
 };
 
 pub type Color = [f32; 3];
 
-#[derive(Debug, Clone)]
+/// 行動の履歴を何個まで覚えておくか
+pub const ACTION_HISTORY_LEN: usize = 16;
+
+/// 新しく置く個体の、ランダムにしないで決めておく部分（`World::try_add_agent` 用）。
+/// 書かなかった部分は `Agent::new_random` と同じ
+#[derive(Debug, Clone, Default)]
+pub struct AgentSpec {
+    pub color: Option<Color>,
+    /// 最初のエネルギー（最大エネルギーまで）
+    pub energy: Option<u32>,
+    /// 形は設定（`Params::brain_shape`）と合っていないといけない
+    pub brain: Option<Brain>,
+    /// 向いている方向
+    pub facing: Option<Direction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Agent {
-    pub(crate) id: AgentId,
-    pub(crate) pos: Position,
-    pub(crate) energy: u32,
-    pub(crate) max_energy: u32,
+    pub id: AgentId,
+    /// 読みやすい名前（ログやインスペクタ用）
+    pub name: String,
+    pub pos: Position,
+    pub energy: u32,
+    pub max_energy: u32,
+    /// 鎧（0.0〜1.0）。受ける攻撃のダメージをこの割合だけ減らす（`ArmorParams`）
+    pub armor: f32,
+    /// 食べすぎた分の蓄え（`ReserveParams::store_overflow` のときだけ増える）
+    pub reserve: u32,
     pub generation: u32,
+    /// 家系の始まりの個体（最初に撒かれた個体）のID。最初の個体は自分自身
+    pub founder: AgentId,
 
     pub(crate) brain: Brain,
 
-    pub(crate) color: Color,
+    pub color: Color,
 
-    pub(crate) last_action: Option<Action>,
+    pub last_action: Option<Action>,
+    /// 最近とった行動（古い順、最大 ACTION_HISTORY_LEN 個）。インスペクタ用
+    pub action_history: VecDeque<Action>,
+    /// 向いている方向（最後に移動しようとした方向）。ダッシュはこっちに進む
+    pub facing: Direction,
+    /// 最後に攻撃したステップ（攻撃のクールダウン用）
+    pub(crate) last_attack_step: Option<u64>,
 
-    pub(crate) age: u32,
+    /// 最近の攻撃回数と食べた餌の数（少しずつ忘れていくカウンタ）
+    pub(crate) recent_attacks: f32,
+    pub(crate) recent_food: f32,
+    /// エネルギーが死ぬしきい値以下になっているステップ数
+    pub(crate) starving_steps: u32,
+
+    pub age: u32,
     /// 寿命（この歳になったら死ぬ）
-    pub(crate) lifespan: u32,
+    pub lifespan: u32,
 }
 
 impl Agent {
     /// ランダムな個体を生成。
     /// 最初のアダムとイブ用。
-    pub fn new_random<R: Rng + ?Sized>(id: usize, pos: Position, rng: &mut R) -> Self {
+    pub fn new_random<R: Rng + ?Sized>(
+        id: usize,
+        name: String,
+        pos: Position,
+        params: &Params,
+        rng: &mut R,
+    ) -> Self {
         // 重みを正規分布で初期化
-        let w1 = random_matrix(HIDDEN_SIZE, INPUT_SIZE, rng);
+        let w1 = random_matrix(HIDDEN_SIZE, params.input_size(), rng);
         let b1 = Array1::zeros(HIDDEN_SIZE);
-        let w2 = random_matrix(OUTPUT_SIZE, HIDDEN_SIZE, rng);
-        let b2 = Array1::zeros(OUTPUT_SIZE);
+        let output_size = params.output_layout().size();
+        let w2 = random_matrix(output_size, HIDDEN_SIZE, rng);
+        let mut b2 = Array1::zeros(output_size);
+        if let OutputBiasInit::Prior(action_biases) = params.output_bias_init {
+            b2.slice_mut(s![..action_biases.len()])
+                .assign(&Array1::from(action_biases.to_vec()));
+        }
 
         let brain = Brain::new(w1, b1, w2, b2);
 
         Self {
             id,
+            name,
             pos,
-            energy: INIT_ENERGY,
+            energy: params.init_energy.min(MAX_ENERGY),
             max_energy: MAX_ENERGY,
+            armor: params
+                .armor
+                .map_or(0.0, |armor| armor.initial.clamp(0.0, 1.0)),
+            reserve: 0,
             generation: 1,
+            founder: id,
             brain,
-            color: [rng.random(), rng.random(), rng.random()],
+            color: params.founder_colors.pick(id, rng),
             last_action: None,
+            action_history: VecDeque::with_capacity(ACTION_HISTORY_LEN),
+            last_attack_step: None,
+            facing: Direction::Up,
+            recent_attacks: 0.0,
+            recent_food: 0.0,
+            starving_steps: 0,
             age: 0,
             lifespan: rng.random_range(LIFESPAN_RANGE),
         }
@@ -56,19 +121,27 @@ impl Agent {
 
     /// 子供を生成する
     /// - new_id: 新しいID
+    /// - new_name: 新しい名前
     /// - new_pos: 生まれる場所
+    /// - mutation: 脳の突然変異の強さ（`MutationSchedule::at` で決める）
+    /// - armor: 鎧の遺伝子の設定（なければ鎧は変異させない）
     /// - rng: 乱数生成器
     pub fn new_child<R: Rng + ?Sized>(
         &self,
         new_id: usize,
+        new_name: String,
         new_pos: Position,
+        mutation: MutationRate,
+        armor: Option<&ArmorParams>,
         rng: &mut R,
     ) -> Self {
         // 1. 脳の遺伝と変異
         // Brain::spawn_child を呼び出す。
-        // rate: 1.0 (全パラメータを変異させる「ドリフト」方式を採用)
-        // sigma: 0.02 (親の値を少しだけズラす)
-        let child_brain = self.brain.spawn_child(1.0, 0.2, rng);
+        // デフォルトは rate: 1.0 (全パラメータを変異させる「ドリフト」方式を採用)
+        // sigma: 0.2 (親の値を少しだけズラす)
+        let child_brain =
+            self.brain
+                .spawn_child(mutation.rate.clamp(0.0, 1.0), mutation.sigma, rng);
 
         // 2. 最大エネルギー(体格)の遺伝と変異
         // 親の値を基準に ±5 の範囲でランダムに変化させる
@@ -77,14 +150,29 @@ impl Agent {
         let diff = rng.random_range(-mutation_range..=mutation_range);
         let child_max_energy = (self.max_energy as i32 + diff).clamp(10, 500) as u32;
 
+        // 3. 鎧の遺伝と変異（設定がないときは乱数を引かない）
+        let child_armor = match armor {
+            Some(armor) => {
+                let range = armor.mutation.abs();
+                (self.armor + rng.random_range(-range..=range)).clamp(0.0, 1.0)
+            }
+            None => self.armor,
+        };
+
         Self {
             id: new_id,
+            name: new_name,
             pos: new_pos,
 
             // 生まれたての状態設定
-            energy: CHILD_INIT_ENERGY, // 子供の初期体力（親のコスト50と同じにして等価交換にする）
+            // 子供の初期体力（親のコスト50と同じにして等価交換にする）。
+            // 体格が小さくて最大エネルギーが足りない子は、最大までしか持てない
+            energy: CHILD_INIT_ENERGY.min(child_max_energy),
             max_energy: child_max_energy,
+            armor: child_armor,
+            reserve: 0,
             generation: self.generation + 1, // 世代を1つ進める
+            founder: self.founder,
 
             brain: child_brain,
 
@@ -92,11 +180,176 @@ impl Agent {
             // (動き始めれば Brain の出力によってすぐに自分の色に変わるよ！)
             color: self.color,
             last_action: None,
+            action_history: VecDeque::with_capacity(ACTION_HISTORY_LEN),
+            last_attack_step: None,
+            // 親と同じ方向を向いて生まれる
+            facing: self.facing,
+            recent_attacks: 0.0,
+            recent_food: 0.0,
+            starving_steps: 0,
 
             age: 0,
             lifespan: rng.random_range(LIFESPAN_RANGE),
         }
     }
+
+    /// 餌を食べて gain だけ回復する。
+    /// 最大を超えた分は、設定によって蓄えに回すか捨てる。
+    /// 実際に増えた量（エネルギーと蓄えの合計）を返す
+    pub(crate) fn eat(&mut self, gain: u32, reserve: &ReserveParams) -> u32 {
+        let before = self.energy + self.reserve;
+        let total = self.energy + gain;
+        self.energy = total.min(self.max_energy);
+        if reserve.store_overflow {
+            let overflow = total - self.energy;
+            self.reserve = (self.reserve + overflow).min(reserve.capacity);
+        }
+        (self.energy + self.reserve).saturating_sub(before)
+    }
+
+    /// 鎧で減らしたあとの、受けるダメージ
+    pub(crate) fn armored(&self, damage: u32) -> u32 {
+        if self.armor <= 0.0 {
+            return damage;
+        }
+        (damage as f64 * (1.0 - self.armor.min(1.0) as f64)).round() as u32
+    }
+
+    /// 飢えてきたら（エネルギーが最大の半分未満）蓄えを取り崩す
+    pub(crate) fn draw_reserve(&mut self, reserve: &ReserveParams) {
+        if self.reserve == 0 || self.energy >= self.max_energy / 2 {
+            return;
+        }
+        let amount = self
+            .reserve
+            .min(reserve.draw_per_step)
+            .min(self.max_energy - self.energy);
+        self.reserve -= amount;
+        self.energy += amount;
+    }
+
+    /// 実際にとった行動を履歴に残す。古いものから捨てる
+    pub(crate) fn push_history(&mut self, action: Action) {
+        if self.action_history.len() == ACTION_HISTORY_LEN {
+            self.action_history.pop_front();
+        }
+        self.action_history.push_back(action);
+    }
+
+    /// 最近の行動から役割を分類する
+    pub fn role(&self, params: &RoleParams) -> Role {
+        // 餌を食べていなくても、攻撃していれば捕食者
+        if self.recent_attacks > 0.0
+            && self.recent_attacks >= params.predator_ratio * self.recent_food
+        {
+            Role::Predator
+        } else {
+            Role::Prey
+        }
+    }
+
+    /// 脳（読むだけ。書き換えは世界の中だけでする）
+    pub fn brain(&self) -> &Brain {
+        &self.brain
+    }
+
+    /// 脳だけ空っぽにしたコピー。脳は大きいので、脳以外が変わったかを見るとき用。
+    /// フィールドを増やしたら、ここでコンパイルエラーになって気づけるようにしてある
+    pub(crate) fn clone_without_brain(&self) -> Agent {
+        let Agent {
+            id,
+            name,
+            pos,
+            energy,
+            max_energy,
+            armor,
+            reserve,
+            generation,
+            founder,
+            brain: _,
+            color,
+            last_action,
+            action_history,
+            facing,
+            last_attack_step,
+            recent_attacks,
+            recent_food,
+            starving_steps,
+            age,
+            lifespan,
+        } = self;
+        Agent {
+            id: *id,
+            name: name.clone(),
+            pos: *pos,
+            energy: *energy,
+            max_energy: *max_energy,
+            armor: *armor,
+            reserve: *reserve,
+            generation: *generation,
+            founder: *founder,
+            brain: Brain::empty(),
+            color: *color,
+            last_action: *last_action,
+            action_history: action_history.clone(),
+            facing: *facing,
+            last_attack_step: *last_attack_step,
+            recent_attacks: *recent_attacks,
+            recent_food: *recent_food,
+            starving_steps: *starving_steps,
+            age: *age,
+            lifespan: *lifespan,
+        }
+    }
+}
+
+/// 生態系での役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// 主に攻撃して生きている
+    Predator,
+    /// 主に餌を食べて生きている（何もしていない個体もこっち）
+    Prey,
+}
+
+/// 子音と母音の組み合わせで1音節。15 * 5 = 75通り。
+const CONSONANTS: &[u8] = b"bdfghklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// IDとシードから、発音できる短い名前を作る（"Bofu", "Zilan" みたいな）。
+/// - 同じ (id, seed) なら必ず同じ名前になる
+/// - 同じシードなら、IDが違えば名前も必ず違う
+pub fn generate_name(id: AgentId, seed: u64) -> String {
+    let syllables = (CONSONANTS.len() * VOWELS.len()) as u64;
+
+    // 2音節から始めて、IDが大きくなったら音節を増やす
+    let mut len = 2;
+    let mut block = syllables.pow(2);
+    let mut index = id as u64;
+    while index >= block {
+        index -= block;
+        len += 1;
+        block *= syllables;
+    }
+
+    // ブロック内で並びを混ぜる（block と互いに素な数を掛けるので全単射）
+    // 連番のIDでも似た名前が並ばないようにするため
+    const MULTIPLIER: u64 = 7919;
+    let offset = seed % block;
+    let mut code =
+        ((index as u128 * MULTIPLIER as u128 + offset as u128) % block as u128) as u64;
+
+    let mut name = String::with_capacity(len * 2);
+    for _ in 0..len {
+        let syllable = (code % syllables) as usize;
+        code /= syllables;
+
+        name.push(CONSONANTS[syllable / VOWELS.len()] as char);
+        name.push(VOWELS[syllable % VOWELS.len()] as char);
+    }
+
+    // 先頭だけ大文字
+    name[..1].to_ascii_uppercase() + &name[1..]
 }
 
 /// ランダム行列を作る
@@ -105,7 +358,8 @@ fn random_matrix<R: Rng + ?Sized>(rows: usize, cols: usize, rng: &mut R) -> Arra
     Array2::from_shape_fn((rows, cols), |_| dist.sample(rng))
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     Up = 0,
     Down = 1,
@@ -114,28 +368,260 @@ pub enum Action {
     Stay = 4,
     Attack = 5,
     Heal = 6,
+    /// 休息。代謝を抑えて体力の消費をなくす（自分だけの回復手段）
+    Rest = 7,
+    /// ダッシュ。向いている方向に一気に数マス進む（逃げる・追いかける用）
+    Sprint = 8,
+    /// 贈り物。自分のエネルギーの一部を、周りで一番弱っている個体に渡す（エネルギーは増えない）
+    Gift = 9,
+    /// 今いるマスの餌を食べる。自分で食べる設定（`EatMode::Active`）のときだけ選べる。
+    /// 脳の出力に足したりしなかったりするので、いつも最後に置いておく
+    Eat = 10,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    /// 1マス進んだときの (dx, dy)
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+/// 行動の数
+pub const ACTION_COUNT: usize = Action::ALL.len();
+
+/// 選べる行動の表（`Action::ALL` の順）。
+/// 設定ファイルでは、選べなくする行動の名前を並べる（例: `disabled_actions = ["attack"]`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Action>", into = "Vec<Action>")]
+pub struct ActionMask {
+    enabled: [bool; ACTION_COUNT],
+}
+
+impl ActionMask {
+    /// 全部選べる
+    pub const ALL: ActionMask = ActionMask {
+        enabled: [true; ACTION_COUNT],
+    };
+
+    /// `action` を選べなくしたもの
+    pub fn without(mut self, action: Action) -> Self {
+        self.enabled[action as usize] = false;
+        self
+    }
+
+    /// `action` を選べるか
+    pub fn allows(&self, action: Action) -> bool {
+        self.enabled[action as usize]
+    }
+}
+
+impl Default for ActionMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// 選べなくする行動の一覧から作る
+impl From<Vec<Action>> for ActionMask {
+    fn from(disabled: Vec<Action>) -> Self {
+        disabled
+            .into_iter()
+            .fold(ActionMask::ALL, |mask, action| mask.without(action))
+    }
+}
+
+/// 選べない行動の一覧にする
+impl From<ActionMask> for Vec<Action> {
+    fn from(mask: ActionMask) -> Self {
+        Action::ALL
+            .into_iter()
+            .filter(|&action| !mask.allows(action))
+            .collect()
+    }
 }
 
 impl Action {
-    // 確率(出力)の配列から、一番値が大きい行動を選ぶ
-    pub fn from_output(output: &[f32]) -> Self {
-        // 0~6番目の要素の中で最大値のインデックスを探す
-        let (index, _) = output
-            .iter()
-            .take(7) // 最初の7つが行動
-            .enumerate()
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-            .unwrap_or((4, &0.0)); // エラーならStay
-
-        match index {
-            0 => Action::Up,
-            1 => Action::Down,
-            2 => Action::Left,
-            3 => Action::Right,
-            4 => Action::Stay,
-            5 => Action::Attack,
-            6 => Action::Heal,
-            _ => Action::Stay,
+    /// 全部の行動（インデックス順）。行動を増やしたらここにも足す
+    pub const ALL: [Action; 11] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Stay,
+        Action::Attack,
+        Action::Heal,
+        Action::Rest,
+        Action::Sprint,
+        Action::Gift,
+        Action::Eat,
+    ];
+
+    /// 出力のインデックス（= enum の値）から行動を作る
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// 移動の行動なら、その方向
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    /// 確率(出力)の配列から、一番値が大きい行動を選ぶ。
+    /// - `stay_bias` は Stay の出力に足す値。正にすると、はっきり得がない限り待機するようになる
+    /// - 同じ値が並んだら、インデックスが小さい行動を選ぶ（Up > Down > Left > Right > Stay > Attack > Heal > Rest > Sprint > Gift > Eat の優先順。`Action::ALL` の並び）
+    /// - NaN と、`mask` で選べなくした行動は選ばない。選べるものがなければ Stay
+    pub fn from_output(
+        output: &[f32],
+        layout: &OutputLayout,
+        stay_bias: f32,
+        mask: &ActionMask,
+    ) -> Self {
+        // 行動の要素の中で最大値のインデックスを探す
+        // (max_by だと同じ値のとき後ろが選ばれるし、NaN で panic するので自前でやる)
+        let mut index = Action::Stay as usize;
+        let mut best = f32::NEG_INFINITY;
+        // 行動の出力だけを見る（並びは `layout` を参照）
+        let actions = &output[layout.actions()];
+        for (i, &v) in actions.iter().enumerate() {
+            if Action::from_index(i).is_some_and(|action| !mask.allows(action)) {
+                continue;
+            }
+            let v = if i == Action::Stay as usize {
+                v + stay_bias
+            } else {
+                v
+            };
+
+            // 厳密に大きいときだけ更新するので、同点なら先に見たほうが残る
+            if v > best {
+                index = i;
+                best = v;
+            }
+        }
+
+        Action::from_index(index).unwrap_or(Action::Stay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::keyed_rng;
+
+    fn agent_with_energy(energy: u32) -> Agent {
+        let params = Params::default();
+        let mut rng = keyed_rng(0, 0, 0);
+        let pos = Position { x: 0, y: 0 };
+        let mut agent = Agent::new_random(0, String::new(), pos, &params, &mut rng);
+        agent.energy = energy;
+        agent
+    }
+
+    #[test]
+    fn overflow_is_wasted_by_default() {
+        let mut agent = agent_with_energy(80);
+        let gained = agent.eat(60, &ReserveParams::default());
+        assert_eq!((agent.energy, agent.reserve, gained), (MAX_ENERGY, 0, 20));
+    }
+
+    #[test]
+    fn overflow_goes_to_the_reserve_and_is_drawn_down_when_hungry() {
+        let reserve = ReserveParams {
+            store_overflow: true,
+            capacity: 30,
+            draw_per_step: 5,
+        };
+        let mut agent = agent_with_energy(80);
+        // 溢れた 40 のうち、蓄えに入るのは上限の 30 まで
+        let gained = agent.eat(60, &reserve);
+        assert_eq!((agent.energy, agent.reserve, gained), (MAX_ENERGY, 30, 50));
+
+        // 最大の半分あるうちは取り崩さない
+        agent.energy = MAX_ENERGY / 2;
+        agent.draw_reserve(&reserve);
+        assert_eq!((agent.energy, agent.reserve), (MAX_ENERGY / 2, 30));
+
+        // 飢えてきたら 1 ステップ draw_per_step ずつ
+        agent.energy = 10;
+        agent.draw_reserve(&reserve);
+        assert_eq!((agent.energy, agent.reserve), (15, 25));
+        for _ in 0..10 {
+            agent.draw_reserve(&reserve);
+        }
+        assert_eq!((agent.energy, agent.reserve), (40, 0));
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_index() {
+        let layout = OutputLayout::new(ACTION_COUNT, false, 0);
+        let mut mask = ActionMask::ALL;
+        for expected in Action::ALL {
+            let output = [1.0; ACTION_COUNT];
+            assert_eq!(Action::from_output(&output, &layout, 0.0, &mask), expected);
+            mask = mask.without(expected);
+        }
+        // 全部選べなくしたら Stay
+        let output = [1.0; ACTION_COUNT];
+        assert_eq!(
+            Action::from_output(&output, &layout, 0.0, &mask),
+            Action::Stay
+        );
+    }
+
+    #[test]
+    fn masked_attack_is_never_chosen() {
+        let layout = OutputLayout::new(ACTION_COUNT, false, 0);
+        let mask = ActionMask::from(vec![Action::Attack]);
+        assert!(!mask.allows(Action::Attack));
+        assert_eq!(Vec::<Action>::from(mask), [Action::Attack]);
+
+        // 攻撃が一番大きくても、その次に大きいものを選ぶ
+        let mut output = [0.0; ACTION_COUNT];
+        output[Action::Attack as usize] = 5.0;
+        output[Action::Heal as usize] = 1.0;
+        let all = ActionMask::ALL;
+        assert_eq!(
+            Action::from_output(&output, &layout, 0.0, &all),
+            Action::Attack
+        );
+        assert_eq!(
+            Action::from_output(&output, &layout, 0.0, &mask),
+            Action::Heal
+        );
+
+        let mut rng = keyed_rng(3, 0, 0);
+        for _ in 0..1000 {
+            let output: Vec<f32> = (0..ACTION_COUNT)
+                .map(|_| rng.random_range(-1.0..1.0))
+                .collect();
+            let action = Action::from_output(&output, &layout, 0.0, &mask);
+            assert_ne!(action, Action::Attack, "{output:?}");
         }
     }
 }
@@ -1,10 +1,23 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::Path,
+};
+
 use ndarray::{Array1, Array2};
 use rand::Rng;
 use rand_distr::{Distribution, StandardNormal};
 
+use crate::agent::Action;
+
 /// ニューラルネットワークの形状。
+/// 自分の色も見る設定（`Params::self_color_input`）なら、入力はこれより `SELF_COLOR_SIZE` 多くなる
 pub const INPUT_SIZE: usize = INPUT_FIELD_SIZE * (INPUT_CELL_TYPE_SIZE + RGB_COLOR_SIZE);
 
+/// 自分の色の入力（R, G, B）。視界のあとに付け足す
+pub const SELF_COLOR_SIZE: usize = RGB_COLOR_SIZE;
+
 pub const INPUT_FIELD_LENGTH: usize = 7;
 pub const INPUT_FIELD_SIZE: usize = INPUT_FIELD_LENGTH * INPUT_FIELD_LENGTH;
 
@@ -13,15 +26,180 @@ pub const INPUT_CELL_TYPE_SIZE: usize = 3;
 
 pub const HIDDEN_SIZE: usize = 64;
 
-pub const OUTPUT_SIZE: usize = OUTPUT_ACTION_SIZE + RGB_COLOR_SIZE;
+/// 脳だけのファイル（`Brain::save`）の先頭に書くマジックとバージョン
+const BRAIN_MAGIC: &[u8; 4] = b"RKBR";
+const BRAIN_VERSION: u8 = 1;
+
+/// 指紋（`Brain::fingerprint`）を取るときに、パラメータを丸める幅。
+/// これより細かい違いは同じ脳とみなす
+pub const FINGERPRINT_STEP: f32 = 0.05;
+
+pub const OUTPUT_SIZE: usize = OUTPUT_LAYOUT.size();
 
-/// 行動(上下左右、待機、攻撃・お裾分け）
-pub const OUTPUT_ACTION_SIZE: usize = 4 + 1 + 2;
+/// 行動(上下左右、待機、攻撃・お裾分け、休息、ダッシュ、贈り物）。
+/// 食べる（`Action::Eat`）は自分で食べる設定のときだけ足すので、ここには数えない
+pub const OUTPUT_ACTION_SIZE: usize = Action::ALL.len() - 1;
+
+/// ふだんの出力の並び。出力を増やすときはここ（と `OutputLayout`）だけ変えればいい。
+/// 設定で変わる分も含めた並びは `Params::output_layout` で取る
+pub const OUTPUT_LAYOUT: OutputLayout = OutputLayout::new(OUTPUT_ACTION_SIZE, true, 0);
 
 /// RGB色
 pub const RGB_COLOR_SIZE: usize = 3;
 
-#[derive(Debug, Clone)]
+/// `World::get_input` が返す入力ベクトルの並び。
+///
+/// 自分を中心とした `INPUT_FIELD_LENGTH` x `INPUT_FIELD_LENGTH` の視界のマスを、
+/// 上の行から順に（各行は左から右へ）並べたもの。
+/// 1マスは `CHANNELS` 個の値で、順番は [壁, 餌, 他の個体, R, G, B]。
+/// 壁・餌・他の個体は 0.0 か 1.0 で、RGB は他の個体がいるときだけその色が入る。
+/// 自分の色も見る設定なら、視界のあとに自分の [R, G, B] が続く（`SELF_COLOR`）。
+///
+/// 例えば自分から見て (dx, dy) のマスの餌は `input[InputLayout::index(dx, dy, InputLayout::FOOD)]`。
+#[derive(Debug, Clone, Copy)]
+pub struct InputLayout;
+
+impl InputLayout {
+    /// 1マスあたりのチャンネル数
+    pub const CHANNELS: usize = INPUT_CELL_TYPE_SIZE + RGB_COLOR_SIZE;
+
+    pub const WALL: usize = 0;
+    pub const FOOD: usize = 1;
+    pub const AGENT: usize = 2;
+    pub const RED: usize = 3;
+    pub const GREEN: usize = 4;
+    pub const BLUE: usize = 5;
+
+    /// 自分の色の入力が始まるインデックス（設定で有効なときだけある）
+    pub const SELF_COLOR: usize = INPUT_SIZE;
+
+    /// 視界の半径。(dx, dy) はそれぞれ -RADIUS..=RADIUS
+    pub const RADIUS: isize = (INPUT_FIELD_LENGTH / 2) as isize;
+
+    /// 自分から見て (dx, dy) のマスの `channel` が入っているインデックス
+    pub fn index(dx: isize, dy: isize, channel: usize) -> usize {
+        debug_assert!((-Self::RADIUS..=Self::RADIUS).contains(&dx));
+        debug_assert!((-Self::RADIUS..=Self::RADIUS).contains(&dy));
+        debug_assert!(channel < Self::CHANNELS);
+
+        let row = (dy + Self::RADIUS) as usize;
+        let col = (dx + Self::RADIUS) as usize;
+        (row * INPUT_FIELD_LENGTH + col) * Self::CHANNELS + channel
+    }
+
+    /// 平らな入力ベクトルを、マスごとの情報の2次元配列（`[行][列]`）に戻す
+    pub fn decode(input: &[f32]) -> Vec<Vec<CellInput>> {
+        debug_assert!(input.len() >= INPUT_FIELD_SIZE * Self::CHANNELS);
+
+        input[..INPUT_FIELD_SIZE * Self::CHANNELS]
+            .chunks_exact(Self::CHANNELS)
+            .map(|cell| CellInput {
+                wall: cell[Self::WALL] > 0.5,
+                food: cell[Self::FOOD] > 0.5,
+                agent: cell[Self::AGENT] > 0.5,
+                color: [cell[Self::RED], cell[Self::GREEN], cell[Self::BLUE]],
+            })
+            .collect::<Vec<_>>()
+            .chunks(INPUT_FIELD_LENGTH)
+            .map(|row| row.to_vec())
+            .collect()
+    }
+}
+
+/// 入力ベクトルの1マス分を読みやすくしたもの
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellInput {
+    pub wall: bool,
+    pub food: bool,
+    pub agent: bool,
+    pub color: [f32; RGB_COLOR_SIZE],
+}
+
+/// 脳の出力ベクトルの並び。
+///
+/// 前から順に [行動, 色 (RGB), 信号] で、使わない機能の分は詰める。
+/// 行動のインデックスは `Action` の値と同じ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLayout {
+    /// 行動の数
+    pub actions: usize,
+    /// 色を出力するか
+    pub color: bool,
+    /// 信号（仲間への合図など）のチャンネル数
+    pub signals: usize,
+}
+
+/// 出力の1要素の意味
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMeaning {
+    Action(Action),
+    /// 色の成分（0: R, 1: G, 2: B）
+    Color(usize),
+    /// 信号のチャンネル
+    Signal(usize),
+}
+
+impl OutputLayout {
+    pub const fn new(actions: usize, color: bool, signals: usize) -> Self {
+        Self {
+            actions,
+            color,
+            signals,
+        }
+    }
+
+    /// 出力ベクトル全体の長さ
+    pub const fn size(&self) -> usize {
+        self.signal_start() + self.signals
+    }
+
+    /// 行動の出力がある範囲
+    pub const fn actions(&self) -> Range<usize> {
+        0..self.actions
+    }
+
+    /// 色の出力がある範囲（色を出力しないなら None）
+    pub const fn color(&self) -> Option<Range<usize>> {
+        if self.color {
+            Some(self.actions..self.actions + RGB_COLOR_SIZE)
+        } else {
+            None
+        }
+    }
+
+    /// 信号の出力がある範囲（信号がなければ None）
+    pub const fn signal(&self) -> Option<Range<usize>> {
+        if self.signals > 0 {
+            Some(self.signal_start()..self.signal_start() + self.signals)
+        } else {
+            None
+        }
+    }
+
+    const fn signal_start(&self) -> usize {
+        self.actions + if self.color { RGB_COLOR_SIZE } else { 0 }
+    }
+
+    /// `index` 番目の出力が何を表しているか
+    pub fn meaning(&self, index: usize) -> Option<OutputMeaning> {
+        if index < self.actions {
+            return Action::from_index(index).map(OutputMeaning::Action);
+        }
+        if let Some(color) = self.color()
+            && color.contains(&index)
+        {
+            return Some(OutputMeaning::Color(index - color.start));
+        }
+        if let Some(signal) = self.signal()
+            && signal.contains(&index)
+        {
+            return Some(OutputMeaning::Signal(index - signal.start));
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Brain {
     weights_l1: Array2<f32>,
     biases_l1: Array1<f32>,
@@ -45,9 +223,21 @@ impl Brain {
         }
     }
 
-    pub fn forward(&self, input: &Array1<f32>) -> Array1<f32> {
+    /// 重みが1つもない脳。中身を入れ替えるまでの仮置き用
+    pub(crate) fn empty() -> Self {
+        Self::new(
+            Array2::zeros((0, 0)),
+            Array1::zeros(0),
+            Array2::zeros((0, 0)),
+            Array1::zeros(0),
+        )
+    }
+
+    /// 入力から出力を計算する。
+    /// `relu_slope` は隠れ層の ReLU で負の値を通す傾き（`Params::relu_slope`）
+    pub fn forward(&self, input: &Array1<f32>, relu_slope: f32) -> Array1<f32> {
         let mut hidden = self.weights_l1.dot(input) + &self.biases_l1;
-        relu_inplace(&mut hidden);
+        relu_inplace(&mut hidden, relu_slope);
         self.weights_l2.dot(&hidden) + &self.biases_l2
     }
 
@@ -64,6 +254,134 @@ impl Brain {
         child
     }
 
+    /// 交叉。2つの親から、パラメータを1つずつどちらかの親から受け継いだ子を作る。
+    /// `self_weight` は各パラメータを `self` から受け継ぐ確率で、
+    /// 0.5 なら五分五分、1.0 なら `self` のコピーになる。
+    /// 親の適応度（エネルギーや子供の数）で重み付けしたいときは `fitness_weight` を使う。
+    /// 突然変異はしないので、必要なら呼び出し側で `mutate_inplace` する。
+    /// 2つの脳の形が違う（`same_shape` でない）ときは混ぜられないので None。
+    pub fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Brain,
+        self_weight: f32,
+        rng: &mut R,
+    ) -> Option<Brain> {
+        debug_assert!((0.0..=1.0).contains(&self_weight));
+        if !self.same_shape(other) {
+            return None;
+        }
+
+        let mut child = self.clone();
+        let mut inherit = |dst: &mut f32, &src: &f32| {
+            if rng.random::<f32>() >= self_weight {
+                *dst = src;
+            }
+        };
+
+        child
+            .weights_l1
+            .iter_mut()
+            .zip(other.weights_l1.iter())
+            .for_each(|(d, s)| inherit(d, s));
+        child
+            .biases_l1
+            .iter_mut()
+            .zip(other.biases_l1.iter())
+            .for_each(|(d, s)| inherit(d, s));
+        child
+            .weights_l2
+            .iter_mut()
+            .zip(other.weights_l2.iter())
+            .for_each(|(d, s)| inherit(d, s));
+        child
+            .biases_l2
+            .iter_mut()
+            .zip(other.biases_l2.iter())
+            .for_each(|(d, s)| inherit(d, s));
+
+        Some(child)
+    }
+
+    /// 2つの脳の違い。全パラメータの差の二乗平均平方根（RMS）。
+    /// 同じ脳なら 0 で、ランダムに作った脳同士なら √2 くらいになる。
+    pub fn distance(&self, other: &Brain) -> f32 {
+        let pairs = self
+            .weights_l1
+            .iter()
+            .zip(other.weights_l1.iter())
+            .chain(self.biases_l1.iter().zip(other.biases_l1.iter()))
+            .chain(self.weights_l2.iter().zip(other.weights_l2.iter()))
+            .chain(self.biases_l2.iter().zip(other.biases_l2.iter()));
+
+        let (sum, count) = pairs.fold((0.0f64, 0usize), |(sum, count), (a, b)| {
+            (sum + ((a - b) as f64).powi(2), count + 1)
+        });
+
+        if count == 0 {
+            0.0
+        } else {
+            (sum / count as f64).sqrt() as f32
+        }
+    }
+
+    /// 脳の指紋。パラメータを `FINGERPRINT_STEP` 刻みに丸めてからハッシュ（FNV-1a）を取る。
+    /// 同じ脳なら必ず同じ値。丸めるので、ほんの少しだけ違う脳もだいたい同じ値になる
+    /// （丸めの境目をまたぐと別の値になる）。
+    /// 総当たりで距離を測るより安く、同じ脳がどれだけいるかを数えられる
+    pub fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        let mix = |hash: u64, value: u32| {
+            value
+                .to_le_bytes()
+                .iter()
+                .fold(hash, |hash, &b| (hash ^ b as u64).wrapping_mul(FNV_PRIME))
+        };
+
+        let (input, hidden, output) = self.shape();
+        let hash = [input, hidden, output]
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, &n| mix(hash, n as u32));
+        self.weights_l1
+            .iter()
+            .chain(self.biases_l1.iter())
+            .chain(self.weights_l2.iter())
+            .chain(self.biases_l2.iter())
+            .fold(hash, |hash, &v| {
+                // -0.0 と 0.0 が同じになるように、丸めてから整数にする
+                let bucket = (v / FINGERPRINT_STEP).round() as i32;
+                mix(hash, bucket as u32)
+            })
+    }
+
+    /// 層の大きさ (入力, 隠れ層, 出力)
+    pub fn shape(&self) -> (usize, usize, usize) {
+        let (hidden, input) = self.weights_l1.dim();
+        (input, hidden, self.weights_l2.nrows())
+    }
+
+    /// 2つの脳が同じ形か。違う形の脳は、交叉したり比べたりできない
+    pub fn same_shape(&self, other: &Brain) -> bool {
+        self.shape() == other.shape()
+    }
+
+    /// パラメータ（重みとバイアス）の総数
+    pub fn param_count(&self) -> usize {
+        self.weights_l1.len()
+            + self.biases_l1.len()
+            + self.weights_l2.len()
+            + self.biases_l2.len()
+    }
+
+    /// 層の形の説明。"294 -> 64 (relu) -> 13, 19725 params" みたいな感じ
+    pub fn summary(&self) -> String {
+        let (input, hidden, output) = self.shape();
+        format!(
+            "{input} -> {hidden} (relu) -> {output}, {} params",
+            self.param_count()
+        )
+    }
+
     /// 突然変異。
     /// 各パラメータを確率 rate で N(0, sigma) だけ揺らす。
     /// `rate`は突然変異の割合。`sigma`は標準偏差。
@@ -97,8 +415,362 @@ impl Brain {
             mutate_val(v);
         }
     }
+
+    /// 形と重みをバイナリで書き出す（f32 はリトルエンディアン）
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for &dim in [
+            self.weights_l1.nrows(),
+            self.weights_l1.ncols(),
+            self.weights_l2.nrows(),
+        ]
+        .iter()
+        {
+            w.write_all(&(dim as u32).to_le_bytes())?;
+        }
+
+        let values = self
+            .weights_l1
+            .iter()
+            .chain(self.biases_l1.iter())
+            .chain(self.weights_l2.iter())
+            .chain(self.biases_l2.iter());
+        for v in values {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// `write_to` で書き出したものを読み込む。
+    /// 形 (入力, 隠れ層, 出力) が `expected` と違えば、中身を読む（メモリを確保する）前にエラーにする。
+    /// 壊れたファイルの大きすぎる形で、確保しようとして落ちないように
+    pub(crate) fn read_from<R: Read>(
+        r: &mut R,
+        expected: (usize, usize, usize),
+    ) -> io::Result<Self> {
+        let read_u32 = |r: &mut R| -> io::Result<usize> {
+            let mut buf = [0; 4];
+            r.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf) as usize)
+        };
+        let hidden = read_u32(r)?;
+        let input = read_u32(r)?;
+        let output = read_u32(r)?;
+        if (input, hidden, output) != expected {
+            let (expected_input, expected_hidden, expected_output) = expected;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{input}-{hidden}-{output} brain \
+                     (expected {expected_input}-{expected_hidden}-{expected_output})"
+                ),
+            ));
+        }
+
+        let mut read_values = |len: usize| -> io::Result<Vec<f32>> {
+            let mut bytes = vec![0; len * 4];
+            r.read_exact(&mut bytes)?;
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        };
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let weights_l1 =
+            Array2::from_shape_vec((hidden, input), read_values(hidden * input)?)
+                .map_err(invalid)?;
+        let biases_l1 = Array1::from(read_values(hidden)?);
+        let weights_l2 =
+            Array2::from_shape_vec((output, hidden), read_values(output * hidden)?)
+                .map_err(invalid)?;
+        let biases_l2 = Array1::from(read_values(output)?);
+
+        Ok(Self::new(weights_l1, biases_l1, weights_l2, biases_l2))
+    }
+}
+
+impl Brain {
+    /// 脳だけをファイルに書き出す。あとで `Brain::load` で読み直して使い回す用
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(BRAIN_MAGIC)?;
+        w.write_all(&[BRAIN_VERSION])?;
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    /// `Brain::save` で書き出したファイルを読み込む。
+    /// 形が `shape`（ふつうは `Params::brain_shape`）と違う脳はエラー
+    pub fn load(path: &Path, shape: (usize, usize, usize)) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != BRAIN_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a brain file", path.display()),
+            ));
+        }
+        if header[4] != BRAIN_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported brain file version {}", header[4]),
+            ));
+        }
+        Self::read_from(&mut r, shape).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            ),
+            _ => e,
+        })
+    }
 }
 
-fn relu_inplace(x: &mut Array1<f32>) {
-    x.mapv_inplace(|v| v.max(0.0));
+/// 2つの親の適応度から、`crossover` に渡す `self_weight` を作る。
+/// 適応度に比例した確率になる。両方 0 以下なら五分五分。
+pub fn fitness_weight(self_fitness: f32, other_fitness: f32) -> f32 {
+    let (a, b) = (self_fitness.max(0.0), other_fitness.max(0.0));
+    if a + b > 0.0 { a / (a + b) } else { 0.5 }
+}
+
+/// ReLU。`slope` が 0 でなければ Leaky ReLU（負の値も `slope` 倍して少しだけ通す）
+fn relu_inplace(x: &mut Array1<f32>, slope: f32) {
+    if slope == 0.0 {
+        x.mapv_inplace(|v| v.max(0.0));
+    } else {
+        x.mapv_inplace(|v| if v > 0.0 { v } else { v * slope });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        params::{EatMode, Params},
+        rng::keyed_rng,
+    };
+
+    /// 入力 20・隠れ層 10・出力 5 のランダムな脳
+    fn random_brain(rng: &mut impl Rng) -> Brain {
+        let mut value = || rng.random_range(-1.0..1.0);
+        Brain::new(
+            Array2::from_shape_simple_fn((10, 20), &mut value),
+            Array1::from_shape_simple_fn(10, &mut value),
+            Array2::from_shape_simple_fn((5, 10), &mut value),
+            Array1::from_shape_simple_fn(5, &mut value),
+        )
+    }
+
+    /// `child` のパラメータのうち、`parent` と同じものの割合
+    fn share_from(child: &Brain, parent: &Brain) -> f32 {
+        let same = child
+            .weights_l1
+            .iter()
+            .zip(parent.weights_l1.iter())
+            .chain(child.biases_l1.iter().zip(parent.biases_l1.iter()))
+            .chain(child.weights_l2.iter().zip(parent.weights_l2.iter()))
+            .chain(child.biases_l2.iter().zip(parent.biases_l2.iter()))
+            .filter(|(a, b)| a == b)
+            .count();
+        same as f32 / child.param_count() as f32
+    }
+
+    #[test]
+    fn full_fitness_weight_clones_that_parent() {
+        let mut rng = keyed_rng(1, 0, 0);
+        let (strong, weak) = (random_brain(&mut rng), random_brain(&mut rng));
+
+        // 片方の適応度が 0 なら、もう片方のコピー
+        let self_weight = fitness_weight(12.0, 0.0);
+        assert_eq!(self_weight, 1.0);
+        let child = strong.crossover(&weak, self_weight, &mut rng).unwrap();
+        assert_eq!(child, strong);
+        // どちらから呼んでも同じ
+        let child = weak.crossover(&strong, fitness_weight(0.0, 12.0), &mut rng);
+        assert_eq!(child.unwrap(), strong);
+
+        // そこに突然変異を足しても、近いのは強いほうの親
+        let mut child = strong.crossover(&weak, self_weight, &mut rng).unwrap();
+        child.mutate_inplace(0.1, 0.05, &mut rng);
+        assert!(child.distance(&strong) < 0.1);
+        assert!(child.distance(&weak) > 0.5);
+    }
+
+    #[test]
+    fn inheritance_follows_fitness_weight() {
+        let mut rng = keyed_rng(2, 0, 0);
+        let (a, b) = (random_brain(&mut rng), random_brain(&mut rng));
+
+        assert_eq!(fitness_weight(0.0, 0.0), 0.5);
+        let child = a.crossover(&b, fitness_weight(3.0, 1.0), &mut rng).unwrap();
+        let share = share_from(&child, &a);
+        assert!((0.65..0.85).contains(&share), "share from a: {share}");
+        assert!((share + share_from(&child, &b) - 1.0).abs() < 1e-6);
+    }
+
+    /// 形だけ決めた、重みが全部 0 の脳
+    fn zero_brain((input, hidden, output): (usize, usize, usize)) -> Brain {
+        Brain::new(
+            Array2::zeros((hidden, input)),
+            Array1::zeros(hidden),
+            Array2::zeros((output, hidden)),
+            Array1::zeros(output),
+        )
+    }
+
+    #[test]
+    fn param_count_matches_the_default_topology() {
+        let shape = Params::default().brain_shape();
+        let (input, hidden, output) = shape;
+        assert_eq!((input, hidden), (INPUT_SIZE, HIDDEN_SIZE));
+        assert_eq!(output, OUTPUT_LAYOUT.size());
+
+        let brain = zero_brain(shape);
+        assert_eq!(brain.shape(), shape);
+        let count = input * hidden + hidden + hidden * output + output;
+        assert_eq!(brain.param_count(), count);
+        assert_eq!(
+            brain.summary(),
+            format!("{input} -> {hidden} (relu) -> {output}, {count} params")
+        );
+    }
+
+    #[test]
+    fn default_output_layout_maps_actions_then_color() {
+        let layout = OUTPUT_LAYOUT;
+        assert_eq!(layout.size(), OUTPUT_ACTION_SIZE + RGB_COLOR_SIZE);
+        assert_eq!(layout.meaning(0), Some(OutputMeaning::Action(Action::Up)));
+        assert_eq!(
+            layout.meaning(OUTPUT_ACTION_SIZE - 1),
+            Some(OutputMeaning::Action(Action::Gift))
+        );
+        assert_eq!(layout.color(), Some(10..13));
+        assert_eq!(layout.meaning(10), Some(OutputMeaning::Color(0)));
+        assert_eq!(layout.meaning(12), Some(OutputMeaning::Color(2)));
+        assert_eq!(layout.signal(), None);
+        assert_eq!(layout.meaning(13), None);
+    }
+
+    #[test]
+    fn output_layout_packs_disabled_features() {
+        // 色なし・信号ありなら、信号が行動のすぐあとに来る
+        let layout = OutputLayout::new(11, false, 2);
+        assert_eq!(layout.size(), 13);
+        assert_eq!(layout.meaning(10), Some(OutputMeaning::Action(Action::Eat)));
+        assert_eq!(layout.color(), None);
+        assert_eq!(layout.signal(), Some(11..13));
+        assert_eq!(layout.meaning(11), Some(OutputMeaning::Signal(0)));
+
+        // 全部あり
+        let layout = OutputLayout::new(10, true, 3);
+        assert_eq!(layout.size(), 16);
+        assert_eq!(layout.actions(), 0..10);
+        assert_eq!(layout.meaning(13), Some(OutputMeaning::Signal(0)));
+        assert_eq!(layout.meaning(15), Some(OutputMeaning::Signal(2)));
+        assert_eq!(layout.meaning(16), None);
+
+        // 自分で食べる設定だと、食べる行動の分だけ色がずれる
+        let params = Params {
+            eat_mode: EatMode::Active,
+            ..Params::default()
+        };
+        let layout = params.output_layout();
+        assert_eq!(layout.meaning(10), Some(OutputMeaning::Action(Action::Eat)));
+        assert_eq!(layout.meaning(11), Some(OutputMeaning::Color(0)));
+        assert_eq!(layout.size(), OUTPUT_LAYOUT.size() + 1);
+    }
+
+    #[test]
+    fn mismatched_brains_are_not_combined() {
+        let mut rng = keyed_rng(4, 0, 0);
+        let base = zero_brain((20, 10, 5));
+        let wider_input = zero_brain((23, 10, 5));
+        let bigger_hidden = zero_brain((20, 12, 5));
+        assert!(base.same_shape(&zero_brain((20, 10, 5))));
+        for other in [&wider_input, &bigger_hidden] {
+            assert!(!base.same_shape(other));
+            // ndarray の中で panic せずに、混ぜるのを断る
+            assert!(base.crossover(other, 0.5, &mut rng).is_none());
+            assert!(other.crossover(&base, 0.5, &mut rng).is_none());
+        }
+    }
+
+    #[test]
+    fn identical_brains_share_a_fingerprint_and_mutants_usually_do_not() {
+        let mut rng = keyed_rng(5, 0, 0);
+        let brain = random_brain(&mut rng);
+        assert_eq!(brain.clone().fingerprint(), brain.fingerprint());
+        // 丸め幅よりずっと小さい違いなら同じ指紋
+        let mut nudged = brain.clone();
+        nudged.biases_l2[0] = (nudged.biases_l2[0] / FINGERPRINT_STEP).round()
+            * FINGERPRINT_STEP
+            + FINGERPRINT_STEP * 0.01;
+        let mut snapped = brain.clone();
+        snapped.biases_l2[0] = nudged.biases_l2[0] - FINGERPRINT_STEP * 0.02;
+        assert_eq!(nudged.fingerprint(), snapped.fingerprint());
+
+        let differing = (0..50)
+            .filter(|_| {
+                let mut mutant = brain.clone();
+                mutant.mutate_inplace(0.1, 0.5, &mut rng);
+                mutant.fingerprint() != brain.fingerprint()
+            })
+            .count();
+        assert!(differing >= 45, "only {differing}/50 mutants differ");
+    }
+
+    #[test]
+    fn leaky_relu_lets_negative_values_through_by_the_slope() {
+        let input = || Array1::from(vec![-2.0, -0.5, 0.0, 3.0]);
+        let mut plain = input();
+        relu_inplace(&mut plain, 0.0);
+        assert_eq!(plain.to_vec(), [0.0, 0.0, 0.0, 3.0]);
+        let mut leaky = input();
+        relu_inplace(&mut leaky, 0.25);
+        assert_eq!(leaky.to_vec(), [-0.5, -0.125, 0.0, 3.0]);
+
+        // 隠れ層が -4 になる脳だと、出力にも傾きの分だけ漏れる
+        let mut brain = zero_brain((1, 1, 1));
+        brain.biases_l1[0] = -4.0;
+        brain.weights_l2[[0, 0]] = 1.0;
+        let zero = Array1::zeros(1);
+        assert_eq!(brain.forward(&zero, 0.0)[0], 0.0);
+        assert_eq!(brain.forward(&zero, 0.25)[0], -1.0);
+    }
+
+    #[test]
+    fn brains_round_trip_only_with_the_expected_shape() {
+        let mut rng = keyed_rng(6, 0, 0);
+        let brain = random_brain(&mut rng);
+        let mut bytes = Vec::new();
+        brain.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            Brain::read_from(&mut &bytes[..], (20, 10, 5)).unwrap(),
+            brain
+        );
+        let error = Brain::read_from(&mut &bytes[..], (21, 10, 5)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn huge_brain_header_is_an_error_not_a_crash() {
+        // 隠れ層も入力も u32::MAX だと、確保する大きさの計算だけであふれる
+        let header: Vec<u8> = [u32::MAX, u32::MAX, 5]
+            .iter()
+            .flat_map(|dim| dim.to_le_bytes())
+            .collect();
+        let error = Brain::read_from(&mut &header[..], (20, 10, 5)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let path = std::env::temp_dir()
+            .join(format!("rikulife-brain-{}.rkbr", std::process::id()));
+        let mut file = BRAIN_MAGIC.to_vec();
+        file.push(BRAIN_VERSION);
+        file.extend(&header);
+        std::fs::write(&path, &file).unwrap();
+        let error = Brain::load(&path, (20, 10, 5)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
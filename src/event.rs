@@ -0,0 +1,65 @@
+//! 1ステップの間に起きた出来事。
+//! UIや統計が、`step()` の中で何が起きたかを後から知るために使う。
+
+use crate::{
+    agent::Action,
+    world::{AgentId, Position},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepEvent {
+    /// 攻撃・回復・贈り物で、周りの個体に作用した
+    Interaction {
+        actor: AgentId,
+        action: Action,
+        /// 作用した個体の位置
+        from: Position,
+        /// 作用を受けた個体がいたマス（誰もいなければ空）
+        targets: Vec<Position>,
+    },
+    /// 移動した先の餌を食べた
+    Eat {
+        id: AgentId,
+        /// 餌があったマス
+        pos: Position,
+        /// 餌の回復量（そのマスのゾーンで決まる）
+        food_value: u32,
+        /// 実際に増えたエネルギー（と蓄え）。満腹だと `food_value` より少なくなる
+        gained: u32,
+    },
+    /// 子供が生まれた
+    Birth {
+        parent: AgentId,
+        child: AgentId,
+        /// 子供が生まれたマス
+        pos: Position,
+    },
+    /// 死んだ（このステップの頭で取り除かれた）
+    Death {
+        id: AgentId,
+        pos: Position,
+        cause: DeathCause,
+    },
+    /// 攻撃で相手のエネルギーを 0 にした。
+    /// 相手はたいてい次のステップの頭で死ぬ（そのあいだに餌を食べれば助かる）
+    Kill {
+        killer: AgentId,
+        victim: AgentId,
+        /// 相手がいたマス
+        pos: Position,
+    },
+    /// みんなが動かなくなった（停滞の見張りが、停滞し始めたのを見つけた）
+    Stagnation {
+        /// 見張りの期間のあいだに場所が変わった個体の割合
+        activity: f32,
+    },
+}
+
+/// 死因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathCause {
+    /// エネルギーが尽きた（`death_energy_threshold` 以下のまま猶予を過ぎた）
+    Starvation,
+    /// 寿命
+    OldAge,
+}
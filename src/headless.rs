@@ -0,0 +1,400 @@
+//! TUIなしでシミュレーションを回すモード。
+//! 長い実行でも様子が分かるように、標準エラーに進捗を出す。
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use rikulife::{
+    agent::Agent,
+    event::StepEvent,
+    params::Params,
+    stats::LineageStats,
+    world::{AgentId, Autosave, ImageMapping, Palette, World},
+};
+use serde::Serialize;
+
+/// 端末のときの進捗表示の更新間隔
+const TTY_INTERVAL: Duration = Duration::from_millis(200);
+/// 端末じゃない（ログファイルなど）ときの進捗表示の間隔
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+/// 何ステップごとに時計を見るか（毎ステップ見ると遅くなるので）
+const CHECK_EVERY: u64 = 16;
+
+/// コマ撮りの書き出し先と撮り方
+#[derive(Debug, Clone)]
+pub struct FrameExport {
+    pub dir: PathBuf,
+    /// 何ステップごとに撮るか
+    pub every: u64,
+    /// 256色に減らして、色の番号の画像とパレットで書くか
+    pub indexed: bool,
+}
+
+impl FrameExport {
+    /// 今の様子を `frame_<ステップ>.png` に書く。
+    /// 256色モードなら、同じ名前の `.pal` にそのコマのパレットも書く
+    fn write(&self, world: &World) -> io::Result<()> {
+        let image = world.render_to_image(&ImageMapping::default());
+        let path = self.dir.join(format!("frame_{:06}.png", world.step));
+        let saved = if self.indexed {
+            // パレットはコマごとに作る（進化で色が変わっていくので）
+            let palette = Palette::from_image(&image);
+            fs::write(path.with_extension("pal"), palette.to_bytes())?;
+            palette.quantize(&image).save(&path)
+        } else {
+            image.save(&path)
+        };
+        saved.map_err(|e| {
+            io::Error::other(format!("cannot write {}: {e}", path.display()))
+        })
+    }
+}
+
+/// `steps` ステップ進めて、最後に統計を標準出力に出す。
+/// `summary_json` があれば、そこに結果のまとめを JSON で書く（途中で絶滅しても書く）。
+/// `frames` があれば、最初と `every` ステップごとにコマ撮りを書き出す。
+/// `autosave` があれば、`every` ステップごとに世界を保存する（最初は保存しない）
+pub fn run(
+    world: &mut World,
+    steps: u64,
+    summary_json: Option<&Path>,
+    frames: Option<&FrameExport>,
+    autosave: Option<&Autosave>,
+) -> io::Result<()> {
+    let mut progress = Progress::new(steps);
+    let start_step = world.step;
+
+    if let Some(frames) = frames {
+        fs::create_dir_all(&frames.dir)?;
+        frames.write(world)?;
+    }
+
+    // CHECK_EVERY ステップずつ進めて、その合間に進捗を出す
+    let mut done = 0;
+    while done < steps && !world.halted() {
+        let mut chunk = CHECK_EVERY.min(steps - done);
+        // コマ撮りのステップをまたがないように区切る
+        if let Some(frames) = frames {
+            chunk = chunk.min(frames.every - world.step % frames.every);
+        }
+        // 自動保存のステップも同じく
+        if let Some(autosave) = autosave {
+            chunk = chunk.min(autosave.every - world.step % autosave.every);
+        }
+        world.run(chunk as usize);
+        // 全滅して途中で止まったら、実際に進んだ分だけ
+        done = world.step - start_step;
+        progress.update(done, world);
+
+        if let Some(frames) = frames
+            && world.step.is_multiple_of(frames.every)
+        {
+            frames.write(world)?;
+        }
+        if let Some(autosave) = autosave
+            && autosave.is_due(world.step)
+        {
+            autosave.save(world)?;
+        }
+    }
+    progress.finish(done, world);
+
+    if let Some(step) = world.extinction_step {
+        if world.halted() {
+            println!("Extinct at step {step} (halted)");
+        } else {
+            println!("Extinct at step {step}");
+        }
+    }
+
+    let stats = world.stats();
+    println!("Step: {}", stats.step);
+    println!("Population: {}", stats.population);
+    println!(
+        "Peak Population: {} (step {})",
+        stats.peak_population, stats.peak_step
+    );
+    println!("Predator:Prey: {}:{}", stats.predators, stats.prey);
+    println!("Max Generation: {}", stats.max_generation);
+    println!("Avg Energy: {:.1}", stats.avg_energy);
+    println!("Food Count: {}", stats.food_count);
+
+    let lineages = world.lineages();
+    println!("Surviving Lineages: {}", lineages.surviving_lineages);
+    println!("Deepest Lineage: {} generations", lineages.deepest_lineage);
+    match lineages.dominant_founder {
+        Some(founder) => println!(
+            "Dominant Lineage: #{founder} ({:.1}% of population)",
+            lineages.dominant_share * 100.0
+        ),
+        None => println!("Dominant Lineage: -"),
+    }
+
+    // `timings` フィーチャー付きでビルドしたときだけ、処理ごとの時間の内訳を出す
+    if let Some(timings) = world.total_timings() {
+        let total = timings.total().as_secs_f64();
+        let share = |d: Duration| d.as_secs_f64() / total.max(f64::EPSILON) * 100.0;
+        println!("Step Timings: {total:.3}s total");
+        for (name, d) in [
+            ("deaths", timings.deaths),
+            ("food_spawn", timings.food_spawn),
+            ("decide", timings.decide),
+            ("apply", timings.apply),
+            ("reproduce", timings.reproduce),
+        ] {
+            println!(
+                "  {name:<10} {:>9.3}s ({:>5.1}%)",
+                d.as_secs_f64(),
+                share(d)
+            );
+        }
+    }
+
+    if let Some(path) = summary_json {
+        let summary = Summary {
+            seed: world.seed,
+            steps_run: world.step - start_step,
+            final_population: stats.population,
+            peak_population: stats.peak_population,
+            peak_step: stats.peak_step,
+            max_generation: stats.max_generation,
+            extinction_step: stats.extinction_step,
+            lineages,
+            config: &world.params,
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &summary)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// `--evolve` で、いちばん良い個体を選ぶ基準
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Fitness {
+    /// 実行中に産んだ子供の数
+    Offspring,
+    /// 最後に持っているエネルギー
+    Energy,
+}
+
+impl Fitness {
+    fn label(self) -> &'static str {
+        match self {
+            Fitness::Offspring => "offspring",
+            Fitness::Energy => "energy",
+        }
+    }
+}
+
+/// `steps` ステップ進めて、生き残りの中でいちばん良い個体の脳を `out` に書き出す。
+/// 世代に関係なく生きている個体から選ぶので、最初の世代しか残っていなくても書ける。
+/// 同じ適応度ならエネルギーが多いほう、それも同じなら ID が小さいほう。
+/// 全滅していたら書く脳がないので、エラーにする
+pub fn evolve(
+    world: &mut World,
+    steps: u64,
+    fitness: Fitness,
+    out: &Path,
+) -> io::Result<()> {
+    let mut progress = Progress::new(steps);
+    let start_step = world.step;
+
+    // 子供の数は出来事から数えるので、1ステップずつ進める
+    let mut offspring: HashMap<AgentId, u32> = HashMap::new();
+    let mut done = 0;
+    while done < steps && !world.halted() {
+        world.step();
+        for event in &world.events {
+            if let StepEvent::Birth { parent, .. } = event {
+                *offspring.entry(*parent).or_default() += 1;
+            }
+        }
+        done = world.step - start_step;
+        if done.is_multiple_of(CHECK_EVERY) {
+            progress.update(done, world);
+        }
+    }
+    progress.finish(done, world);
+
+    let score = |agent: &Agent| match fitness {
+        Fitness::Offspring => offspring.get(&agent.id).copied().unwrap_or(0),
+        Fitness::Energy => agent.energy,
+    };
+    let best = world
+        .agents
+        .values()
+        .max_by_key(|a| (score(a), a.energy, Reverse(a.id)))
+        .ok_or_else(|| {
+            io::Error::other(format!(
+                "no agents alive at step {}, no brain to save",
+                world.step
+            ))
+        })?;
+    best.brain().save(out)?;
+
+    println!("Step: {}", world.step);
+    println!("Population: {}", world.agents.len());
+    println!("Best Agent: {} (#{})", best.name, best.id);
+    println!("Generation: {}", best.generation);
+    println!("Fitness: {} {}", score(best), fitness.label());
+    println!("Saved Brain: {}", out.display());
+
+    Ok(())
+}
+
+/// 再現性と速さをまとめて確認する。
+/// `steps` ステップ進めて、最後の状態のハッシュと1秒あたりのステップ数を出す。
+/// `expect_hash` があって、ハッシュが違ったらエラーにする（CI でこけるように）
+pub fn bench(world: &mut World, steps: u64, expect_hash: Option<u64>) -> io::Result<()> {
+    let started = Instant::now();
+    world.run(steps as usize);
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let hash = world.state_hash();
+    println!("Steps: {steps}");
+    println!("State Hash: {hash:016x}");
+    println!(
+        "Throughput: {:.1} steps/s ({:.2}s)",
+        steps as f64 / elapsed,
+        elapsed
+    );
+
+    match expect_hash {
+        Some(expected) if expected != hash => Err(io::Error::other(format!(
+            "state hash mismatch: expected {expected:016x}, got {hash:016x}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// スクリプトから読むための、1回の実行結果のまとめ
+#[derive(Serialize)]
+struct Summary<'a> {
+    seed: u64,
+    steps_run: u64,
+    final_population: usize,
+    peak_population: usize,
+    peak_step: u64,
+    max_generation: u32,
+    /// 絶滅したステップ。最後まで生き残っていれば null
+    extinction_step: Option<u64>,
+    /// 最後に残った家系のまとめ
+    lineages: LineageStats,
+    /// 使った設定
+    config: &'a Params,
+}
+
+/// 進捗表示。
+/// 端末なら1行を上書きし続けて、そうでなければたまに普通の行で出す。
+struct Progress {
+    total: u64,
+    started: Instant,
+    last_print: Instant,
+    is_tty: bool,
+}
+
+impl Progress {
+    fn new(total: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            total,
+            started: now,
+            last_print: now,
+            is_tty: io::stderr().is_terminal(),
+        }
+    }
+
+    fn update(&mut self, done: u64, world: &World) {
+        let interval = if self.is_tty {
+            TTY_INTERVAL
+        } else {
+            PLAIN_INTERVAL
+        };
+        if self.last_print.elapsed() < interval {
+            return;
+        }
+        self.last_print = Instant::now();
+
+        let line = self.format_line(done, world);
+        let mut stderr = io::stderr().lock();
+        if self.is_tty {
+            // \x1b[K で行末まで消して、前の表示の残りカスを消す
+            let _ = write!(stderr, "\r{line}\x1b[K");
+        } else {
+            let _ = writeln!(stderr, "{line}");
+        }
+        let _ = stderr.flush();
+    }
+
+    fn finish(&self, done: u64, world: &World) {
+        let line = self.format_line(done, world);
+        if self.is_tty {
+            eprintln!("\r{line}\x1b[K");
+        } else {
+            eprintln!("{line}");
+        }
+    }
+
+    fn format_line(&self, done: u64, world: &World) -> String {
+        let percent = if self.total > 0 {
+            done as f64 / self.total as f64 * 100.0
+        } else {
+            100.0
+        };
+
+        // 今までの速さから残り時間を見積もる
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let eta = if done > 0 {
+            elapsed / done as f64 * (self.total - done) as f64
+        } else {
+            0.0
+        };
+
+        format!(
+            "Step {done}/{} ({percent:.1}%) | Population: {} | ETA: {}",
+            self.total,
+            world.agents.len(),
+            format_duration(eta),
+        )
+    }
+}
+
+/// 秒数を "1h02m03s" みたいな形にする
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}h{m:02}m{s:02}s")
+    } else if m > 0 {
+        format!("{m}m{s:02}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
+/// `cargo test --release --features bench-test -- --nocapture` で回す、再現性と速さの確認
+#[cfg(all(test, feature = "bench-test"))]
+mod tests {
+    use super::*;
+
+    /// `--seed 42`・デフォルトの設定で `BENCH_STEPS` ステップ進めたあとの状態のハッシュ。
+    /// 挙動や保存形式をわざと変えたときは、`--nocapture` で出てくる `State Hash` で書き換える
+    const EXPECTED_HASH: u64 = 0x1b4f_9afd_8fc0_627e;
+    const BENCH_STEPS: u64 = 10_000;
+
+    #[test]
+    fn default_world_matches_expected_hash() {
+        let mut world = crate::create_world(42, Params::default());
+        bench(&mut world, BENCH_STEPS, Some(EXPECTED_HASH)).unwrap();
+    }
+}
@@ -1,3 +1,10 @@
 pub mod agent;
 pub mod brain;
+pub mod event;
+pub mod params;
+pub mod rng;
+pub mod stats;
+pub mod terrain;
+pub mod ticker;
+pub mod ui;
 pub mod world;
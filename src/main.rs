@@ -1,3 +1,4 @@
+use clap::Parser;
 use crossterm::{
     event::{self, Event, KeyCode},
     execute,
@@ -5,56 +6,231 @@ use crossterm::{
         EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
     },
 };
-use rand::Rng;
-use ratatui::{
-    prelude::*,
-    widgets::{
-        Block, Borders, Paragraph,
-        canvas::{Canvas, Rectangle},
-    },
-};
-use std::{io, time::Duration};
-
-// 自分で作ったモジュールたち
-mod agent;
-mod brain;
-mod world;
+use ratatui::prelude::*;
+use std::{io, path::PathBuf, time::Duration};
 
+// 自分で作ったモジュールたち (lib.rs)
 // ※定数は world.rs か consts.rs にある想定
 // ここでは簡易的に直書きしてるけど、適宜 use してね
-use crate::world::{Position, World};
+use rikulife::{
+    params::Params,
+    stats::Churn,
+    ticker::{Ticker, TickerEvent},
+    ui::{self, AgeTint, ColorMode, ViewOptions},
+    world::{AgentId, Autosave, HEIGHT, ImageMapping, Position, WIDTH, World},
+};
+
+mod headless;
+mod sweep;
+
+/// コマンドライン引数
+#[derive(Debug, Parser)]
+struct Cli {
+    /// 乱数のシード。固定すると再現性が取れるよ
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+
+    /// 設定ファイル（TOML）。書かなかった項目はデフォルト値になる
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// TUIを出さずに、シミュレーションだけ回す
+    #[arg(long)]
+    headless: bool,
+
+    /// 初期配置を画像（PNG）から読み込む。白は何もない、緑 (0,255,0) は餌、黒は壁、
+    /// それ以外の色はその色の個体になる。画像はフィールドと同じ大きさにする
+    #[arg(long)]
+    scenario: Option<PathBuf>,
+
+    /// ヘッドレスモードで進めるステップ数
+    #[arg(long, default_value_t = 10_000)]
+    steps: u64,
+
+    /// ヘッドレスモードの最後に、結果のまとめを JSON で書き出す先
+    #[arg(long, requires = "headless")]
+    summary_json: Option<PathBuf>,
+
+    /// ヘッドレスモードで、コマ撮りの画像（1マス1ピクセルの PNG）を書き出すフォルダ
+    #[arg(long, requires = "headless", conflicts_with = "bench")]
+    frames: Option<PathBuf>,
+
+    /// コマ撮りを何ステップごとに撮るか
+    #[arg(long, default_value_t = 10, requires = "frames")]
+    frame_every: u64,
+
+    /// コマ撮りを256色に減らして、色の番号の画像（グレースケールの PNG）と
+    /// パレット（RGB を並べた .pal）で書き出す。GIF にまとめるとき用
+    #[arg(long, requires = "frames")]
+    indexed_frames: bool,
+
+    /// ヘッドレスモードで、世界を自動保存する先。
+    /// `<パス>.0` と `<パス>.1` に交互に書くので、片方が壊れてももう片方が残る
+    #[arg(long, requires = "headless", conflicts_with_all = ["bench", "seeds"])]
+    autosave_path: Option<PathBuf>,
+
+    /// 何ステップごとに自動保存するか
+    #[arg(long, default_value_t = 1000, requires = "autosave_path")]
+    autosave_every: u64,
+
+    /// `--autosave-path` で保存したところから続ける（壊れていない一番新しいものを読む）。
+    /// 設定は保存していないので、保存したときと同じ `--config` を渡す。
+    /// `--steps` は読み込んだところからさらに進める数
+    #[arg(long, conflicts_with_all = ["scenario", "seeds"])]
+    resume: Option<PathBuf>,
+
+    /// ヘッドレスモードで、統計の代わりに最後の状態のハッシュと速さ（steps/s）を出す
+    #[arg(long, requires = "headless", conflicts_with = "summary_json")]
+    bench: bool,
+
+    /// `--bench` で、最後の状態のハッシュがこれ（16進数）と違ったら失敗にする
+    #[arg(long, requires = "bench", value_parser = parse_hash)]
+    expect_hash: Option<u64>,
+
+    /// ヘッドレスモードで、このシードの範囲を全部回して結果をまとめる（例: `1..50`, `1..=50`）。
+    /// `--seed` の代わりに使う
+    #[arg(long, requires = "headless", conflicts_with = "summary_json")]
+    seeds: Option<sweep::SeedRange>,
+
+    /// ヘッドレスで `--steps` ステップ進めて、いちばん良い個体の脳を `--out` に書き出す
+    #[arg(
+        long,
+        requires = "out",
+        conflicts_with_all = ["bench", "seeds", "summary_json", "frames"]
+    )]
+    evolve: bool,
+
+    /// `--evolve` で脳を書き出す先
+    #[arg(long, requires = "evolve")]
+    out: Option<PathBuf>,
+
+    /// `--evolve` で、いちばん良い個体を何で選ぶか
+    #[arg(long, value_enum, default_value_t = headless::Fitness::Offspring)]
+    fitness: headless::Fitness,
+
+    /// グレースケールで描画する（色覚特性のある人やモノクロ端末向け）
+    #[arg(long)]
+    grayscale: bool,
+
+    /// 年齢モードで、年寄りの色を寄せていく先
+    #[arg(long, value_enum, default_value_t = AgeTint::Gray)]
+    age_tint: AgeTint,
+
+    /// 年齢モードで、寿命ぎりぎりの個体をどれだけ寄せるか（0.0〜1.0）。
+    /// 1.0 なら寿命で完全に `--age-tint` の色になる
+    #[arg(long, default_value_t = 0.8)]
+    age_tint_strength: f32,
+
+    /// 出来事のログに流す種類（カンマ区切り）
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "birth,death,kill,stagnation"
+    )]
+    ticker_events: Vec<TickerEvent>,
+
+    /// フィールドの背景色（"black" や "#202020" など）。書かなければ端末のまま
+    #[arg(long)]
+    background: Option<Color>,
+
+    /// フィールドにうっすら格子を描く
+    #[arg(long)]
+    grid: bool,
+
+    /// 冬のあいだ、枠を寒い色にして雪の印を出す
+    #[arg(long)]
+    season_theme: bool,
+}
 
 fn main() -> io::Result<()> {
-    // 1. ターミナルのセットアップ (Ratatuiのおまじない)
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let cli = Cli::parse();
 
-    // 2. 世界の創造 🌍
-    // シード値は何でもいいけど、固定すると再現性が取れるよ
-    let mut world = World::new(42);
-
-    // 初期エージェントを50匹くらい撒く
-    let mut rem: usize = 100;
-    while rem > 0 {
-        let x = world.rng.random_range(0..crate::world::WIDTH);
-        let y = world.rng.random_range(0..crate::world::HEIGHT);
-        if world.add_new_agent(Position { x, y }).is_some() {
-            rem -= 1;
-        }
+    let params = match &cli.config {
+        Some(path) => Params::load(path)?,
+        None => Params::default(),
+    };
+
+    if let Some(seeds) = &cli.seeds {
+        sweep::run(seeds, cli.steps, &params);
+        return Ok(());
+    }
 
-        if rem == 0 {
-            break;
+    // 世界の創造 🌍
+    let mut world = match (&cli.resume, &cli.scenario) {
+        (Some(path), _) => {
+            let world = Autosave::load_latest(path, params)?;
+            eprintln!("Resumed from step {}", world.step);
+            world
+        }
+        (None, Some(path)) => {
+            World::from_image(path, &ImageMapping::default(), cli.seed, params)?
         }
+        (None, None) => create_world(cli.seed, params),
+    };
+
+    if cli.evolve
+        && let Some(out) = &cli.out
+    {
+        headless::evolve(&mut world, cli.steps, cli.fitness, out)?;
+        return Ok(());
     }
 
-    for _ in 0..5000 {
-        world.spawn_foods();
+    if cli.bench {
+        headless::bench(&mut world, cli.steps, cli.expect_hash)?;
+        return Ok(());
+    }
+
+    if cli.headless {
+        let frames = cli.frames.map(|dir| headless::FrameExport {
+            dir,
+            every: cli.frame_every.max(1),
+            indexed: cli.indexed_frames,
+        });
+        let autosave = cli
+            .autosave_path
+            .map(|path| Autosave::new(path, cli.autosave_every));
+        headless::run(
+            &mut world,
+            cli.steps,
+            cli.summary_json.as_deref(),
+            frames.as_ref(),
+            autosave.as_ref(),
+        )?;
+        return Ok(());
     }
 
-    run_app(&mut terminal, &mut world.clone()).unwrap();
+    let mut view = ViewOptions {
+        color_mode: if cli.grayscale {
+            ColorMode::Grayscale
+        } else {
+            ColorMode::Full
+        },
+        selected: None,
+        cursor: None,
+        paused: false,
+        tick_rate: ui::DEFAULT_TICK_RATE,
+        show_vision: false,
+        show_births: true,
+        show_facing: false,
+        food_density: false,
+        ticker: Ticker::new(cli.ticker_events),
+        churn: Churn::new(ui::CHURN_WINDOW),
+        background: cli.background,
+        grid: cli.grid,
+        season_theme: cli.season_theme,
+        age_tint: cli.age_tint,
+        age_tint_strength: cli.age_tint_strength.clamp(0.0, 1.0),
+    };
+
+    // 1. ターミナルのセットアップ (Ratatuiのおまじない)
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    run_app(&mut terminal, &mut world.clone(), &mut view).unwrap();
 
     // 4. お片付け (終了処理)
     disable_raw_mode()?;
@@ -66,185 +242,161 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, world: &mut World) -> io::Result<()> {
-    #[allow(unused_mut)]
+/// `--expect-hash` の16進数を読む（先頭の 0x はあってもなくてもいい）
+fn parse_hash(s: &str) -> Result<u64, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(digits, 16).map_err(|e| format!("invalid hash {s:?}: {e}"))
+}
+
+/// 初期エージェントと餌を撒いた世界を作る
+fn create_world(seed: u64, params: Params) -> World {
+    // シード値は何でもいいけど、固定すると再現性が取れるよ
+    let mut world = World::with_params(seed, params);
+
+    // 初期エージェントを100匹くらい撒く
+    let placement = world.params.initial_placement;
+    world.seed_agents_with(placement, 100);
+
+    for _ in 0..5000 {
+        world.spawn_foods();
+    }
+
+    world
+}
+
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    world: &mut World,
+    view: &mut ViewOptions,
+) -> io::Result<()> {
     let mut last_tick = std::time::Instant::now();
-    let tick_rate = Duration::from_millis(50); // 更新速度 (50ms = 20fps)
 
     loop {
         // --- 描画フェーズ 🎨 ---
-        terminal.draw(|f| ui(f, world))?;
+        terminal.draw(|f| ui::draw(f, world, view))?;
 
         // --- 入力 & 更新フェーズ 🎮 ---
-        let timeout = tick_rate
+        // 間隔がほぼ 0 でも、毎回キー入力は見る（'q' がすぐ効くように）
+        let timeout = view
+            .tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
 
         // キー入力があれば処理、なければ待機
+        let mut step_once = false;
         if crossterm::event::poll(timeout)?
             && let Event::Key(key) = event::read()?
         {
             match key.code {
                 KeyCode::Char('q') => return Ok(()), // 'q' で終了
-                KeyCode::Char(' ') => {
-                    // スペースキーでポーズとか入れたいならここに
+                KeyCode::Char(' ') => view.paused = !view.paused,
+                // 止めている間だけ、1ステップずつ進める
+                KeyCode::Char('.') => step_once = view.paused,
+                // '+' は Shift なしでも押せるように '=' でも
+                KeyCode::Char('+' | '=') => {
+                    view.tick_rate = ui::faster_tick_rate(view.tick_rate)
+                }
+                KeyCode::Char('-') => {
+                    view.tick_rate = ui::slower_tick_rate(view.tick_rate)
+                }
+                KeyCode::Char('c') => view.color_mode = view.color_mode.next(),
+                KeyCode::Char('b') => view.show_births = !view.show_births,
+                KeyCode::Char('f') => view.food_density = !view.food_density,
+                KeyCode::Char('g') => view.grid = !view.grid,
+                KeyCode::Tab => view.selected = next_agent_id(world, view.selected),
+                KeyCode::Char('v') => view.show_vision = !view.show_vision,
+                KeyCode::Char('o') => view.show_facing = !view.show_facing,
+                KeyCode::Char('w') => view.season_theme = !view.season_theme,
+                KeyCode::Up => view.cursor = Some(move_cursor(world, view.cursor, 0, -1)),
+                KeyCode::Down => {
+                    view.cursor = Some(move_cursor(world, view.cursor, 0, 1))
+                }
+                KeyCode::Left => {
+                    view.cursor = Some(move_cursor(world, view.cursor, -1, 0))
+                }
+                KeyCode::Right => {
+                    view.cursor = Some(move_cursor(world, view.cursor, 1, 0))
+                }
+                KeyCode::Esc => view.cursor = None,
+                // カーソルのマスに誰もいなければ何もしない
+                KeyCode::Char('k') => {
+                    if let Some(id) = agent_at_cursor(world, view.cursor) {
+                        world.kill_agent(id);
+                    }
+                }
+                KeyCode::Char('e') => {
+                    if let Some(id) = agent_at_cursor(world, view.cursor) {
+                        world.feed_agent(id, ui::FEED_AMOUNT);
+                    }
                 }
                 _ => {}
             }
         }
 
         // 時間が経ったら World を1ステップ進める
-        // if last_tick.elapsed() >= tick_rate {
-        //     world.step();
-        //     last_tick = std::time::Instant::now();
-        // }
+        if last_tick.elapsed() >= view.tick_rate {
+            if !view.paused {
+                advance(world, view);
+            }
+            last_tick = std::time::Instant::now();
+        }
+        if step_once {
+            advance(world, view);
+        }
+    }
+}
 
+/// World を1ステップ進めて、ログとペースに記録する
+fn advance(world: &mut World, view: &mut ViewOptions) {
+    // 全滅して止まる設定なら、もう進めない
+    if !world.halted() {
         world.step();
+        view.ticker.record(world);
+        view.churn.record(&world.events);
     }
 }
 
-// --- UI構築ロジック 🖼️ ---
-fn ui(f: &mut Frame, world: &World) {
-    // 画面を左右に分割
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(70), // 左70%: マップ
-            Constraint::Percentage(30), // 右30%: 情報
-        ])
-        .split(f.area());
-
-    // --- 1. 左側: 世界の描画 (Canvas) ---
-    // Canvasウィジェットを使うと、座標指定で矩形を描けるので便利！
-    let canvas = Canvas::default()
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(" Artificial Life "),
-        )
-        .x_bounds([0.0, crate::world::WIDTH as f64])
-        .y_bounds([0.0, crate::world::HEIGHT as f64])
-        .paint(|ctx| {
-            // A. 餌を描画 (緑色の小さな点) 🍏
-            for y in 0..crate::world::HEIGHT {
-                for x in 0..crate::world::WIDTH {
-                    if world.foods[y][x] {
-                        let (draw_x, draw_y) = calc_draw_position(Position { x, y });
-                        ctx.draw(&Rectangle {
-                            x: draw_x,
-                            y: draw_y,
-                            width: 1.0,
-                            height: 1.0,
-                            color: Color::Green,
-                        });
-                    }
-                }
-            }
-
-            // B. エージェントを描画 (RGB色の四角形)
-            for agent in world.agents.values() {
-                // Agentの色 (0.0~1.0) を u8 (0~255) に変換
-                let r = (agent.color[0] * 255.0) as u8;
-                let g = (agent.color[1] * 255.0) as u8;
-                let b = (agent.color[2] * 255.0) as u8;
-
-                let (draw_x, draw_y) = calc_draw_position(agent.pos);
-
-                ctx.draw(&Rectangle {
-                    x: agent.pos.x as f64,
-                    y: (crate::world::HEIGHT - 1 - agent.pos.y) as f64,
-                    width: 1.0,
-                    height: 1.0,
-                    color: Color::Rgb(r, g, b),
-                });
-
-                if let Some(action) = agent.last_action {
-                    match action {
-                        crate::agent::Action::Attack => {
-                            // 攻撃してる時は赤い "x" を重ねる
-                            ctx.print(
-                                draw_x,
-                                draw_y,
-                                Span::styled(
-                                    "x",
-                                    Style::default()
-                                        .fg(Color::LightBlue)
-                                        .add_modifier(Modifier::BOLD),
-                                ),
-                            );
-                        }
-                        crate::agent::Action::Heal => {
-                            // 回復してる時は緑の "+" を重ねる
-                            ctx.print(
-                                draw_x,
-                                draw_y,
-                                Span::styled(
-                                    "+",
-                                    Style::default()
-                                        .fg(Color::LightGreen)
-                                        .add_modifier(Modifier::BOLD),
-                                ),
-                            );
-                        }
-                        _ => {
-                            // 移動や待機の時は、記号を出さずにRGBの色だけ見せる
-                            // (何も描画しない)
-                        }
-                    }
-                }
-            }
-        });
-
-    f.render_widget(canvas, chunks[0]);
-
-    // --- 2. 右側: 統計情報 (Paragraph) ---
-    let population = world.agents.len();
-    let max_gen = world
-        .agents
-        .values()
-        .map(|a| a.generation)
-        .max()
-        .unwrap_or(0);
-    let total_energy: u32 = world.agents.values().map(|a| a.energy).sum();
-    let avg_energy = if population > 0 {
-        total_energy / population as u32
-    } else {
-        0
+/// カーソルを (dx, dy) だけ動かす（フィールドの外には出さない）。
+/// まだ出していなければ、真ん中に出す
+fn move_cursor(
+    world: &World,
+    cursor: Option<Position>,
+    dx: isize,
+    dy: isize,
+) -> Position {
+    let Some(cursor) = cursor else {
+        return Position {
+            x: WIDTH / 2,
+            y: HEIGHT / 2,
+        };
     };
-    let food_count: usize = world
-        .foods
-        .iter()
-        .map(|row| row.iter().filter(|&&f| f).count())
-        .sum();
-
-    let info_text = vec![
-        Line::from(vec![Span::raw("Statistics 📊")]),
-        Line::from(""),
-        Line::from(vec![Span::raw(format!("Step: {}", world.step))]),
-        Line::from(vec![Span::styled(
-            format!("Population: {}", population),
-            Style::default().fg(Color::Yellow),
-        )]),
-        Line::from(vec![Span::raw(format!("Max Generation: {}", max_gen))]),
-        Line::from(vec![Span::raw(format!("Avg Energy: {}", avg_energy))]),
-        Line::from(""),
-        Line::from(vec![Span::styled(
-            format!("Food Count: {}", food_count),
-            Style::default().fg(Color::Green),
-        )]),
-        Line::from(""),
-        Line::from("Controls:"),
-        Line::from(" 'q' to Quit"),
-    ];
-
-    let info_block = Paragraph::new(info_text)
-        .block(Block::default().borders(Borders::ALL).title(" Info "));
-
-    f.render_widget(info_block, chunks[1]);
+    let (x, y) = (cursor.x as isize + dx, cursor.y as isize + dy);
+    if world.in_bounds(x, y) {
+        Position {
+            x: x as usize,
+            y: y as usize,
+        }
+    } else {
+        cursor
+    }
+}
+
+/// カーソルのマスにいる個体（何匹もいたら ID が一番小さい個体）
+fn agent_at_cursor(world: &World, cursor: Option<Position>) -> Option<AgentId> {
+    world.agents_at(cursor?).first().copied()
 }
 
-fn calc_draw_position(pos: crate::world::Position) -> (f64, f64) {
-    let draw_x = pos.x as f64;
-    let draw_y = (crate::world::HEIGHT - 1 - pos.y) as f64;
-    (draw_x, draw_y)
+/// インスペクタの対象を、ID順で次のエージェントに切り替える
+fn next_agent_id(world: &World, current: Option<AgentId>) -> Option<AgentId> {
+    let ids = world.agents_sorted_by(|a| a.id);
+
+    match current {
+        // 今の次のIDを探して、なければ先頭に戻る
+        Some(current) => ids
+            .iter()
+            .copied()
+            .find(|&id| id > current)
+            .or(ids.first().copied()),
+        None => ids.first().copied(),
+    }
 }
@@ -0,0 +1,1100 @@
+use std::{io, path::Path};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::{Action, ActionMask, Agent, Color},
+    brain::{
+        HIDDEN_SIZE, INPUT_SIZE, OUTPUT_ACTION_SIZE, OUTPUT_LAYOUT, OutputLayout,
+        SELF_COLOR_SIZE,
+    },
+    terrain::Terrain,
+    world::{
+        ATTACK_AMOUNT, FOOD_ENERGY, HEIGHT, INIT_ENERGY, INTERACT_COST, MAX_FOODS, WIDTH,
+    },
+};
+
+/// シミュレーションの設定値。
+/// `Default` は今までの挙動（world.rs の定数）と同じになるようにしてある。
+/// 設定ファイル（TOML）で書かなかった項目はデフォルト値になる。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// 最初の個体（とあとから放り込む個体）の初期エネルギー。最大エネルギーまで。
+    /// 子供の初期エネルギー（`CHILD_INIT_ENERGY`）とは別
+    pub init_energy: u32,
+
+    /// 攻撃・回復が届く半径。1 なら周囲8マス、2 なら周囲24マス。
+    pub interact_radius: usize,
+    /// 子供を産める場所の半径。
+    pub reproduce_radius: usize,
+    /// 空いている場所のうち、どこに子供を産むか
+    pub birth_placement: BirthPlacement,
+    /// どれだけエネルギーがたまったら子供を産むか
+    pub reproduce_threshold: ReproduceThreshold,
+
+    /// 夏の餌の分布
+    pub summer_food_spread: FoodSpread,
+    /// 冬の餌の分布。夏より中心に寄せて、餌を奪い合うようにする
+    pub winter_food_spread: FoodSpread,
+
+    /// 行動を選ぶときに Stay の出力に足す値。
+    /// 生まれたてのランダムな脳が無駄に動き回ってエネルギーを使うのを抑える
+    pub stay_bias: f32,
+
+    /// 他の個体がどこまで見えるか
+    pub perception: Perception,
+
+    /// 自分の色も入力に入れるか。入れると脳の入力が `SELF_COLOR_SIZE` 個増える
+    /// （途中で変えると、保存した世界は読み込めなくなる）
+    pub self_color_input: bool,
+
+    /// 視線の遮蔽。true なら、壁や他の個体の向こう側のマスは見えない（入力が全部 0 になる）
+    pub vision_occlusion: bool,
+
+    /// 最初の個体の出力層バイアスの初期化方法
+    pub output_bias_init: OutputBiasInit,
+    /// 脳の隠れ層の ReLU で、負の値を通す傾き（Leaky ReLU）。0.0 なら普通の ReLU。
+    /// 突然変異を重ねると、ずっと 0 しか出さない（死んだ）ユニットが増えやすいので、その対策
+    pub relu_slope: f32,
+    /// 最初の個体の色の決め方
+    pub founder_colors: FounderColors,
+    /// 子供の脳の突然変異の強さが、時間とともにどう変わるか
+    pub mutation: MutationSchedule,
+
+    /// 攻撃したあと、次に攻撃できるまで待つステップ数。
+    /// 0 なら毎ステップ攻撃できる。クールダウン中に攻撃しようとすると待機になる
+    pub attack_cooldown: u64,
+
+    /// 攻撃1回で相手に与える基本のダメージ（`attack_scaling` で変わる前の値）
+    pub attack_damage: u32,
+    /// 攻撃で自分が得るエネルギー（ダメージとは別に決められる）
+    pub attack_gain: AttackGain,
+    /// 攻撃のダメージの決め方
+    pub attack_scaling: AttackScaling,
+    /// 鎧の遺伝子（受けるダメージが減る代わりに、基礎代謝が増える）。
+    /// 書かなければみんな鎧 0 のまま変わらない
+    pub armor: Option<ArmorParams>,
+
+    /// 攻撃の反動。攻撃が当たった相手1匹ごとに、自分のエネルギーがこれだけ減る
+    pub attack_recoil: u32,
+
+    /// 攻撃が周りの誰に当たるか
+    pub attack_targeting: InteractTargeting,
+    /// 回復が周りの誰に効くか
+    pub heal_targeting: InteractTargeting,
+    /// 回復で相手に渡すエネルギーの出どころ
+    pub heal_mode: HealMode,
+
+    /// 行動ごとのエネルギーのコスト
+    pub action_costs: ActionCosts,
+    /// 選べなくする行動（攻撃なしの世界を比べるときなど）。
+    /// 脳がその行動を一番に出しても、次に大きい行動を選ぶ
+    pub disabled_actions: ActionMask,
+
+    /// 待機したときに基礎代謝から割り引く量（鎧の維持費は割り引かない）。
+    /// 基礎代謝以上にすると、待機はタダになる（じっとしている個体は飢饉でも寿命までは生き延びる）
+    pub stay_metabolism_discount: u32,
+
+    /// エネルギーがこれ以下になると死ぬ（0 なら今まで通り、空っぽになったら死ぬ）
+    pub death_energy_threshold: u32,
+    /// しきい値以下になってから、何ステップ持ちこたえられるか。0 ならすぐ死ぬ
+    pub death_grace_steps: u32,
+
+    /// 壁に向かって移動しようとしたときの扱い
+    pub wall_bump: WallBump,
+    /// 餌の食べ方（入ったら勝手に食べるか、`Action::Eat` で自分で食べるか）
+    pub eat_mode: EatMode,
+    /// ダッシュで進む最大のマス数
+    pub sprint_distance: usize,
+
+    /// 1マスに入れるエージェントの数。1 なら今まで通り重なれない。
+    /// 重なっているマスは、視界には「自分以外で一番IDが小さい個体」として見える
+    pub max_agents_per_cell: usize,
+
+    /// 贈り物で渡す、自分のエネルギーの割合（0.0〜1.0）
+    pub gift_fraction: f32,
+
+    /// 餌が湧くゾーン。ゾーンごとに湧きやすさ・栄養・分布を変えられる。
+    /// 重なっている場所は先に書いたゾーンが優先。どのゾーンにも入らない場所には湧かない
+    pub food_zones: Vec<FoodZone>,
+
+    /// 地形（泥や荒地）の作り方と、そこを通るコスト
+    pub terrain: TerrainParams,
+
+    /// フィールドにある餌の上限。これ以上あると新しく湧かない
+    pub max_foods: usize,
+    /// 個体数が少ないときに餌の上限を引き上げる（絶滅しかけたときの立て直し用）。
+    /// 書かなければ上限は `max_foods` のまま
+    pub dynamic_food_cap: Option<DynamicFoodCap>,
+    /// 1ステップで湧かせる餌の数の目安。これだけ置けたらそのステップは打ち切る。
+    /// 書かなければ、決まった回数だけ場所を選び続ける
+    pub food_spawn_target: Option<usize>,
+    /// 1ステップで、もう餌がある場所を引いてしまった回数の上限。
+    /// 餌でほぼ埋まっているときに、空振りし続けて時間を食わないように
+    pub food_spawn_max_misses: Option<usize>,
+    /// 死んだ個体が餌（死骸）を落とす設定。書かなければ何も落とさない
+    pub corpse_drop: Option<CorpseDrop>,
+
+    /// 満腹で食べきれなかった餌のエネルギーの扱い
+    pub reserve: ReserveParams,
+
+    /// 個体数の上限。ここまで増えたら子供を産めなくなる。書かなければ上限なし
+    pub max_population: Option<usize>,
+    /// 上限のせいで産めなかったときも、親は繁殖コストを払うか（混雑ペナルティ）
+    pub charge_at_capacity: bool,
+    /// 1ステップで生まれる子供の数の上限（出生の予算）。
+    /// 使い切ったら、そのステップの残りの個体は産まない（繁殖コストも払わない）。
+    /// 書かなければ上限なし
+    pub birth_budget: Option<usize>,
+
+    /// 最初の個体の撒き方
+    pub initial_placement: Placement,
+    /// 全滅したときにどうするか
+    pub on_extinction: ExtinctionPolicy,
+
+    /// 平均エネルギーの指数移動平均の係数（0.0〜1.0）。小さいほどなめらか
+    pub energy_smoothing: f32,
+
+    /// step() で個体を動かす順番
+    pub update_order: UpdateOrder,
+
+    /// 脳の計算に使うスレッド数。1 なら並列にしない。
+    /// 何スレッドでも結果は同じになる（視界が変わった個体は計算し直すので）
+    pub threads: usize,
+
+    /// 脳の多様性がなくなった（収束した）ことの検出
+    pub convergence: ConvergenceCheck,
+
+    /// みんながじっとして動かなくなっていないかの見張り
+    pub stagnation: StagnationCheck,
+
+    /// 捕食者・被食者の分類
+    pub roles: RoleParams,
+
+    /// 群れ全体の戦略（"Foragers" など）の見出しの決め方
+    pub strategy: StrategyThresholds,
+
+    /// 世代の内訳（`WorldStats::generation_bands`）で、何世代ずつ1つの帯にまとめるか
+    pub generation_band_width: u32,
+}
+
+/// 個体数に合わせて餌の上限を変える設定。
+/// 個体数が `low_population` 以下になると上限が上がり始め、0 匹で `max_foods * max_boost` になる
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamicFoodCap {
+    /// これより少なくなったら上限を上げる
+    pub low_population: usize,
+    /// 上限を最大で何倍にするか
+    pub max_boost: f32,
+}
+
+impl Default for DynamicFoodCap {
+    fn default() -> Self {
+        Self {
+            low_population: 100,
+            max_boost: 2.0,
+        }
+    }
+}
+
+/// 死んだ個体が落とす餌（死骸）の設定。
+/// 餌の上限（`Params::food_cap`）を超える分は落とさない
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorpseDrop {
+    /// 落とす餌の数の決め方
+    pub amount: CorpseAmount,
+    /// 死んだマスに餌がもうあったら、このマス数以内の周りに近い順に散らして置く。
+    /// 0 なら死んだマスにしか置かない（なので多くても1個）
+    pub spread_radius: usize,
+}
+
+impl Default for CorpseDrop {
+    fn default() -> Self {
+        Self {
+            amount: CorpseAmount::Fixed { count: 1 },
+            spread_radius: 0,
+        }
+    }
+}
+
+/// 死骸の餌の数の決め方。エネルギーから決めるときは、餌1個を `FOOD_ENERGY` として数える
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorpseAmount {
+    /// いつも count 個
+    Fixed { count: usize },
+    /// 最大エネルギーの fraction 倍の分。大きい個体ほどたくさん落とす
+    MaxEnergy { fraction: f32 },
+    /// 死んだときに残っていたエネルギーの fraction 倍の分。
+    /// 飢え死にや攻撃で死んだ個体はたいてい空っぽなので、寿命で死んだ個体くらいしか落とさない
+    RemainingEnergy { fraction: f32 },
+}
+
+impl CorpseAmount {
+    /// 残りのエネルギー `energy`、最大エネルギー `max_energy` の個体が死んだときに落とす餌の数
+    pub fn count(self, energy: u32, max_energy: u32) -> usize {
+        let foods = |energy: u32, fraction: f32| {
+            (energy as f32 * fraction.max(0.0) / FOOD_ENERGY as f32).round() as usize
+        };
+        match self {
+            CorpseAmount::Fixed { count } => count,
+            CorpseAmount::MaxEnergy { fraction } => foods(max_energy, fraction),
+            CorpseAmount::RemainingEnergy { fraction } => foods(energy, fraction),
+        }
+    }
+}
+
+/// 食べすぎた分のエネルギーを蓄え（脂肪みたいなもの）にしておく設定。
+/// 蓄えは、エネルギーが最大の半分を切ったら少しずつ取り崩される
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReserveParams {
+    /// true なら溢れた分を蓄えに回す。false なら今まで通り捨てる
+    pub store_overflow: bool,
+    /// 蓄えられる最大量
+    pub capacity: u32,
+    /// 1ステップで蓄えから取り崩せる量
+    pub draw_per_step: u32,
+}
+
+impl Default for ReserveParams {
+    fn default() -> Self {
+        Self {
+            store_overflow: false,
+            capacity: 100,
+            draw_per_step: 5,
+        }
+    }
+}
+
+/// 最近の行動から、捕食者か被食者かを分類するための設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoleParams {
+    /// だいたい何ステップ分の行動を見るか。
+    /// 攻撃・食事の回数は1ステップごとに `1 - 1/window` 倍されて忘れられていく
+    pub window: u32,
+    /// 攻撃回数 / 食べた餌の数 がこれ以上なら捕食者
+    pub predator_ratio: f32,
+}
+
+impl RoleParams {
+    /// 1ステップごとにカウンタに掛ける値
+    pub fn decay(&self) -> f32 {
+        1.0 - 1.0 / self.window.max(1) as f32
+    }
+}
+
+/// 群れ全体の戦略の見出しを決めるしきい値。
+/// 直前のステップの行動の内訳を見て、Predators → Altruists → Foragers → Idlers の順に、
+/// 割合がしきい値以上になった最初のものにする。どれにも届かなければ Mixed。
+/// 攻撃や回復はそもそも珍しい行動なので、しきい値を低めにしてある
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StrategyThresholds {
+    /// 攻撃の割合がこれ以上なら Predators
+    pub predators: f32,
+    /// 回復と贈り物の割合がこれ以上なら Altruists
+    pub altruists: f32,
+    /// 移動とダッシュの割合がこれ以上なら Foragers
+    pub foragers: f32,
+    /// 待機と休息の割合がこれ以上なら Idlers
+    pub idlers: f32,
+}
+
+impl Default for StrategyThresholds {
+    fn default() -> Self {
+        Self {
+            predators: 0.2,
+            altruists: 0.2,
+            foragers: 0.5,
+            idlers: 0.5,
+        }
+    }
+}
+
+impl Default for RoleParams {
+    fn default() -> Self {
+        Self {
+            window: 50,
+            predator_ratio: 1.0,
+        }
+    }
+}
+
+/// 集団の脳がほぼ同じになった（局所解にハマって進化が止まった）かどうかの検出。
+/// 全ペアの比較は重いので、一部の個体だけ取り出して比べる。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvergenceCheck {
+    /// 何ステップごとに調べるか。0 なら調べない
+    pub interval: u64,
+    /// 比べる個体の数
+    pub sample_size: usize,
+    /// 脳の違い（`Brain::distance` の平均）がこれより小さければ収束したとみなす
+    pub threshold: f32,
+    /// 収束したときに放り込む新しいランダムな個体の数。0 なら何もしない
+    pub inject_count: usize,
+}
+
+impl Default for ConvergenceCheck {
+    fn default() -> Self {
+        Self {
+            interval: 100,
+            sample_size: 32,
+            threshold: 0.5,
+            inject_count: 0,
+        }
+    }
+}
+
+/// 停滞の見張りの設定。
+/// 一定ステップの間に場所が変わった個体の割合を測って、少なすぎたら停滞とみなす。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StagnationCheck {
+    /// 何ステップの間の動きを見るか。0 なら見張らない
+    pub window: u64,
+    /// 動いた個体の割合がこれより小さければ停滞とみなす
+    pub threshold: f32,
+}
+
+impl Default for StagnationCheck {
+    fn default() -> Self {
+        Self {
+            window: 100,
+            threshold: 0.05,
+        }
+    }
+}
+
+/// 最初の個体の撒き方。創始者効果や序盤の競争の激しさが変わる
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// フィールド全体にばらばらに撒く
+    Uniform,
+    /// いくつかの群れ（コロニー）に分けて撒く。
+    /// 各個体は群れの中心から縦横 radius マス以内に置かれる
+    Clustered { clusters: usize, radius: usize },
+    /// フィールドの中心を囲む輪っかの上に撒く
+    CenterRing { radius: f32, thickness: f32 },
+}
+
+/// 全滅したときの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtinctionPolicy {
+    /// そのまま続ける（誰もいない世界で餌だけ湧き続ける）
+    Continue,
+    /// 止める。`World::run` やヘッドレスモードはそこで打ち切る
+    Halt,
+    /// `initial_placement` の撒き方で、新しいランダムな個体を count 匹撒き直す
+    Reseed { count: usize },
+}
+
+/// 最初の個体の色の決め方。
+/// どちらでも、そのあとの色は脳の出力で変わっていく
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FounderColors {
+    /// 1匹ずつばらばらのランダムな色
+    Random,
+    /// 決まった色のどれか（ID順に順番に割り当てる）。
+    /// 始まりの「種」ごとに色が分かれるので、どう分かれていくか追いやすい
+    Palette(Vec<Color>),
+}
+
+impl FounderColors {
+    /// ID が `id` の最初の個体の色。ランダムのときだけ `rng` を使う
+    pub fn pick<R: Rng + ?Sized>(&self, id: usize, rng: &mut R) -> Color {
+        match self {
+            FounderColors::Palette(palette) if !palette.is_empty() => {
+                palette[id % palette.len()]
+            }
+            _ => [rng.random(), rng.random(), rng.random()],
+        }
+    }
+}
+
+/// 脳の突然変異の強さ（`Brain::mutate_inplace` に渡すもの）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MutationRate {
+    /// 変異させるパラメータの割合（0.0〜1.0）
+    pub rate: f32,
+    /// 変異の大きさ（正規分布の標準偏差）
+    pub sigma: f32,
+}
+
+impl Default for MutationRate {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            sigma: 0.2,
+        }
+    }
+}
+
+/// 突然変異の強さの予定表。焼きなましみたいに、
+/// 最初は大きく変異させていろいろ試して、あとから小さくして磨く、ができる
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationSchedule {
+    /// ずっと同じ
+    Constant(MutationRate),
+    /// `steps` ステップかけて、sigma が `start_sigma` から `end_sigma` まで直線で変わる。
+    /// そのあとは `end_sigma` のまま
+    LinearDecay {
+        rate: f32,
+        start_sigma: f32,
+        end_sigma: f32,
+        steps: u64,
+    },
+    /// `every` ステップごとに、sigma が `factor` 倍になる
+    StepDecay {
+        rate: f32,
+        sigma: f32,
+        factor: f32,
+        every: u64,
+    },
+}
+
+impl MutationSchedule {
+    /// ステップ `step` で生まれる子供の突然変異の強さ
+    pub fn at(&self, step: u64) -> MutationRate {
+        match *self {
+            MutationSchedule::Constant(rate) => rate,
+            MutationSchedule::LinearDecay {
+                rate,
+                start_sigma,
+                end_sigma,
+                steps,
+            } => {
+                let t = if steps == 0 {
+                    1.0
+                } else {
+                    (step as f32 / steps as f32).min(1.0)
+                };
+                MutationRate {
+                    rate,
+                    sigma: start_sigma + (end_sigma - start_sigma) * t,
+                }
+            }
+            MutationSchedule::StepDecay {
+                rate,
+                sigma,
+                factor,
+                every,
+            } => {
+                let times = step.checked_div(every).unwrap_or(0);
+                MutationRate {
+                    rate,
+                    sigma: sigma * factor.powi(times.min(i32::MAX as u64) as i32),
+                }
+            }
+        }
+    }
+}
+
+/// 行動ごとのエネルギーのコスト表。
+/// 休息以外は基礎代謝を払って、そのうえで行動ごとのコストがかかる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionCosts {
+    /// 毎ステップかかる基礎代謝。0 にすると、何もしなければエネルギーが減らない。
+    /// 待機のときだけ安くしたいなら `Params::stay_metabolism_discount`
+    pub basal: u32,
+    /// 上下左右に1マス移動する
+    pub movement: u32,
+    /// ダッシュ（普通の移動より疲れる）
+    pub sprint: u32,
+    pub attack: u32,
+    pub heal: u32,
+    pub gift: u32,
+    pub stay: u32,
+    pub rest: u32,
+    /// 餌を食べる（自分で食べる設定のときだけ）
+    pub eat: u32,
+}
+
+impl ActionCosts {
+    /// `action` をしたときの基礎代謝（休息中は代謝を抑えるのでかからない）
+    pub fn basal(&self, action: Action) -> u32 {
+        match action {
+            Action::Rest => 0,
+            _ => self.basal,
+        }
+    }
+
+    /// `action` そのもののコスト（基礎代謝とは別）
+    pub fn of(&self, action: Action) -> u32 {
+        match action {
+            Action::Up | Action::Down | Action::Left | Action::Right => self.movement,
+            Action::Sprint => self.sprint,
+            Action::Attack => self.attack,
+            Action::Heal => self.heal,
+            Action::Gift => self.gift,
+            Action::Stay => self.stay,
+            Action::Rest => self.rest,
+            Action::Eat => self.eat,
+        }
+    }
+}
+
+impl Default for ActionCosts {
+    fn default() -> Self {
+        Self {
+            basal: 1,
+            movement: 1,
+            sprint: 3,
+            attack: INTERACT_COST,
+            heal: INTERACT_COST,
+            gift: 0,
+            stay: 0,
+            rest: 0,
+            eat: 0,
+        }
+    }
+}
+
+/// step() で個体を動かす順番
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateOrder {
+    /// エネルギーが少ない順（今まで通り）。弱っている個体が先に餌にありつける
+    LowestEnergyFirst,
+    /// ステップごとにランダム（`World::shuffle_deterministic` で混ぜるので再現性はある）
+    Random,
+}
+
+/// 餌の食べ方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EatMode {
+    /// 餌のマスに入ったら勝手に食べる（今まで通り）
+    Auto,
+    /// 入っただけでは食べない。`Action::Eat` を選ぶと、今いるマスの餌を食べる。
+    /// 脳の出力が1つ増えるので、`Auto` で保存した世界とは脳の形が合わない
+    Active,
+}
+
+/// 壁（フィールドの端か、壁の地形）にぶつかって動けなかったときの扱い。
+/// 他の個体にぶつかったときは、いつも移動コストを払う
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WallBump {
+    /// 動けなくても移動コストは払う（今まで通り）
+    Pay,
+    /// 動けなかったので移動コストを返す
+    Refund,
+    /// 移動コストに加えて、ぶつかった罰としてこれだけ減る
+    Penalty(u32),
+}
+
+/// 子供を産めるようになるエネルギーの量
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReproduceThreshold {
+    /// 満タン（`max_energy`）になったら
+    Full,
+    /// エネルギーがこの値以上になったら（`max_energy` より大きくても満タンで産める）
+    Absolute(u32),
+    /// エネルギーが `max_energy` のこの割合以上になったら
+    Fraction(f32),
+}
+
+impl ReproduceThreshold {
+    /// エネルギーが `energy` の個体が産めるか
+    pub fn reached(self, energy: u32, max_energy: u32) -> bool {
+        let threshold = match self {
+            ReproduceThreshold::Full => max_energy,
+            ReproduceThreshold::Absolute(value) => value.min(max_energy),
+            ReproduceThreshold::Fraction(fraction) => (max_energy as f32 * fraction)
+                .ceil()
+                .clamp(0.0, max_energy as f32)
+                as u32,
+        };
+        energy >= threshold
+    }
+}
+
+/// 鎧の遺伝子の設定。鎧は 0.0〜1.0 で、受ける攻撃のダメージをその割合だけ減らす
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArmorParams {
+    /// 最初に撒く個体の鎧
+    pub initial: f32,
+    /// 子供に受け継ぐときに、親の値から最大でどれだけずらすか
+    pub mutation: f32,
+    /// 鎧 1.0 のときに増える基礎代謝（鎧の値に比例させて切り上げる）。休息中はかからない
+    pub metabolism: f32,
+}
+
+impl Default for ArmorParams {
+    fn default() -> Self {
+        Self {
+            initial: 0.0,
+            mutation: 0.05,
+            metabolism: 2.0,
+        }
+    }
+}
+
+impl ArmorParams {
+    /// 鎧 `armor` の個体が、毎ステップ余分に払う基礎代謝。
+    /// 切り上げるので、鎧が少しでもあればタダにはならない
+    pub fn upkeep(&self, armor: f32) -> u32 {
+        (armor.max(0.0) * self.metabolism.max(0.0)).ceil() as u32
+    }
+}
+
+/// 攻撃で自分が得るエネルギー
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttackGain {
+    /// 実際に与えたダメージ（相手が持っていた分まで）の割合。
+    /// 0.0 なら奪わずに傷つけるだけ、1.0 より大きいと与えた以上に得る
+    Fraction(f32),
+    /// ダメージに関係なく、何か奪えた相手1匹ごとにこれだけ得る
+    Fixed(u32),
+}
+
+impl AttackGain {
+    /// 相手に実際に `damage` だけ与えたときに得るエネルギー
+    pub fn gained(self, damage: u32) -> u32 {
+        match self {
+            AttackGain::Fraction(fraction) => (damage as f32 * fraction.max(0.0)) as u32,
+            AttackGain::Fixed(amount) if damage > 0 => amount,
+            AttackGain::Fixed(_) => 0,
+        }
+    }
+}
+
+/// 攻撃のダメージの決め方
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttackScaling {
+    /// いつも同じ（`attack_damage`）
+    Flat,
+    /// 攻撃する側のエネルギーの割合で変わる。
+    /// 空っぽなら `attack_damage * min_factor`、満タンなら `attack_damage * max_factor` で、その間は直線
+    Energy { min_factor: f32, max_factor: f32 },
+}
+
+impl AttackScaling {
+    /// 基本のダメージ `damage` を、攻撃する側のエネルギーに合わせて変える
+    pub fn scale(self, damage: u32, energy: u32, max_energy: u32) -> u32 {
+        match self {
+            AttackScaling::Flat => damage,
+            AttackScaling::Energy {
+                min_factor,
+                max_factor,
+            } => {
+                let ratio = if max_energy > 0 {
+                    (energy as f32 / max_energy as f32).min(1.0)
+                } else {
+                    0.0
+                };
+                let factor = (min_factor + (max_factor - min_factor) * ratio).max(0.0);
+                (damage as f32 * factor).round() as u32
+            }
+        }
+    }
+}
+
+/// 子供を産む場所の選び方。
+/// 好みに合う場所が複数あればその中からランダム、1つもなければ空いている場所全部からランダム
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BirthPlacement {
+    /// 空いている場所からランダム（今まで通り）
+    Random,
+    /// そのマスか隣（周囲8マス）に餌がある場所
+    TowardFood,
+    /// 周囲8マスにいる個体が一番少ない場所（散らばりやすくなる）
+    AwayFromCrowd,
+}
+
+/// 攻撃・回復を、周りのどの個体に向けるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractTargeting {
+    /// 周りの全員（今まで通り）
+    All,
+    /// 一番エネルギーが少ない1匹だけ（同じなら先に見つけたほう）。
+    /// 攻撃ならとどめを刺しに行く、回復なら一番弱っている仲間を助ける
+    WeakestOnly,
+    /// 一番エネルギーが多い1匹だけ（同じなら先に見つけたほう）
+    StrongestOnly,
+}
+
+/// 回復のエネルギーの出どころ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealMode {
+    /// 何もないところから湧く（今まで通り）。回復するほど全体のエネルギーが増える
+    Free,
+    /// 回復した分だけ自分が減る。全体のエネルギーは変わらない（コストの分は減る）。
+    /// 自分の手持ちより多くは渡せない
+    Transfer,
+}
+
+/// 視界に他の個体がどう映るか
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Perception {
+    /// 誰でも見える
+    All,
+    /// 自分と色が近い（RGB空間での距離が max_color_distance 以下の）個体だけ見える。
+    /// それ以外の個体がいるマスは、何もいないように見える
+    OwnSpecies { max_color_distance: f32 },
+}
+
+impl Perception {
+    /// `viewer` から `target` が見えるか
+    pub fn can_see(self, viewer: &Agent, target: &Agent) -> bool {
+        match self {
+            Perception::All => true,
+            Perception::OwnSpecies { max_color_distance } => {
+                let distance = viewer
+                    .color
+                    .iter()
+                    .zip(target.color)
+                    .map(|(a, b)| (a - b).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                distance <= max_color_distance
+            }
+        }
+    }
+}
+
+/// 出力層バイアスの初期化方法。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBiasInit {
+    /// 全部 0。行動はランダムな重みだけで決まる
+    Zero,
+    /// 行動ごとに決めた値で初期化する（インデックスは `Action` の値）。
+    /// 例えば移動を少しだけ高くしておくと、最初から全員が攻撃だけ、みたいな偏りを防げる。
+    /// 色の出力と、自分で食べる設定のときの `Action::Eat` は 0 のまま。
+    Prior([f32; OUTPUT_ACTION_SIZE]),
+}
+
+/// 餌がどれくらい中心に集まって湧くか。
+/// 湧く確率は `基本確率 * (1 - 距離 / (最大距離 * radius_scale))^exponent`。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FoodSpread {
+    /// 大きいほど中心付近に急激に集まる
+    pub exponent: f32,
+    /// 餌が湧く範囲の広さ（中心から角までの距離に対する割合）。小さいほど狭い
+    pub radius_scale: f32,
+}
+
+/// 餌が湧く長方形の領域と、そこでのルール
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoodZone {
+    /// 左上の座標
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// 湧くチャンスが来た時の基本確率
+    pub spawn_probability: f32,
+    /// 1個食べた時の回復量
+    pub food_energy: u32,
+    pub distribution: FoodDistribution,
+}
+
+impl FoodZone {
+    /// フィールド全体を覆うゾーン（今までの餌の湧き方）
+    pub fn whole_world() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: WIDTH,
+            height: HEIGHT,
+            spawn_probability: 0.2,
+            food_energy: FOOD_ENERGY,
+            distribution: FoodDistribution::Centered,
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x)
+            && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// 地形の設定。デフォルトは全部平地（今まで通り）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainParams {
+    /// シードからランダムに散らす泥の塊の数
+    pub mud_patches: usize,
+    /// シードからランダムに散らす荒地の塊の数
+    pub rough_patches: usize,
+    /// 塊の半径
+    pub patch_radius: usize,
+    /// 手で置く地形。ランダムな塊より優先で、後に書いたものが上書きする
+    pub zones: Vec<TerrainZone>,
+    /// 泥のマスに入るときに余分にかかるエネルギー
+    pub mud_cost: u32,
+    /// 荒地のマスに入るときに余分にかかるエネルギー
+    pub rough_cost: u32,
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        Self {
+            mud_patches: 0,
+            rough_patches: 0,
+            patch_radius: 4,
+            zones: Vec::new(),
+            mud_cost: 3,
+            rough_cost: 1,
+        }
+    }
+}
+
+/// 地形を置く長方形の領域
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainZone {
+    /// 左上の座標
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub terrain: Terrain,
+}
+
+/// ゾーンの中での餌の分布
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoodDistribution {
+    /// ゾーンの中心に近いほど湧きやすい（季節ごとの `FoodSpread` に従う）
+    Centered,
+    /// ゾーンの中ならどこでも同じ確率
+    Uniform,
+}
+
+impl Params {
+    /// TOMLの設定ファイルを読み込む
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// (x, y) が入っているゾーン
+    pub fn food_zone_at(&self, x: usize, y: usize) -> Option<&FoodZone> {
+        self.food_zones.iter().find(|zone| zone.contains(x, y))
+    }
+
+    /// 脳の形（入力, 隠れ層, 出力 の数）
+    pub fn brain_shape(&self) -> (usize, usize, usize) {
+        (self.input_size(), HIDDEN_SIZE, self.output_layout().size())
+    }
+
+    /// 脳の入力の数
+    pub fn input_size(&self) -> usize {
+        if self.self_color_input {
+            INPUT_SIZE + SELF_COLOR_SIZE
+        } else {
+            INPUT_SIZE
+        }
+    }
+
+    /// 脳の出力の並び。自分で食べる設定なら、行動の最後に `Action::Eat` が増える
+    pub fn output_layout(&self) -> OutputLayout {
+        match self.eat_mode {
+            EatMode::Auto => OUTPUT_LAYOUT,
+            EatMode::Active => OutputLayout::new(
+                OUTPUT_LAYOUT.actions + 1,
+                OUTPUT_LAYOUT.color,
+                OUTPUT_LAYOUT.signals,
+            ),
+        }
+    }
+
+    /// (x, y) の餌を1個食べたときの回復量。その場所のゾーンで決まる
+    pub fn food_value_at(&self, x: usize, y: usize) -> u32 {
+        self.food_zone_at(x, y)
+            .map_or(FOOD_ENERGY, |zone| zone.food_energy)
+    }
+
+    /// 今の個体数での餌の上限
+    pub fn food_cap(&self, population: usize) -> usize {
+        let Some(dynamic) = self.dynamic_food_cap else {
+            return self.max_foods;
+        };
+        if dynamic.low_population == 0 || population >= dynamic.low_population {
+            return self.max_foods;
+        }
+
+        // 個体数が減るほど直線的に上がる
+        let shortage = 1.0 - population as f32 / dynamic.low_population as f32;
+        let boost = 1.0 + (dynamic.max_boost - 1.0) * shortage;
+        (self.max_foods as f32 * boost).round() as usize
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            init_energy: INIT_ENERGY,
+            interact_radius: 1,
+            reproduce_radius: 1,
+            birth_placement: BirthPlacement::Random,
+            reproduce_threshold: ReproduceThreshold::Full,
+            summer_food_spread: FoodSpread {
+                exponent: 2.0,
+                radius_scale: 1.0,
+            },
+            winter_food_spread: FoodSpread {
+                exponent: 3.0,
+                radius_scale: 0.8,
+            },
+            stay_bias: 0.0,
+            output_bias_init: OutputBiasInit::Zero,
+            relu_slope: 0.0,
+            founder_colors: FounderColors::Random,
+            mutation: MutationSchedule::Constant(MutationRate::default()),
+            perception: Perception::All,
+            self_color_input: false,
+            vision_occlusion: false,
+            attack_cooldown: 0,
+            attack_recoil: 0,
+            attack_targeting: InteractTargeting::All,
+            heal_targeting: InteractTargeting::All,
+            heal_mode: HealMode::Free,
+            attack_damage: ATTACK_AMOUNT.unsigned_abs(),
+            attack_gain: AttackGain::Fraction(0.8),
+            armor: None,
+            attack_scaling: AttackScaling::Flat,
+            action_costs: ActionCosts::default(),
+            disabled_actions: ActionMask::ALL,
+            stay_metabolism_discount: 0,
+            death_energy_threshold: 0,
+            death_grace_steps: 0,
+            wall_bump: WallBump::Pay,
+            eat_mode: EatMode::Auto,
+            sprint_distance: 2,
+            max_agents_per_cell: 1,
+            gift_fraction: 0.5,
+            food_zones: vec![FoodZone::whole_world()],
+            terrain: TerrainParams::default(),
+            max_foods: MAX_FOODS,
+            dynamic_food_cap: None,
+            food_spawn_target: None,
+            food_spawn_max_misses: None,
+            corpse_drop: None,
+            reserve: ReserveParams::default(),
+            max_population: None,
+            birth_budget: None,
+            charge_at_capacity: true,
+            initial_placement: Placement::Uniform,
+            on_extinction: ExtinctionPolicy::Continue,
+            energy_smoothing: 0.05,
+            update_order: UpdateOrder::LowestEnergyFirst,
+            threads: 1,
+            convergence: ConvergenceCheck::default(),
+            stagnation: StagnationCheck::default(),
+            roles: RoleParams::default(),
+            strategy: StrategyThresholds::default(),
+            generation_band_width: 10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn food_cap_is_fixed_without_dynamic_cap() {
+        let params = Params::default();
+        assert_eq!(params.food_cap(0), MAX_FOODS);
+        assert_eq!(params.food_cap(1000), MAX_FOODS);
+    }
+
+    #[test]
+    fn dynamic_food_cap_rises_as_population_falls() {
+        let params = Params {
+            max_foods: 100,
+            dynamic_food_cap: Some(DynamicFoodCap {
+                low_population: 50,
+                max_boost: 3.0,
+            }),
+            ..Params::default()
+        };
+        assert_eq!(params.food_cap(80), 100);
+        assert_eq!(params.food_cap(50), 100);
+        assert_eq!(params.food_cap(25), 200);
+        assert_eq!(params.food_cap(0), 300);
+        let caps: Vec<usize> = (0..=50).rev().map(|n| params.food_cap(n)).collect();
+        assert!(caps.is_sorted());
+    }
+
+    #[test]
+    fn reproduce_thresholds() {
+        assert!(ReproduceThreshold::Full.reached(100, 100));
+        assert!(!ReproduceThreshold::Full.reached(99, 100));
+
+        let fraction = ReproduceThreshold::Fraction(0.8);
+        assert!(fraction.reached(80, 100));
+        assert!(!fraction.reached(79, 100));
+        // 半端なら切り上げ
+        assert!(!fraction.reached(7, 9) && fraction.reached(8, 9));
+
+        // 最大より上の値は満タンと同じ
+        assert!(ReproduceThreshold::Absolute(150).reached(100, 100));
+        assert!(ReproduceThreshold::Absolute(60).reached(60, 100));
+        assert!(!ReproduceThreshold::Absolute(60).reached(59, 100));
+    }
+
+    #[test]
+    fn constant_mutation_matches_the_old_fixed_rate() {
+        let schedule = Params::default().mutation;
+        for step in [0, 1000, 1_000_000] {
+            assert_eq!(
+                schedule.at(step),
+                MutationRate {
+                    rate: 1.0,
+                    sigma: 0.2
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn decay_schedules_change_sigma_over_steps() {
+        let linear = MutationSchedule::LinearDecay {
+            rate: 0.5,
+            start_sigma: 0.4,
+            end_sigma: 0.1,
+            steps: 1000,
+        };
+        let sigma = |step| linear.at(step).sigma;
+        assert_eq!(sigma(0), 0.4);
+        assert!((sigma(500) - 0.25).abs() < 1e-6);
+        assert!((sigma(1000) - 0.1).abs() < 1e-6);
+        assert!((sigma(5000) - 0.1).abs() < 1e-6);
+        assert_eq!(linear.at(500).rate, 0.5);
+
+        let stepped = MutationSchedule::StepDecay {
+            rate: 1.0,
+            sigma: 0.4,
+            factor: 0.5,
+            every: 100,
+        };
+        let sigma = |step| stepped.at(step).sigma;
+        assert_eq!(sigma(0), 0.4);
+        assert_eq!(sigma(99), 0.4);
+        assert_eq!(sigma(100), 0.2);
+        assert_eq!(sigma(250), 0.1);
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{self, Read, Write};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+/// 世界で使う乱数生成器。
+/// `WorldRng` の中身と同じアルゴリズムだけど、状態を取り出して保存できる。
+pub type WorldRng = ChaCha12Rng;
+
+/// 乱数の用途。
+/// 用途ごとに別々の乱数列を使うので、新しい機能で乱数を使う回数が変わっても
+/// 他の用途の乱数列はズレない（再現性が保たれる）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngStream {
+    /// 餌の湧き
+    Food,
+    /// 脳や体質の生成・突然変異、寿命
+    Mutation,
+    /// 個体を置く場所
+    Placement,
+}
+
+impl RngStream {
+    pub const ALL: [RngStream; 3] =
+        [RngStream::Food, RngStream::Mutation, RngStream::Placement];
+}
+
+/// 用途ごとの乱数列のセット。
+/// それぞれのシードはマスターシードから決定的に作る。
+#[derive(Debug, Clone)]
+pub struct RngStreams {
+    food: WorldRng,
+    mutation: WorldRng,
+    placement: WorldRng,
+}
+
+impl RngStreams {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            food: WorldRng::seed_from_u64(stream_seed(seed, RngStream::Food)),
+            mutation: WorldRng::seed_from_u64(stream_seed(seed, RngStream::Mutation)),
+            placement: WorldRng::seed_from_u64(stream_seed(seed, RngStream::Placement)),
+        }
+    }
+
+    pub fn get(&self, stream: RngStream) -> &WorldRng {
+        match stream {
+            RngStream::Food => &self.food,
+            RngStream::Mutation => &self.mutation,
+            RngStream::Placement => &self.placement,
+        }
+    }
+
+    pub fn get_mut(&mut self, stream: RngStream) -> &mut WorldRng {
+        match stream {
+            RngStream::Food => &mut self.food,
+            RngStream::Mutation => &mut self.mutation,
+            RngStream::Placement => &mut self.placement,
+        }
+    }
+}
+
+impl RngStreams {
+    /// 全部の乱数列の状態を書き出す
+    /// （`RngStream::ALL` の順に、シード 32バイト、ストリーム u64、位置 u128、リトルエンディアン）
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for stream in RngStream::ALL {
+            let rng = self.get(stream);
+            w.write_all(&rng.get_seed())?;
+            w.write_all(&rng.get_stream().to_le_bytes())?;
+            w.write_all(&rng.get_word_pos().to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// `write_to` で書いた状態を読み込む
+    pub(crate) fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut rngs = Self::new(0);
+        for stream in RngStream::ALL {
+            let mut seed = [0; 32];
+            r.read_exact(&mut seed)?;
+            let mut stream_id = [0; 8];
+            r.read_exact(&mut stream_id)?;
+            let mut word_pos = [0; 16];
+            r.read_exact(&mut word_pos)?;
+
+            let rng = rngs.get_mut(stream);
+            *rng = WorldRng::from_seed(seed);
+            rng.set_stream(u64::from_le_bytes(stream_id));
+            rng.set_word_pos(u128::from_le_bytes(word_pos));
+        }
+        Ok(rngs)
+    }
+}
+
+/// 乱数列の状態とステップ数だけのチェックポイント（`World::rng_checkpoint` で作る）。
+/// ステップの途中で取ったときのために、そのステップで `shuffle_deterministic` を
+/// 何回呼んだかも持っておく。
+///
+/// 世界全体のスナップショットよりずっと軽いけど、これだけでは世界は戻らない。
+/// 同じ途中から何度もやり直すには、チェックポイントを取ったときと同じ世界の状態
+/// （スナップショットを読み込んだもの、など）に `World::restore_rng` する必要がある。
+#[derive(Debug, Clone)]
+pub struct RngCheckpoint {
+    pub step: u64,
+    shuffles_this_step: u64,
+    streams: RngStreams,
+}
+
+impl RngCheckpoint {
+    pub(crate) fn new(step: u64, shuffles_this_step: u64, streams: RngStreams) -> Self {
+        Self {
+            step,
+            shuffles_this_step,
+            streams,
+        }
+    }
+
+    pub(crate) fn shuffles_this_step(&self) -> u64 {
+        self.shuffles_this_step
+    }
+
+    pub(crate) fn streams(&self) -> &RngStreams {
+        &self.streams
+    }
+
+    /// バイナリで書き出す（ステップ u64、そのステップで混ぜた回数 u64、乱数列の状態）
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.step.to_le_bytes())?;
+        w.write_all(&self.shuffles_this_step.to_le_bytes())?;
+        self.streams.write_to(w)
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut step = [0; 8];
+        r.read_exact(&mut step)?;
+        let mut shuffles_this_step = [0; 8];
+        r.read_exact(&mut shuffles_this_step)?;
+        Ok(Self {
+            step: u64::from_le_bytes(step),
+            shuffles_this_step: u64::from_le_bytes(shuffles_this_step),
+            streams: RngStreams::read_from(r)?,
+        })
+    }
+}
+
+/// マスターシードと用途から、その用途のシードを作る（SplitMix64）
+fn stream_seed(seed: u64, stream: RngStream) -> u64 {
+    splitmix(seed ^ (stream as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// マスターシード・ステップ・そのステップで何回目か、だけで決まる使い捨ての乱数。
+/// 状態を持ち越さないので、保存しなくても読み込み直したあとで同じ値になるし、
+/// 使う回数が変わっても `RngStreams` の乱数列はズレない
+pub fn keyed_rng(seed: u64, step: u64, index: u64) -> WorldRng {
+    let key = splitmix(splitmix(seed ^ step.wrapping_mul(0x9E37_79B9_7F4A_7C15)) ^ index);
+    WorldRng::seed_from_u64(key)
+}
+
+fn splitmix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
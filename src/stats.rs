@@ -0,0 +1,352 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::AddAssign,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    agent::Action, event::StepEvent, params::StrategyThresholds, world::AgentId,
+};
+
+/// ある時点の世界の統計データ。
+/// 全体の傾向を見る用（README の「記録」を参照）。
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldStats {
+    /// 経過ターン数
+    pub step: u64,
+    /// 生存数
+    pub population: usize,
+    /// フィールドにある餌の総数
+    pub food_count: usize,
+    /// フィールドにある餌の回復量の合計（ゾーンごとの栄養の違いを反映したもの）
+    pub food_energy: u64,
+    /// 直前のステップで食べられた餌の数
+    pub food_eaten: usize,
+    /// いま生きている中で最大の世代数
+    pub max_generation: u32,
+    /// 全員の平均エネルギー
+    pub avg_energy: f32,
+    /// 全員の平均の鎧（誰もいなければ 0）
+    pub avg_armor: f32,
+    /// 平均エネルギーの指数移動平均（`Params::energy_smoothing` でなめらかさを変えられる）
+    pub smoothed_energy: f32,
+    /// これまでの最大の生存数
+    pub peak_population: usize,
+    /// 最大の生存数になったステップ
+    pub peak_step: u64,
+    /// 全滅したステップ（生き残っていれば None）
+    pub extinction_step: Option<u64>,
+    /// 捕食者に分類された個体数（`Agent::role` を参照）
+    pub predators: usize,
+    /// 被食者（餌を食べて暮らす個体）に分類された個体数
+    pub prey: usize,
+    /// 違う脳（`Brain::fingerprint` が違うもの）の数
+    pub distinct_genomes: usize,
+    /// 一番多い脳が生存数に占める割合（誰もいなければ 0）。1 に近いほど同じ脳ばかり
+    pub dominant_genome_share: f32,
+    /// 直前のステップの処理ごとの時間（`timings` フィーチャーがないときは None）
+    pub timings: Option<StepTimings>,
+    /// 直前のステップで、それぞれの行動をとった個体の数
+    pub action_tally: ActionTally,
+    /// 直前のステップの行動の内訳から決めた、群れ全体の戦略（誰も動いていなければ None）
+    pub strategy: Option<Strategy>,
+    /// 世代の帯（`Params::generation_band_width` 世代ずつ）ごとの生存数。古い順
+    pub generation_bands: Vec<GenerationBand>,
+}
+
+/// 世代の帯（`first`〜`last` 世代）にいる生存数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GenerationBand {
+    pub first: u32,
+    pub last: u32,
+    pub count: usize,
+}
+
+impl GenerationBand {
+    /// 世代を `width` 世代ずつの帯（1〜width, width+1〜2*width, ...）に分けて数える。
+    /// 誰もいない帯は入れない。古い順に並ぶ
+    pub fn tally(
+        generations: impl Iterator<Item = u32>,
+        width: u32,
+    ) -> Vec<GenerationBand> {
+        let width = width.max(1);
+        let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+        for generation in generations {
+            *counts
+                .entry(generation.saturating_sub(1) / width)
+                .or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(band, count)| GenerationBand {
+                first: band * width + 1,
+                last: band * width + width,
+                count,
+            })
+            .collect()
+    }
+}
+
+/// 1ステップの間に、それぞれの行動が何回とられたか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ActionTally {
+    /// `Action` の値がインデックス
+    pub counts: [usize; Action::ALL.len()],
+}
+
+impl ActionTally {
+    pub fn add(&mut self, action: Action) {
+        self.counts[action as usize] += 1;
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.counts[action as usize]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// `actions` のどれかだった割合（何もなければ 0）
+    pub fn share(&self, actions: &[Action]) -> f32 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        actions.iter().map(|&a| self.count(a)).sum::<usize>() as f32 / total as f32
+    }
+}
+
+/// 群れ全体の戦略の見出し
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Strategy {
+    /// 攻撃が多い
+    Predators,
+    /// 回復や贈り物が多い
+    Altruists,
+    /// 動き回って餌を探している
+    Foragers,
+    /// じっとしている
+    Idlers,
+    /// どれとも言えない
+    Mixed,
+}
+
+impl Strategy {
+    /// 行動の内訳から戦略を決める（決め方は `StrategyThresholds` を参照）。
+    /// 誰も行動していなければ None
+    pub fn classify(
+        tally: &ActionTally,
+        thresholds: &StrategyThresholds,
+    ) -> Option<Self> {
+        if tally.total() == 0 {
+            return None;
+        }
+        let checks = [
+            (
+                Strategy::Predators,
+                thresholds.predators,
+                &[Action::Attack][..],
+            ),
+            (
+                Strategy::Altruists,
+                thresholds.altruists,
+                &[Action::Heal, Action::Gift],
+            ),
+            (
+                Strategy::Foragers,
+                thresholds.foragers,
+                &[
+                    Action::Up,
+                    Action::Down,
+                    Action::Left,
+                    Action::Right,
+                    Action::Sprint,
+                    Action::Eat,
+                ],
+            ),
+            (
+                Strategy::Idlers,
+                thresholds.idlers,
+                &[Action::Stay, Action::Rest],
+            ),
+        ];
+        let strategy = checks
+            .into_iter()
+            .find(|&(_, threshold, actions)| tally.share(actions) >= threshold)
+            .map_or(Strategy::Mixed, |(strategy, _, _)| strategy);
+        Some(strategy)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Strategy::Predators => "Predators",
+            Strategy::Altruists => "Altruists",
+            Strategy::Foragers => "Foragers",
+            Strategy::Idlers => "Idlers",
+            Strategy::Mixed => "Mixed",
+        }
+    }
+}
+
+/// `step()` の中の処理ごとにかかった時間。
+/// 個体数が多いときにどこが重いのかを、当て推量じゃなく測って見る用
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct StepTimings {
+    /// 死んだ個体を取り除く
+    pub deaths: Duration,
+    /// 餌を湧かせる
+    pub food_spawn: Duration,
+    /// 視界を作って脳を計算する（並列モードの先読みも含む）
+    pub decide: Duration,
+    /// 行動を適用する
+    pub apply: Duration,
+    /// 子供を産む
+    pub reproduce: Duration,
+}
+
+impl StepTimings {
+    pub fn total(&self) -> Duration {
+        self.deaths + self.food_spawn + self.decide + self.apply + self.reproduce
+    }
+}
+
+impl AddAssign for StepTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.deaths += other.deaths;
+        self.food_spawn += other.food_spawn;
+        self.decide += other.decide;
+        self.apply += other.apply;
+        self.reproduce += other.reproduce;
+    }
+}
+
+/// 最近の出生と死亡のペース（直近 `window` ステップの平均）。
+/// 個体数が同じでも、入れ替わりが激しいのか、ほとんど動いていないのかを見分ける用
+#[derive(Debug, Clone)]
+pub struct Churn {
+    window: usize,
+    /// ステップごとの (出生数, 死亡数)。古い順
+    history: VecDeque<(usize, usize)>,
+}
+
+impl Churn {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            history: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// 1ステップ分の出来事から、生まれた数と死んだ数を数えて足す
+    pub fn record(&mut self, events: &[StepEvent]) {
+        let births = events
+            .iter()
+            .filter(|e| matches!(e, StepEvent::Birth { .. }))
+            .count();
+        let deaths = events
+            .iter()
+            .filter(|e| matches!(e, StepEvent::Death { .. }))
+            .count();
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back((births, deaths));
+    }
+
+    /// 1ステップあたりの出生数（まだ何も記録していなければ 0）
+    pub fn birth_rate(&self) -> f32 {
+        self.average(|&(births, _)| births)
+    }
+
+    /// 1ステップあたりの死亡数
+    pub fn death_rate(&self) -> f32 {
+        self.average(|&(_, deaths)| deaths)
+    }
+
+    /// 1ステップあたりの増減（出生 - 死亡）
+    pub fn net_rate(&self) -> f32 {
+        self.birth_rate() - self.death_rate()
+    }
+
+    fn average(&self, count: impl Fn(&(usize, usize)) -> usize) -> f32 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        self.history.iter().map(count).sum::<usize>() as f32 / self.history.len() as f32
+    }
+}
+
+/// 家系（同じ最初の個体から続く子孫）のまとめ。
+/// 1つの家系が乗っ取ったのか、いろんな家系が残っているのかを見る用
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineageStats {
+    /// 生き残っている家系の数
+    pub surviving_lineages: usize,
+    /// 一番深い家系の長さ（生きている個体の最大の世代数）
+    pub deepest_lineage: u32,
+    /// 一番多い家系の始まりの個体のID（誰もいなければ None）
+    pub dominant_founder: Option<AgentId>,
+    /// 一番多い家系が生存数に占める割合
+    pub dominant_share: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::DeathCause,
+        world::{Position, small_params, test_world},
+    };
+
+    fn births_and_deaths(births: usize, deaths: usize) -> Vec<StepEvent> {
+        let pos = Position { x: 0, y: 0 };
+        let birth = (0..births).map(|i| StepEvent::Birth {
+            parent: 0,
+            child: i as AgentId + 1,
+            pos,
+        });
+        let death = (0..deaths).map(|i| StepEvent::Death {
+            id: i as AgentId + 100,
+            pos,
+            cause: DeathCause::Starvation,
+        });
+        birth.chain(death).collect()
+    }
+
+    #[test]
+    fn churn_averages_over_the_last_window() {
+        let mut churn = Churn::new(2);
+        assert_eq!(churn.birth_rate(), 0.0);
+
+        churn.record(&births_and_deaths(2, 1));
+        churn.record(&births_and_deaths(0, 1));
+        assert_eq!((churn.birth_rate(), churn.death_rate()), (1.0, 1.0));
+        assert_eq!(churn.net_rate(), 0.0);
+
+        // 窓からあふれた一番古いステップは数えない
+        churn.record(&births_and_deaths(4, 0));
+        assert_eq!((churn.birth_rate(), churn.death_rate()), (2.0, 0.5));
+        assert_eq!(churn.net_rate(), 1.5);
+    }
+
+    #[test]
+    fn churn_matches_the_population_change() {
+        let mut world = test_world(3, small_params());
+        let start = world.agents.len();
+
+        const STEPS: usize = 200;
+        let mut churn = Churn::new(STEPS);
+        for _ in 0..STEPS {
+            world.step();
+            churn.record(&world.events);
+        }
+        let births = (churn.birth_rate() * STEPS as f32).round() as usize;
+        let deaths = (churn.death_rate() * STEPS as f32).round() as usize;
+        assert!(births > 0 && deaths > 0, "births {births}, deaths {deaths}");
+        assert_eq!(start + births - deaths, world.agents.len());
+    }
+}
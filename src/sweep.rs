@@ -0,0 +1,145 @@
+//! 同じ設定を何個ものシードで回して、結果をまとめるモード。
+//! 1回の実行だと運に左右されるので、バランスの話をするときはこっちを使う。
+
+use std::{ops::RangeInclusive, str::FromStr, thread};
+
+use rikulife::{params::Params, stats::WorldStats};
+
+use crate::create_world;
+
+/// `--seeds` で渡すシードの範囲。
+/// `1..50`（50は含まない）、`1..=50`（50を含む）、`7`（1個だけ）のどれかで書く
+#[derive(Debug, Clone)]
+pub struct SeedRange(RangeInclusive<u64>);
+
+impl FromStr for SeedRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u64>()
+                .map_err(|e| format!("invalid seed {n:?}: {e}"))
+        };
+
+        let range = if let Some((start, end)) = s.split_once("..=") {
+            parse(start)?..=parse(end)?
+        } else if let Some((start, end)) = s.split_once("..") {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if end == 0 {
+                return Err(format!("empty seed range {s:?}"));
+            }
+            start..=end - 1
+        } else {
+            let seed = parse(s)?;
+            seed..=seed
+        };
+
+        if range.is_empty() {
+            return Err(format!("empty seed range {s:?}"));
+        }
+        Ok(Self(range))
+    }
+}
+
+/// 各シードで `steps` ステップ回して、最後の統計の平均などを表にして出す
+pub fn run(seeds: &SeedRange, steps: u64, params: &Params) {
+    let seeds: Vec<u64> = seeds.0.clone().collect();
+
+    // シードごとに別々の世界なので、CPUの数だけスレッドに分けて回す
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(seeds.len());
+    let chunk_size = seeds.len().div_ceil(threads);
+    let results: Vec<WorldStats> = thread::scope(|scope| {
+        let handles: Vec<_> = seeds
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&seed| {
+                            let mut world = create_world(seed, params.clone());
+                            world.run(steps as usize)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("seed worker panicked"))
+            .collect()
+    });
+
+    print_table(&results, steps);
+}
+
+/// 統計から1つの指標を取り出す関数
+type Metric = fn(&WorldStats) -> f64;
+
+/// 表の1行に出す指標の名前と、統計からの取り出し方
+const METRICS: [(&str, Metric); 8] = [
+    ("population", |s| s.population as f64),
+    ("peak_population", |s| s.peak_population as f64),
+    ("peak_step", |s| s.peak_step as f64),
+    ("max_generation", |s| s.max_generation as f64),
+    ("avg_energy", |s| s.avg_energy as f64),
+    ("food_count", |s| s.food_count as f64),
+    ("predators", |s| s.predators as f64),
+    ("prey", |s| s.prey as f64),
+];
+
+fn print_table(results: &[WorldStats], steps: u64) {
+    let survived = results.iter().filter(|s| s.population > 0).count();
+    println!("Seeds: {} | Steps: {steps}", results.len());
+    println!(
+        "Survival: {survived}/{} ({:.1}%)",
+        results.len(),
+        survived as f64 / results.len() as f64 * 100.0
+    );
+    println!();
+    println!(
+        "| {:<16} | {:>10} | {:>10} | {:>10} | {:>10} |",
+        "metric", "mean", "std", "min", "max"
+    );
+    println!(
+        "|{:-<18}|{:->12}|{:->12}|{:->12}|{:->12}|",
+        "", "", "", "", ""
+    );
+    for (name, get) in METRICS {
+        let values: Vec<f64> = results.iter().map(get).collect();
+        let summary = Summary::of(&values);
+        println!(
+            "| {name:<16} | {:>10.2} | {:>10.2} | {:>10.2} | {:>10.2} |",
+            summary.mean, summary.std, summary.min, summary.max
+        );
+    }
+}
+
+/// 1つの指標の要約
+struct Summary {
+    mean: f64,
+    /// 標本標準偏差（シードが1個なら 0）
+    std: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Summary {
+    fn of(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = if values.len() > 1 {
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            std: variance.sqrt(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! マスごとの地形。泥や荒地は入るのにエネルギーが余分にかかる。
+//! 壁は通れない（フィールドの外と同じ扱い）。
+
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    params::TerrainParams,
+    rng::WorldRng,
+    world::{HEIGHT, WIDTH},
+};
+
+/// 地形の生成用の乱数のシードに混ぜる値（他の乱数列と被らないように）
+const TERRAIN_SEED_SALT: u64 = 0x7465_7272_6169_6e00;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Terrain {
+    /// 平地。余分なコストなし
+    #[default]
+    Plains,
+    /// 泥。すごく疲れる（自然な関所や隠れ家になる）
+    Mud,
+    /// 荒地。少し疲れる
+    Rough,
+    /// 壁。入れないし、向こうも見えない（視界では範囲外と同じく壁に見える）。
+    /// 餌も湧かない
+    Wall,
+}
+
+impl Terrain {
+    /// 全部の地形（保存するときの番号の順）
+    pub const ALL: [Terrain; 4] =
+        [Terrain::Plains, Terrain::Mud, Terrain::Rough, Terrain::Wall];
+
+    /// このマスに入るときに、移動コストとは別にかかるエネルギー
+    pub fn extra_cost(self, params: &TerrainParams) -> u32 {
+        match self {
+            Terrain::Plains => 0,
+            Terrain::Mud => params.mud_cost,
+            Terrain::Rough => params.rough_cost,
+            Terrain::Wall => 0,
+        }
+    }
+
+    /// 個体が入れるか
+    pub fn is_passable(self) -> bool {
+        self != Terrain::Wall
+    }
+}
+
+/// シードと設定から地形を作る。同じシード・設定なら必ず同じ地形になる。
+/// ランダムな塊を散らしてから、設定ファイルのゾーンで上書きする
+pub fn generate(seed: u64, params: &TerrainParams) -> Vec<Vec<Terrain>> {
+    let mut map = vec![vec![Terrain::Plains; WIDTH]; HEIGHT];
+
+    // 他の乱数列には触らないように、地形だけの乱数を使う
+    let mut rng = WorldRng::seed_from_u64(seed ^ TERRAIN_SEED_SALT);
+    let patches = [
+        (Terrain::Mud, params.mud_patches),
+        (Terrain::Rough, params.rough_patches),
+    ];
+    for (terrain, count) in patches {
+        for _ in 0..count {
+            let cx = rng.random_range(0..WIDTH) as isize;
+            let cy = rng.random_range(0..HEIGHT) as isize;
+            let r = params.patch_radius as isize;
+
+            // 円形に塗る
+            for y in (cy - r).max(0)..=(cy + r).min(HEIGHT as isize - 1) {
+                for x in (cx - r).max(0)..=(cx + r).min(WIDTH as isize - 1) {
+                    if (x - cx).pow(2) + (y - cy).pow(2) <= r * r {
+                        map[y as usize][x as usize] = terrain;
+                    }
+                }
+            }
+        }
+    }
+
+    for zone in &params.zones {
+        for row in map.iter_mut().skip(zone.y).take(zone.height) {
+            for cell in row.iter_mut().skip(zone.x).take(zone.width) {
+                *cell = zone.terrain;
+            }
+        }
+    }
+
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::TerrainZone;
+
+    #[test]
+    fn default_terrain_is_all_plains() {
+        let map = generate(1, &TerrainParams::default());
+        assert!(map.iter().flatten().all(|&t| t == Terrain::Plains));
+    }
+
+    #[test]
+    fn patches_follow_the_seed_and_zones_win() {
+        let params = TerrainParams {
+            mud_patches: 3,
+            rough_patches: 3,
+            zones: vec![TerrainZone {
+                x: 0,
+                y: 0,
+                width: 5,
+                height: 2,
+                terrain: Terrain::Wall,
+            }],
+            ..TerrainParams::default()
+        };
+        let map = generate(7, &params);
+        assert_eq!(map, generate(7, &params));
+        assert_ne!(map, generate(8, &params));
+        assert!(map.iter().flatten().any(|&t| t == Terrain::Mud));
+        assert!(map.iter().flatten().any(|&t| t == Terrain::Rough));
+
+        let walls: Vec<(usize, usize)> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| map[y][x] == Terrain::Wall)
+            .collect();
+        assert_eq!(walls.len(), 10);
+        assert!(walls.iter().all(|&(x, y)| x < 5 && y < 2));
+    }
+}
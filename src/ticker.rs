@@ -0,0 +1,97 @@
+//! 情報パネルの下に流す、最近の出来事のログ。
+//! キャンバスを眺めているだけだと見逃すような、珍しい出来事を拾うため。
+
+use std::collections::VecDeque;
+
+use crate::{
+    event::{DeathCause, StepEvent},
+    world::{AgentId, World},
+};
+use clap::ValueEnum;
+
+/// ログに残しておく行数の上限（表示するのはパネルに入る分だけ）
+const TICKER_CAPACITY: usize = 64;
+
+/// ログに流す出来事の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TickerEvent {
+    Birth,
+    Death,
+    Kill,
+    Stagnation,
+}
+
+impl TickerEvent {
+    /// `event` がこの種類か（ログに関係ない出来事は None）
+    fn of(event: &StepEvent) -> Option<Self> {
+        match event {
+            StepEvent::Interaction { .. } | StepEvent::Eat { .. } => None,
+            StepEvent::Birth { .. } => Some(TickerEvent::Birth),
+            StepEvent::Death { .. } => Some(TickerEvent::Death),
+            StepEvent::Kill { .. } => Some(TickerEvent::Kill),
+            StepEvent::Stagnation { .. } => Some(TickerEvent::Stagnation),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    /// 古い順に並んだログの行
+    pub lines: VecDeque<String>,
+    /// 流す出来事の種類
+    filter: Vec<TickerEvent>,
+}
+
+impl Ticker {
+    pub fn new(filter: Vec<TickerEvent>) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(TICKER_CAPACITY),
+            filter,
+        }
+    }
+
+    /// 直前のステップで起きた出来事のうち、フィルタに合うものをログに足す
+    pub fn record(&mut self, world: &World) {
+        for event in &world.events {
+            if !TickerEvent::of(event).is_some_and(|kind| self.filter.contains(&kind)) {
+                continue;
+            }
+            if self.lines.len() == TICKER_CAPACITY {
+                self.lines.pop_front();
+            }
+            self.lines
+                .push_back(format!("{:>6} {}", world.step, describe(world, event)));
+        }
+    }
+}
+
+/// 出来事を1行の文にする
+fn describe(world: &World, event: &StepEvent) -> String {
+    // 死んだ個体はもう名前が分からないので、IDで出す
+    let name = |id: &AgentId| {
+        world
+            .agents
+            .get(id)
+            .map_or_else(|| format!("#{id}"), |a| a.name.clone())
+    };
+
+    match event {
+        StepEvent::Birth { parent, child, .. } => {
+            format!("+ {} born to {}", name(child), name(parent))
+        }
+        StepEvent::Death { id, cause, .. } => match cause {
+            DeathCause::Starvation => format!("x #{id} starved"),
+            DeathCause::OldAge => format!("x #{id} died of old age"),
+        },
+        StepEvent::Kill { killer, victim, .. } => {
+            format!("! {} killed {}", name(killer), name(victim))
+        }
+        StepEvent::Stagnation { activity } => {
+            format!("? stagnant: only {:.0}% moved", activity * 100.0)
+        }
+        StepEvent::Eat { id, gained, .. } => format!("{} ate (+{gained})", name(id)),
+        StepEvent::Interaction { actor, action, .. } => {
+            format!("{} used {action:?}", name(actor))
+        }
+    }
+}
@@ -0,0 +1,1169 @@
+//! TUI の描画。
+//! 端末の準備（raw モードなど）とは分けてあるので、ratatui の `TestBackend` にも描ける。
+//!
+//! ```
+//! use ratatui::{Terminal, backend::TestBackend};
+//! use rikulife::{ui::{self, ViewOptions}, world::World};
+//!
+//! let world = World::new(42);
+//! let view = ViewOptions::default();
+//! let mut terminal = Terminal::new(TestBackend::new(120, 60)).unwrap();
+//! terminal.draw(|f| ui::draw(f, &world, &view)).unwrap();
+//! let buffer = terminal.backend().buffer();
+//! # assert!(buffer.area.width == 120);
+//! ```
+
+use std::time::Duration;
+
+use clap::ValueEnum;
+use ratatui::{
+    prelude::*,
+    widgets::{
+        Block, Borders, Paragraph,
+        canvas::{self, Canvas, Rectangle},
+    },
+};
+
+use crate::{
+    agent::Action,
+    brain::InputLayout,
+    event::StepEvent,
+    stats::{Churn, GenerationBand, Strategy},
+    terrain::Terrain,
+    ticker::{Ticker, TickerEvent},
+    world::{AgentId, Position, Season, World},
+};
+
+/// エージェントの色の描き方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// 進化した色（RGB）そのまま
+    Full,
+    /// 色を明るさに変換して、濃淡の違うブロック文字で描く
+    Grayscale,
+    /// 世代で色分けする（古い世代は青、新しい世代は赤）
+    Generation,
+    /// 進化した色を、年を取るほど白や灰色に寄せる（age / lifespan の割合で）
+    Age,
+}
+
+impl ColorMode {
+    /// 'c' キーで次のモードへ
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Full => ColorMode::Grayscale,
+            ColorMode::Grayscale => ColorMode::Generation,
+            ColorMode::Generation => ColorMode::Age,
+            ColorMode::Age => ColorMode::Full,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorMode::Full => "Color",
+            ColorMode::Grayscale => "Grayscale",
+            ColorMode::Generation => "Generation",
+            ColorMode::Age => "Age",
+        }
+    }
+}
+
+/// 年齢モードで、年寄りの色を寄せていく先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AgeTint {
+    White,
+    Gray,
+}
+
+impl AgeTint {
+    fn rgb(self) -> [f32; 3] {
+        match self {
+            AgeTint::White => [1.0, 1.0, 1.0],
+            AgeTint::Gray => [0.5, 0.5, 0.5],
+        }
+    }
+}
+
+/// 描画まわりの設定
+#[derive(Debug, Clone)]
+pub struct ViewOptions {
+    pub color_mode: ColorMode,
+    /// インスペクタで見ているエージェント
+    pub selected: Option<AgentId>,
+    /// 手で殺したり餌をやったりするマス（矢印キーで動かす）。None なら出さない
+    pub cursor: Option<Position>,
+    /// 止めているか（止めている間は '.' で1ステップずつ進める）
+    pub paused: bool,
+    /// 1ステップ進める間隔（'+' / '-' で変える）
+    pub tick_rate: Duration,
+    /// インスペクタに、見ている個体の視界（脳への入力）を出すか
+    pub show_vision: bool,
+    /// 生まれたての個体に印をつけるか
+    pub show_births: bool,
+    /// 個体の向いている方向を矢印で描くか（混んでいるとごちゃごちゃするので、ふだんは描かない）
+    pub show_facing: bool,
+    /// 餌を周りの餌の多さで濃淡をつけて描くか
+    pub food_density: bool,
+    /// 最近の出来事のログ
+    pub ticker: Ticker,
+    /// 最近の出生と死亡のペース
+    pub churn: Churn,
+    /// フィールドの背景色（None なら端末のまま）
+    pub background: Option<Color>,
+    /// 格子を描くか
+    pub grid: bool,
+    /// 冬のあいだ、枠を寒い色にして雪の印を出すか（フィールドの中の色は変えない）
+    pub season_theme: bool,
+    /// 年齢モードで寄せていく先の色
+    pub age_tint: AgeTint,
+    /// 年齢モードで、寿命のときにどれだけ寄せるか（0.0〜1.0）
+    pub age_tint_strength: f32,
+}
+
+impl Default for ViewOptions {
+    /// コマンドラインで何も指定しなかったときと同じ見た目
+    fn default() -> Self {
+        Self {
+            color_mode: ColorMode::Full,
+            selected: None,
+            cursor: None,
+            paused: false,
+            tick_rate: DEFAULT_TICK_RATE,
+            show_vision: false,
+            show_births: true,
+            show_facing: false,
+            food_density: false,
+            ticker: Ticker::new(TickerEvent::value_variants().to_vec()),
+            churn: Churn::new(CHURN_WINDOW),
+            background: None,
+            grid: false,
+            season_theme: false,
+            age_tint: AgeTint::Gray,
+            age_tint_strength: 0.8,
+        }
+    }
+}
+
+// --- UI構築ロジック 🖼️ ---
+pub fn draw(f: &mut Frame, world: &World, view: &ViewOptions) {
+    // 画面を左右に分割
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(70), // 左70%: マップ
+            Constraint::Percentage(30), // 右30%: 情報
+        ])
+        .split(f.area());
+
+    // 冬の見た目にするか（枠とタイトルだけ変える）
+    let winter = view.season_theme && world.season() == Season::Winter;
+
+    // 世代の色分け用に、今いる世代の範囲を出しておく
+    let by_generation = world.agents_sorted_by(|a| a.generation);
+    let generation_of =
+        |id: Option<&AgentId>| id.map_or(0, |id| world.agents[id].generation);
+    let generation_range = (
+        generation_of(by_generation.first()),
+        generation_of(by_generation.last()),
+    );
+
+    // --- 1. 左側: 世界の描画 (Canvas) ---
+    // Canvasウィジェットを使うと、座標指定で矩形を描けるので便利！
+    let canvas = Canvas::default()
+        .block(themed_block(
+            if winter {
+                " Artificial Life ❄ "
+            } else {
+                " Artificial Life "
+            },
+            winter,
+        ))
+        .x_bounds([0.0, crate::world::WIDTH as f64])
+        .y_bounds([0.0, crate::world::HEIGHT as f64])
+        // Reset は端末の背景色のまま
+        .background_color(view.background.unwrap_or(Color::Reset))
+        .paint(|ctx| {
+            // 格子をうっすら描く（一番下に描く）
+            if view.grid {
+                draw_grid(ctx);
+            }
+
+            // 0. 餌のゾーンの境界をうっすら描く（フィールド全体のゾーンは枠と重なるので描かない）
+            for zone in &world.params.food_zones {
+                if zone.x == 0
+                    && zone.y == 0
+                    && zone.width >= crate::world::WIDTH
+                    && zone.height >= crate::world::HEIGHT
+                {
+                    continue;
+                }
+                ctx.draw(&Rectangle {
+                    x: zone.x as f64,
+                    y: crate::world::HEIGHT.saturating_sub(zone.y + zone.height) as f64,
+                    width: zone.width as f64,
+                    height: zone.height as f64,
+                    color: Color::DarkGray,
+                });
+            }
+            // 地形をうっすら描く（平地は何も描かない）
+            for (y, row) in world.terrain.iter().enumerate() {
+                for (x, &terrain) in row.iter().enumerate() {
+                    let (draw_x, draw_y) = calc_draw_position(Position { x, y });
+                    let color = match terrain {
+                        Terrain::Plains => continue,
+                        Terrain::Mud => Color::Rgb(70, 45, 20),
+                        Terrain::Rough => Color::Rgb(55, 55, 55),
+                        Terrain::Wall => Color::Gray,
+                    };
+                    match view.color_mode {
+                        ColorMode::Full | ColorMode::Generation | ColorMode::Age => ctx
+                            .draw(&Rectangle {
+                                x: draw_x,
+                                y: draw_y,
+                                width: 1.0,
+                                height: 1.0,
+                                color,
+                            }),
+                        ColorMode::Grayscale => ctx.print(
+                            draw_x,
+                            draw_y,
+                            match terrain {
+                                Terrain::Mud => "~",
+                                Terrain::Wall => "#",
+                                _ => "^",
+                            },
+                        ),
+                    }
+                }
+            }
+            ctx.layer();
+
+            // A. 餌を描画 (緑色の小さな点) 🍏
+            for y in 0..crate::world::HEIGHT {
+                for x in 0..crate::world::WIDTH {
+                    if world.foods[y][x] {
+                        let (draw_x, draw_y) = calc_draw_position(Position { x, y });
+                        // 濃淡モードなら、周りの餌の割合 (0.0~1.0)
+                        let density = view
+                            .food_density
+                            .then(|| food_density(world, Position { x, y }));
+                        match view.color_mode {
+                            ColorMode::Full | ColorMode::Generation | ColorMode::Age => {
+                                ctx.draw(&Rectangle {
+                                    x: draw_x,
+                                    y: draw_y,
+                                    width: 1.0,
+                                    height: 1.0,
+                                    color: density.map_or(Color::Green, |d| {
+                                        Color::Rgb(0, 60 + (d * 195.0) as u8, 0)
+                                    }),
+                                })
+                            }
+                            // 色に頼らず、記号で餌だと分かるようにする
+                            ColorMode::Grayscale => ctx.print(
+                                draw_x,
+                                draw_y,
+                                match density {
+                                    Some(d) if d >= 0.5 => "•",
+                                    _ => "·",
+                                },
+                            ),
+                        }
+                    }
+                }
+            }
+
+            // 攻撃・回復・贈り物が届いた相手へ、うっすら線を引く（エージェントの下に描く）
+            for event in &world.events {
+                let StepEvent::Interaction {
+                    action,
+                    from,
+                    targets,
+                    ..
+                } = event
+                else {
+                    continue;
+                };
+                let color = match action {
+                    Action::Attack => Color::Rgb(110, 30, 30),
+                    _ => Color::Rgb(30, 100, 40),
+                };
+                let (x1, y1) = calc_draw_position(*from);
+                for &target in targets {
+                    let (x2, y2) = calc_draw_position(target);
+                    ctx.draw(&canvas::Line {
+                        x1: x1 + 0.5,
+                        y1: y1 + 0.5,
+                        x2: x2 + 0.5,
+                        y2: y2 + 0.5,
+                        color,
+                    });
+                }
+            }
+
+            // B. エージェントを描画 (RGB色の四角形)
+            for agent in world.agents.values() {
+                let (draw_x, draw_y) = calc_draw_position(agent.pos);
+
+                match view.color_mode {
+                    ColorMode::Full => {
+                        ctx.draw(&Rectangle {
+                            x: draw_x,
+                            y: draw_y,
+                            width: 1.0,
+                            height: 1.0,
+                            color: rgb_color(agent.color),
+                        });
+                    }
+                    ColorMode::Grayscale => {
+                        ctx.print(draw_x, draw_y, shade_glyph(agent.color));
+                    }
+                    ColorMode::Generation => {
+                        ctx.draw(&Rectangle {
+                            x: draw_x,
+                            y: draw_y,
+                            width: 1.0,
+                            height: 1.0,
+                            color: generation_color(agent.generation, generation_range),
+                        });
+                    }
+                    ColorMode::Age => {
+                        let aged = age_ratio(agent.age, agent.lifespan);
+                        ctx.draw(&Rectangle {
+                            x: draw_x,
+                            y: draw_y,
+                            width: 1.0,
+                            height: 1.0,
+                            color: age_tinted(agent.color, aged, view),
+                        });
+                    }
+                }
+
+                // 生まれたばかりの子供には黄色い "*" を重ねる（最初の個体は除く）
+                let newborn = agent.generation > 1 && agent.age < BIRTH_MARKER_STEPS;
+                if view.show_births && newborn {
+                    ctx.print(
+                        draw_x,
+                        draw_y,
+                        Span::styled(
+                            "*",
+                            Style::default()
+                                .fg(Color::Yellow)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                    );
+                } else if let Some(action) = agent.last_action {
+                    match action {
+                        Action::Attack => {
+                            // 攻撃してる時は赤い "x" を重ねる
+                            ctx.print(
+                                draw_x,
+                                draw_y,
+                                Span::styled(
+                                    "x",
+                                    Style::default()
+                                        .fg(Color::LightBlue)
+                                        .add_modifier(Modifier::BOLD),
+                                ),
+                            );
+                        }
+                        Action::Heal => {
+                            // 回復してる時は緑の "+" を重ねる
+                            ctx.print(
+                                draw_x,
+                                draw_y,
+                                Span::styled(
+                                    "+",
+                                    Style::default()
+                                        .fg(Color::LightGreen)
+                                        .add_modifier(Modifier::BOLD),
+                                ),
+                            );
+                        }
+                        _ => {
+                            // 移動や待機の時は、向きの矢印だけ（描く設定なら）。
+                            // そうでなければ記号を出さずにRGBの色だけ見せる
+                            if view.show_facing {
+                                ctx.print(draw_x, draw_y, facing_span(agent.facing));
+                            }
+                        }
+                    }
+                } else if view.show_facing {
+                    // まだ何もしていない個体も、向きは持っている
+                    ctx.print(draw_x, draw_y, facing_span(agent.facing));
+                }
+            }
+
+            // インスペクタで見ている個体の視界を、うっすら枠で囲む
+            if let Some(agent) = view.selected.and_then(|id| world.agents.get(&id)) {
+                draw_vision_box(ctx, agent.pos);
+            }
+
+            // カーソルは一番上に描く
+            if let Some(cursor) = view.cursor {
+                let (x, y) = calc_draw_position(cursor);
+                ctx.draw(&Rectangle {
+                    x,
+                    y,
+                    width: 1.0,
+                    height: 1.0,
+                    color: CURSOR_COLOR,
+                });
+            }
+        });
+
+    f.render_widget(canvas, chunks[0]);
+
+    // --- 2. 右側: 統計情報 (Paragraph) と、その下に出来事のログ ---
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(TICKER_HEIGHT)])
+        .split(chunks[1]);
+    let stats = world.stats();
+
+    let mut info_text = vec![
+        Line::from(vec![Span::raw("Statistics 📊")]),
+        // 全滅していたら目立つように出す
+        match stats.extinction_step {
+            Some(step) if stats.population == 0 => Line::from(vec![Span::styled(
+                format!(" EXTINCT (step {step}) "),
+                Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Red)
+                    .add_modifier(Modifier::BOLD),
+            )]),
+            _ => Line::from(""),
+        },
+        Line::from(vec![
+            Span::raw(format!("Step: {} ", stats.step)),
+            if view.paused {
+                Span::styled(
+                    "⏸ PAUSED",
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )
+            } else {
+                Span::styled("▶ Running", Style::default().fg(Color::LightGreen))
+            },
+        ]),
+        Line::from(format!(
+            "Speed: {:.1} steps/s ({}ms)",
+            1.0 / view.tick_rate.as_secs_f64(),
+            view.tick_rate.as_secs_f64() * 1000.0
+        )),
+        Line::from(vec![match world.season() {
+            Season::Summer => {
+                Span::styled("Season: Summer ☀", Style::default().fg(Color::LightRed))
+            }
+            Season::Winter => {
+                Span::styled("Season: Winter ❄", Style::default().fg(Color::LightCyan))
+            }
+        }]),
+        Line::from(vec![
+            Span::styled(
+                format!("Population: {}", stats.population),
+                Style::default().fg(Color::Yellow),
+            ),
+            if world.at_capacity() {
+                Span::styled(" AT CAPACITY", Style::default().fg(Color::Red))
+            } else {
+                Span::raw("")
+            },
+        ]),
+        Line::from(vec![Span::raw(format!(
+            "Peak: {} (step {})",
+            stats.peak_population, stats.peak_step
+        ))]),
+        Line::from(vec![
+            Span::styled(
+                format!("Predators: {}", stats.predators),
+                Style::default().fg(Color::LightRed),
+            ),
+            Span::raw(" : "),
+            Span::styled(
+                format!("Prey: {}", stats.prey),
+                Style::default().fg(Color::LightGreen),
+            ),
+        ]),
+        Line::from(format!(
+            "Strategy: {}",
+            stats.strategy.map_or("-", Strategy::label)
+        )),
+        // 1行だと情報パネルの幅に収まらないので、2行に分ける
+        Line::from(format!("Births/step: {:.2}", view.churn.birth_rate())),
+        Line::from(format!(
+            "Deaths/step: {:.2} (net {:+.2})",
+            view.churn.death_rate(),
+            view.churn.net_rate()
+        )),
+        Line::from(vec![Span::raw(format!(
+            "Max Generation: {}",
+            stats.max_generation
+        ))]),
+        Line::from(vec![Span::raw(format!(
+            "Avg Energy: {:.0} (smoothed {:.1})",
+            stats.avg_energy, stats.smoothed_energy
+        ))]),
+        // 鎧を使っていないときは、みんな 0 なので出さない
+        if world.params.armor.is_some() {
+            Line::from(format!("Avg Armor: {:.2}", stats.avg_armor))
+        } else {
+            Line::from("Avg Armor: -")
+        },
+        match world.genome_diversity {
+            Some(diversity) if world.converged => Line::from(vec![Span::styled(
+                format!("Diversity: {diversity:.2} (CONVERGED)"),
+                Style::default().fg(Color::Red),
+            )]),
+            Some(diversity) => Line::from(format!("Diversity: {diversity:.2}")),
+            None => Line::from("Diversity: -"),
+        },
+        Line::from(format!(
+            "Genomes: {} distinct (top {:.0}%)",
+            stats.distinct_genomes,
+            stats.dominant_genome_share * 100.0
+        )),
+        match world.activity {
+            Some(activity) if world.stagnant => Line::from(vec![Span::styled(
+                format!("Activity: {:.0}% (STAGNANT)", activity * 100.0),
+                Style::default().fg(Color::Red),
+            )]),
+            Some(activity) => Line::from(format!("Activity: {:.0}%", activity * 100.0)),
+            None => Line::from("Activity: -"),
+        },
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            format!(
+                "Food Count: {} (energy {})",
+                stats.food_count, stats.food_energy
+            ),
+            Style::default().fg(Color::Green),
+        )]),
+        Line::from(vec![Span::styled(
+            format!("Eaten this step: {}", stats.food_eaten),
+            Style::default().fg(Color::Green),
+        )]),
+        Line::from(""),
+        Line::from(format!("Mode: {}", view.color_mode.label())),
+        Line::from(""),
+    ];
+    info_text.extend(generation_band_lines(
+        &stats.generation_bands,
+        stats.population,
+    ));
+
+    // `timings` フィーチャー付きでビルドしたときだけ、直前のステップの内訳を出す
+    if let Some(timings) = stats.timings {
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        info_text.push(Line::from(format!(
+            "Step: {:.2}ms (decide {:.2} apply {:.2})",
+            ms(timings.total()),
+            ms(timings.decide),
+            ms(timings.apply)
+        )));
+        info_text.push(Line::from(format!(
+            "  deaths {:.2} food {:.2} repro {:.2}",
+            ms(timings.deaths),
+            ms(timings.food_spawn),
+            ms(timings.reproduce)
+        )));
+    }
+
+    // 世代モードのときは凡例を出す
+    if view.color_mode == ColorMode::Generation {
+        let (min, max) = generation_range;
+        let mut legend = vec![Span::raw(format!(" Gen {min} "))];
+        for i in 0..LEGEND_STEPS {
+            let generation = min + (max - min) * i / (LEGEND_STEPS - 1);
+            legend.push(Span::styled(
+                "█",
+                Style::default().fg(generation_color(generation, generation_range)),
+            ));
+        }
+        legend.push(Span::raw(format!(" {max}")));
+        info_text.push(Line::from(legend));
+    }
+
+    // 年齢モードのときは、赤い個体が年を取るとどうなるかを凡例に出す
+    if view.color_mode == ColorMode::Age {
+        let mut legend = vec![Span::raw(" Young ")];
+        for i in 0..LEGEND_STEPS {
+            let aged = i as f32 / (LEGEND_STEPS - 1) as f32;
+            legend.push(Span::styled(
+                "█",
+                Style::default().fg(age_tinted([1.0, 0.0, 0.0], aged, view)),
+            ));
+        }
+        legend.push(Span::raw(" Old"));
+        info_text.push(Line::from(legend));
+    }
+    info_text.push(Line::from(""));
+
+    // --- インスペクタ 🔍 ---
+    if let Some(agent) = view.selected.and_then(|id| world.agents.get(&id)) {
+        info_text.extend([
+            Line::from(vec![Span::styled(
+                format!("Inspector: {} (#{})", agent.name, agent.id),
+                Style::default().fg(Color::Cyan),
+            )]),
+            Line::from(format!(" Pos: ({}, {})", agent.pos.x, agent.pos.y)),
+            Line::from(format!(
+                " Energy: {}/{} (reserve {})",
+                agent.energy, agent.max_energy, agent.reserve
+            )),
+            Line::from(format!(" Generation: {}", agent.generation)),
+            Line::from(format!(" Age: {}/{}", agent.age, agent.lifespan)),
+            Line::from(format!(" Brain: {}", agent.brain().summary())),
+            Line::from(format!(
+                " History: {}",
+                agent
+                    .action_history
+                    .iter()
+                    .map(|&a| action_symbol(a))
+                    .collect::<String>()
+            )),
+        ]);
+        if view.show_vision {
+            info_text.push(Line::from(" Vision (# wall, * food, a agent):"));
+            info_text.extend(vision_lines(world, agent.id));
+        }
+        info_text.push(Line::from(""));
+    }
+
+    // --- カーソル ---
+    if let Some(cursor) = view.cursor {
+        let target = match world.agents_at(cursor).first() {
+            Some(id) => {
+                let agent = &world.agents[id];
+                format!(
+                    "{} (#{}) {}/{}",
+                    agent.name, id, agent.energy, agent.max_energy
+                )
+            }
+            None => "empty".to_string(),
+        };
+        info_text.push(Line::from(vec![Span::styled(
+            format!("Cursor: ({}, {}) {target}", cursor.x, cursor.y),
+            Style::default().fg(Color::Yellow),
+        )]));
+    }
+
+    info_text.extend([
+        Line::from(""),
+        Line::from("Controls:"),
+        Line::from(" 'q' to Quit"),
+        Line::from(" Space to Pause, '.' to Step"),
+        Line::from(" '+' / '-' to Speed Up / Slow Down"),
+        Line::from(" 'c' to Switch Color Mode"),
+        Line::from(" 'b' to Toggle Birth Markers"),
+        Line::from(" 'f' to Toggle Food Density"),
+        Line::from(" 'g' to Toggle Grid"),
+        Line::from(" Tab to Inspect Next Agent"),
+        Line::from(" 'v' to Toggle Vision Input"),
+        Line::from(" 'o' to Toggle Facing Arrows"),
+        Line::from(" 'w' to Toggle Winter Theme"),
+        Line::from(" Arrows to Move Cursor, Esc to Hide"),
+        Line::from(" 'k' to Kill / 'e' to Feed at Cursor"),
+    ]);
+
+    let info_block = Paragraph::new(info_text).block(themed_block(" Info ", winter));
+
+    f.render_widget(info_block, side[0]);
+
+    // パネルに入る分だけ、新しいものを下に出す
+    let visible = side[1].height.saturating_sub(2) as usize;
+    let skip = view.ticker.lines.len().saturating_sub(visible);
+    let ticker_text: Vec<Line> = view
+        .ticker
+        .lines
+        .iter()
+        .skip(skip)
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let ticker_block = Paragraph::new(ticker_text)
+        .style(Style::default().fg(Color::Gray))
+        .block(themed_block(" Events ", winter));
+    f.render_widget(ticker_block, side[1]);
+}
+
+/// 枠つきのブロック。冬の見た目なら、枠を寒い色にする
+fn themed_block(title: &str, winter: bool) -> Block<'_> {
+    let block = Block::default().borders(Borders::ALL).title(title);
+    if winter {
+        block.border_style(Style::default().fg(WINTER_BORDER_COLOR))
+    } else {
+        block
+    }
+}
+
+/// 餌の濃淡を見る範囲（周囲何マスか）
+const FOOD_DENSITY_RADIUS: usize = 2;
+
+/// pos の周りのマスのうち、餌があるマスの割合
+fn food_density(world: &World, pos: Position) -> f32 {
+    let (mut food, mut cells) = (0, 0);
+    for p in world.neighbors(pos, FOOD_DENSITY_RADIUS) {
+        cells += 1;
+        if world.foods[p.y][p.x] {
+            food += 1;
+        }
+    }
+    if cells > 0 {
+        food as f32 / cells as f32
+    } else {
+        0.0
+    }
+}
+
+/// 視界の枠の色
+const VISION_BOX_COLOR: Color = Color::DarkGray;
+/// 冬の見た目のときの枠の色
+const WINTER_BORDER_COLOR: Color = Color::LightCyan;
+/// カーソルの枠の色
+const CURSOR_COLOR: Color = Color::Yellow;
+
+/// `center` の個体の視界（一辺 `INPUT_FIELD_LENGTH` マス）を枠で囲む。
+/// フィールドの外にはみ出した分は描かない
+fn draw_vision_box(ctx: &mut canvas::Context, center: Position) {
+    let radius = InputLayout::RADIUS as usize;
+    let (left, top) = (
+        center.x.saturating_sub(radius),
+        center.y.saturating_sub(radius),
+    );
+    let right = (center.x + radius).min(crate::world::WIDTH - 1);
+    let bottom = (center.y + radius).min(crate::world::HEIGHT - 1);
+
+    // 描画座標は上下が逆なので、下の端が y になる
+    let (x, y) = calc_draw_position(Position { x: left, y: bottom });
+    ctx.draw(&Rectangle {
+        x,
+        y,
+        width: (right - left + 1) as f64,
+        height: (bottom - top + 1) as f64,
+        color: VISION_BOX_COLOR,
+    });
+}
+
+/// 個体の視界（脳への入力）を、マスごとに1文字で並べる。
+/// 真ん中の '@' が自分。フィールドの外は壁として見えているので '#' になる。
+/// 他の個体は、見えている色で描く
+fn vision_lines(world: &World, id: AgentId) -> Vec<Line<'static>> {
+    let input = world.get_input(id);
+    let cells = InputLayout::decode(input.as_slice().expect("input is contiguous"));
+    let center = InputLayout::RADIUS as usize;
+
+    cells
+        .iter()
+        .enumerate()
+        .map(|(row, line)| {
+            let mut spans = vec![Span::raw("  ")];
+            spans.extend(line.iter().enumerate().map(|(col, cell)| {
+                if (row, col) == (center, center) {
+                    Span::styled("@", Style::default().fg(Color::Cyan))
+                } else if cell.wall {
+                    Span::raw("#")
+                } else if cell.agent {
+                    Span::styled("a", Style::default().fg(rgb_color(cell.color)))
+                } else if cell.food {
+                    Span::styled("*", Style::default().fg(Color::Green))
+                } else {
+                    Span::styled(".", Style::default().fg(Color::DarkGray))
+                }
+            }));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// インスペクタの行動履歴に使う記号
+/// 世代の帯ごとの生存数を、横向きの棒グラフにする。
+/// 古い世代が残っているのか、どんどん入れ替わっているのかを見る用
+fn generation_band_lines(
+    bands: &[GenerationBand],
+    population: usize,
+) -> Vec<Line<'static>> {
+    if bands.is_empty() {
+        return Vec::new();
+    }
+    let bar = |label: String, count: usize| {
+        let filled = (count * GENERATION_BAR_WIDTH).div_ceil(population.max(1));
+        Line::from(vec![
+            Span::raw(format!(" {label:>9} ")),
+            Span::styled("█".repeat(filled), Style::default().fg(Color::LightMagenta)),
+            Span::raw(format!(" {count}")),
+        ])
+    };
+
+    let mut lines = vec![Line::from("Generations:")];
+    // 帯が多すぎたら、古いほうを1本にまとめる
+    let split = bands.len().saturating_sub(GENERATION_BAND_ROWS);
+    let (older, newer) = bands.split_at(split);
+    if let (Some(first), Some(last)) = (older.first(), older.last()) {
+        let count = older.iter().map(|band| band.count).sum();
+        lines.push(bar(format!("{}-{}", first.first, last.last), count));
+    }
+    for band in newer {
+        lines.push(bar(format!("{}-{}", band.first, band.last), band.count));
+    }
+    lines
+}
+
+/// 向きの矢印（攻撃の "x" などと同じく、エージェントの上に重ねる）
+fn facing_span(facing: crate::agent::Direction) -> Span<'static> {
+    let arrow = match facing {
+        crate::agent::Direction::Up => "↑",
+        crate::agent::Direction::Down => "↓",
+        crate::agent::Direction::Left => "←",
+        crate::agent::Direction::Right => "→",
+    };
+    Span::styled(arrow, Style::default().fg(Color::White))
+}
+
+fn action_symbol(action: Action) -> char {
+    match action {
+        Action::Up => '↑',
+        Action::Down => '↓',
+        Action::Left => '←',
+        Action::Right => '→',
+        Action::Stay => '·',
+        Action::Attack => 'x',
+        Action::Heal => '+',
+        Action::Rest => 'z',
+        Action::Sprint => '»',
+        Action::Gift => 'g',
+        Action::Eat => 'e',
+    }
+}
+
+/// 生まれてから何ステップの間、印をつけるか
+const BIRTH_MARKER_STEPS: u32 = 5;
+
+/// GRID_SPACING マスごとに格子の線を引く
+fn draw_grid(ctx: &mut canvas::Context) {
+    let (width, height) = (crate::world::WIDTH, crate::world::HEIGHT);
+    for x in (GRID_SPACING..width).step_by(GRID_SPACING) {
+        ctx.draw(&canvas::Line {
+            x1: x as f64,
+            y1: 0.0,
+            x2: x as f64,
+            y2: height as f64,
+            color: GRID_COLOR,
+        });
+    }
+    for y in (GRID_SPACING..height).step_by(GRID_SPACING) {
+        ctx.draw(&canvas::Line {
+            x1: 0.0,
+            y1: y as f64,
+            x2: width as f64,
+            y2: y as f64,
+            color: GRID_COLOR,
+        });
+    }
+}
+
+/// 格子の間隔（マス）
+const GRID_SPACING: usize = 10;
+/// 格子の色
+const GRID_COLOR: Color = Color::Rgb(40, 40, 40);
+
+/// 世代の凡例のブロックの数
+const LEGEND_STEPS: u32 = 8;
+
+/// 世代の内訳で、新しいほうから何本まで帯を出すか（それより古い帯は1本にまとめる）
+const GENERATION_BAND_ROWS: usize = 5;
+/// 世代の内訳の棒の長さ（全員が1つの帯にいるときの長さ）
+const GENERATION_BAR_WIDTH: usize = 12;
+
+/// 出生・死亡のペースを何ステップの平均で出すか
+pub const CHURN_WINDOW: usize = 50;
+
+/// カーソルの個体に 'e' で餌をやったときに増えるエネルギー（餌1個分）
+pub const FEED_AMOUNT: u32 = crate::world::FOOD_ENERGY;
+
+/// 1ステップ進める間隔（50ms = 20fps）
+pub const DEFAULT_TICK_RATE: Duration = Duration::from_millis(50);
+/// '+' と '-' で1段ずつ選べる間隔（ミリ秒、短い順）。
+/// `DEFAULT_TICK_RATE` も入っているので、'+' と '-' を同じ回数押せば元の速さに戻る
+const TICK_RATES_MS: [u64; 10] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000];
+
+/// 1段速くした間隔（'+'）。一番速ければそのまま
+pub fn faster_tick_rate(tick_rate: Duration) -> Duration {
+    TICK_RATES_MS
+        .iter()
+        .rev()
+        .map(|&ms| Duration::from_millis(ms))
+        .find(|&rate| rate < tick_rate)
+        .unwrap_or(tick_rate)
+}
+
+/// 1段遅くした間隔（'-'）。一番遅ければそのまま
+pub fn slower_tick_rate(tick_rate: Duration) -> Duration {
+    TICK_RATES_MS
+        .iter()
+        .map(|&ms| Duration::from_millis(ms))
+        .find(|&rate| rate > tick_rate)
+        .unwrap_or(tick_rate)
+}
+
+/// 出来事のログの欄の高さ（枠を含む）
+const TICKER_HEIGHT: u16 = 10;
+
+/// 世代を色に変換する。
+/// 今いる中で一番古い世代は青、一番新しい世代は赤で、その間は緑・黄色を通る。
+fn generation_color(generation: u32, (min, max): (u32, u32)) -> Color {
+    let t = if max > min {
+        (generation.saturating_sub(min)) as f32 / (max - min) as f32
+    } else {
+        1.0
+    };
+
+    // 色相 240°(青) → 0°(赤)。彩度・明度は最大
+    let hue = 240.0 * (1.0 - t.clamp(0.0, 1.0));
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        _ => (0.0, x, 1.0),
+    };
+
+    Color::Rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+/// エージェントの色 (0.0~1.0) を u8 (0~255) の端末の色に変換
+fn rgb_color(color: crate::agent::Color) -> Color {
+    let r = (color[0] * 255.0) as u8;
+    let g = (color[1] * 255.0) as u8;
+    let b = (color[2] * 255.0) as u8;
+    Color::Rgb(r, g, b)
+}
+
+/// 寿命のうちどれだけ生きたか（0.0〜1.0）
+fn age_ratio(age: u32, lifespan: u32) -> f32 {
+    if lifespan == 0 {
+        return 1.0;
+    }
+    (age as f32 / lifespan as f32).min(1.0)
+}
+
+/// 進化した色を、`aged`（0.0〜1.0）に比例して年齢モードの色に寄せる
+fn age_tinted(color: crate::agent::Color, aged: f32, view: &ViewOptions) -> Color {
+    let t = aged * view.age_tint_strength;
+    let tint = view.age_tint.rgb();
+    rgb_color([0, 1, 2].map(|i| color[i] + (tint[i] - color[i]) * t))
+}
+
+/// 色の明るさ（輝度）に応じた濃淡ブロック文字を返す
+fn shade_glyph(color: crate::agent::Color) -> &'static str {
+    // ITU-R BT.709 の係数で輝度を計算
+    let luminance = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+
+    // 真っ暗でも見えるように、一番薄いのは '░' にしておく
+    match luminance {
+        l if l < 0.25 => "░",
+        l if l < 0.5 => "▒",
+        l if l < 0.75 => "▓",
+        _ => "█",
+    }
+}
+
+fn calc_draw_position(pos: crate::world::Position) -> (f64, f64) {
+    let draw_x = pos.x as f64;
+    let draw_y = (crate::world::HEIGHT - 1 - pos.y) as f64;
+    (draw_x, draw_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::{agent::AgentSpec, params::Params};
+
+    const TERMINAL_WIDTH: u16 = 160;
+    const TERMINAL_HEIGHT: u16 = 70;
+
+    /// `TestBackend` に描いて、画面の中身を返す
+    fn render(world: &World, view: &ViewOptions) -> Buffer {
+        let backend = TestBackend::new(TERMINAL_WIDTH, TERMINAL_HEIGHT);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, world, view)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    /// `symbol` が描かれているマス（左上から順に）
+    fn cells_with(buffer: &Buffer, symbol: &str) -> Vec<(u16, u16)> {
+        let area = buffer.area;
+        (area.top()..area.bottom())
+            .flat_map(|y| (area.left()..area.right()).map(move |x| (x, y)))
+            .filter(|&(x, y)| buffer[(x, y)].symbol() == symbol)
+            .collect()
+    }
+
+    /// 画面の `y` 行目の文字列
+    fn row_text(buffer: &Buffer, y: u16) -> String {
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect()
+    }
+
+    /// 白い個体を左上に、黒い個体を右下に置いた世界（餌はなし）
+    fn two_agent_world() -> World {
+        let mut world = World::with_params(1, Params::default());
+        for (pos, color) in [
+            (Position { x: 5, y: 5 }, [1.0, 1.0, 1.0]),
+            (Position { x: 44, y: 44 }, [0.0, 0.0, 0.0]),
+        ] {
+            let spec = AgentSpec {
+                color: Some(color),
+                ..AgentSpec::default()
+            };
+            world.try_add_agent(pos, spec).unwrap();
+        }
+        world
+    }
+
+    #[test]
+    fn grayscale_field_draws_agents_where_they_are() {
+        let world = two_agent_world();
+        let view = ViewOptions {
+            color_mode: ColorMode::Grayscale,
+            ..ViewOptions::default()
+        };
+        let buffer = render(&world, &view);
+
+        // フィールド（左の 70%）の中だけ見る（情報パネルの棒グラフも同じ文字を使うので）
+        let field_right = TERMINAL_WIDTH * 7 / 10;
+        let in_field = |symbol| {
+            cells_with(&buffer, symbol)
+                .into_iter()
+                .filter(|&(x, _)| x < field_right)
+                .collect::<Vec<_>>()
+        };
+        let white = in_field(shade_glyph([1.0, 1.0, 1.0]));
+        let black = in_field(shade_glyph([0.0, 0.0, 0.0]));
+        assert_eq!(white.len(), 1, "white agent drawn {white:?}");
+        assert_eq!(black.len(), 1, "black agent drawn {black:?}");
+        let ((wx, wy), (bx, by)) = (white[0], black[0]);
+        // 左上の個体は、右下の個体より左で上
+        assert!(wx < bx && wy < by, "white {white:?}, black {black:?}");
+    }
+
+    #[test]
+    fn full_color_field_uses_agent_color() {
+        let world = two_agent_world();
+        let buffer = render(&world, &ViewOptions::default());
+        let white_cells = buffer
+            .content
+            .iter()
+            .filter(|cell| cell.fg == Color::Rgb(255, 255, 255))
+            .count();
+        assert!(white_cells > 0, "no cell painted with the agent's color");
+    }
+
+    #[test]
+    fn info_panel_shows_population() {
+        let world = two_agent_world();
+        let buffer = render(&world, &ViewOptions::default());
+        let population = (0..TERMINAL_HEIGHT)
+            .map(|y| row_text(&buffer, y))
+            .find(|row| row.contains("Population:"))
+            .expect("no Population line");
+        assert!(population.contains("Population: 2"), "{population}");
+    }
+
+    #[test]
+    fn extinct_banner_appears_only_after_extinction() {
+        let has_banner = |world: &World| {
+            let buffer = render(world, &ViewOptions::default());
+            (0..TERMINAL_HEIGHT)
+                .any(|y| row_text(&buffer, y).contains("EXTINCT (step 1)"))
+        };
+        let mut world = two_agent_world();
+        assert!(!has_banner(&world));
+
+        for id in world.living_ids().collect::<Vec<_>>() {
+            world.kill_agent(id);
+        }
+        // 次のステップの頭で全滅して、そのあとも出っぱなし
+        world.run(3);
+        assert_eq!(world.extinction_step, Some(1));
+        assert!(has_banner(&world));
+    }
+
+    #[test]
+    fn facing_arrows_point_where_agents_face() {
+        use crate::agent::Direction;
+
+        // 四隅に、それぞれ違う向きの個体を置く
+        let mut world = World::with_params(1, Params::default());
+        for (pos, facing) in [
+            (Position { x: 5, y: 5 }, Direction::Up),
+            (Position { x: 44, y: 5 }, Direction::Right),
+            (Position { x: 5, y: 44 }, Direction::Down),
+            (Position { x: 44, y: 44 }, Direction::Left),
+        ] {
+            let spec = AgentSpec {
+                facing: Some(facing),
+                ..AgentSpec::default()
+            };
+            world.try_add_agent(pos, spec).unwrap();
+        }
+
+        let field_right = TERMINAL_WIDTH * 7 / 10;
+        let arrows = |view: &ViewOptions| {
+            let buffer = render(&world, view);
+            ["↑", "→", "↓", "←"].map(|arrow| {
+                cells_with(&buffer, arrow)
+                    .into_iter()
+                    .filter(|&(x, _)| x < field_right)
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let hidden = arrows(&ViewOptions::default());
+        assert!(hidden.iter().all(Vec::is_empty), "{hidden:?}");
+
+        let view = ViewOptions {
+            show_facing: true,
+            ..ViewOptions::default()
+        };
+        let [up, right, down, left] = arrows(&view);
+        for cells in [&up, &right, &down, &left] {
+            assert_eq!(cells.len(), 1, "{up:?} {right:?} {down:?} {left:?}");
+        }
+        let (up, right, down, left) = (up[0], right[0], down[0], left[0]);
+        assert!(up.0 < right.0 && up.1 == right.1, "{up:?} {right:?}");
+        assert!(down.0 < left.0 && down.1 == left.1, "{down:?} {left:?}");
+        assert!(up.0 == down.0 && up.1 < down.1, "{up:?} {down:?}");
+    }
+
+    #[test]
+    fn speed_keys_step_through_the_ladder_and_back() {
+        let mut rate = DEFAULT_TICK_RATE;
+        let mut faster = Vec::new();
+        for _ in 0..TICK_RATES_MS.len() {
+            rate = faster_tick_rate(rate);
+            faster.push(rate.as_millis());
+        }
+        // 一番速いところで止まる
+        assert_eq!(faster, [20, 10, 5, 2, 1, 1, 1, 1, 1, 1]);
+
+        // 同じ回数だけ遅くすれば元に戻る
+        let mut rate = DEFAULT_TICK_RATE;
+        for _ in 0..3 {
+            rate = faster_tick_rate(rate);
+        }
+        for _ in 0..3 {
+            rate = slower_tick_rate(rate);
+        }
+        assert_eq!(rate, DEFAULT_TICK_RATE);
+
+        for _ in 0..TICK_RATES_MS.len() {
+            rate = slower_tick_rate(rate);
+        }
+        assert_eq!(rate, Duration::from_millis(1000));
+        assert_eq!(faster_tick_rate(rate), Duration::from_millis(500));
+    }
+}
@@ -1,13 +1,37 @@
-use std::{collections::HashMap, ops::Range};
+use std::{cmp::Reverse, collections::HashMap, ops::Range};
 
 use ndarray::Array1;
-use rand::{Rng, SeedableRng, seq::IndexedRandom};
+use rand::{
+    Rng,
+    seq::{IndexedRandom, SliceRandom},
+};
 
 use crate::{
-    agent::{Action, Agent, Color},
-    brain::{INPUT_FIELD_LENGTH, INPUT_SIZE},
+    agent::{Action, Agent, AgentSpec, Color, Direction, Role, generate_name},
+    brain::{INPUT_FIELD_LENGTH, InputLayout},
+    event::{DeathCause, StepEvent},
+    params::{
+        BirthPlacement, EatMode, ExtinctionPolicy, FoodDistribution, HealMode,
+        InteractTargeting, Params, Placement, UpdateOrder, WallBump,
+    },
+    rng::{RngCheckpoint, RngStream, RngStreams, WorldRng, keyed_rng},
+    stats::{
+        ActionTally, GenerationBand, LineageStats, StepTimings, Strategy, WorldStats,
+    },
+    terrain::{self, Terrain},
 };
 
+mod autosave;
+mod diff;
+mod render;
+mod scenario;
+mod snapshot;
+
+pub use autosave::Autosave;
+pub use diff::WorldDiff;
+pub use render::Palette;
+pub use scenario::{ImageMapping, Scenario, ScenarioAgent};
+
 pub type AgentId = usize;
 
 pub const WIDTH: usize = 50;
@@ -26,14 +50,23 @@ pub const FOOD_ENERGY: u32 = 60;
 
 /// 攻撃、回復にかかるコスト
 pub const INTERACT_COST: u32 = 10;
-/// 攻撃の相手の体力の変化量（吸血の場合は、これに手数料を引いたものをゲットできる）
+/// 攻撃の相手の体力の変化量のデフォルト（`Params::attack_damage`）
 pub const ATTACK_AMOUNT: i32 = -20;
 /// 回復の相手の体力の変化量
 pub const HEAL_AMOUNT: u32 = 8;
 
 pub const LIFESPAN_RANGE: Range<u32> = 500..700;
 
-#[derive(Debug, Clone, Copy)]
+/// 夏と冬が入れ替わる間隔（ステップ数）
+pub const SEASON_LENGTH: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Summer,
+    Winter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -44,63 +77,266 @@ pub struct World {
     pub step: u64,
     pub agents: HashMap<AgentId, Agent>,
 
-    pub grid: Vec<Vec<Option<AgentId>>>,
+    /// マスごとにいるエージェントのID（ID順）。
+    /// 普通は1マスに1匹だけど、`max_agents_per_cell` を増やすと重なれる
+    pub grid: Vec<Vec<Vec<AgentId>>>,
     pub foods: Vec<Vec<bool>>,
+    /// マスごとの地形（シードと設定から作るので保存はしない）
+    pub terrain: Vec<Vec<Terrain>>,
 
-    pub rng: rand::rngs::StdRng,
+    /// 用途ごとの乱数列。外からは `rng_for` で使う
+    rngs: RngStreams,
+    /// 乱数列や名前の生成に使う
+    pub seed: u64,
     next_id: usize,
+
+    pub params: Params,
+
+    /// これまでで一番多かった生存数と、そのステップ
+    pub peak_population: usize,
+    pub peak_step: u64,
+    /// 全滅したステップ（まだなら None）
+    pub extinction_step: Option<u64>,
+    /// 平均エネルギーの指数移動平均（まだ1ステップも進めていなければ None）
+    pub smoothed_energy: Option<f32>,
+
+    /// 最後に測った脳の多様性（サンプルした個体同士の `Brain::distance` の平均）
+    pub genome_diversity: Option<f32>,
+    /// 脳の多様性がしきい値を下回っているか
+    pub converged: bool,
+
+    /// 最後に測った、見張りの期間のあいだに場所が変わった個体の割合
+    pub activity: Option<f32>,
+    /// 動いた個体の割合がしきい値を下回っているか
+    pub stagnant: bool,
+    /// 見張りの期間の始まりに、各個体がいた場所
+    window_positions: HashMap<AgentId, Position>,
+
+    /// 直前の step() の間に起きた出来事（step() の最初に空にする）
+    pub events: Vec<StepEvent>,
+
+    /// step() の更新順を入れるバッファ
+    order_buf: Vec<AgentId>,
+    /// このステップで `shuffle_deterministic` を呼んだ回数（step() の最初に 0 に戻す）
+    shuffles_this_step: u64,
+
+    /// 直前の step() で、それぞれの行動をとった個体の数
+    action_tally: ActionTally,
+
+    /// 直前の step() の処理ごとの時間（`timings` フィーチャーがないときはずっと 0）
+    timings: StepTimings,
+    /// これまでの step() の処理ごとの時間の合計
+    total_timings: StepTimings,
+}
+
+/// step() の処理ごとの時間を測る時計。
+/// `timings` フィーチャーがないときは何もしないので、ふだんの速さには響かない
+struct PhaseClock {
+    #[cfg(feature = "timings")]
+    last: std::time::Instant,
+}
+
+impl PhaseClock {
+    #[inline]
+    fn start() -> Self {
+        Self {
+            #[cfg(feature = "timings")]
+            last: std::time::Instant::now(),
+        }
+    }
+
+    /// 前に測ったときからの時間を `slot` に足して、測り直す
+    #[inline]
+    fn lap(&mut self, _slot: &mut std::time::Duration) {
+        #[cfg(feature = "timings")]
+        {
+            let now = std::time::Instant::now();
+            *_slot += now - self.last;
+            self.last = now;
+        }
+    }
 }
 
 impl World {
     pub fn new(seed: u64) -> Self {
+        Self::with_params(seed, Params::default())
+    }
+
+    pub fn with_params(seed: u64, params: Params) -> Self {
         Self {
             step: 0,
             agents: HashMap::new(),
-            grid: vec![vec![None; WIDTH]; HEIGHT],
+            grid: vec![vec![Vec::new(); WIDTH]; HEIGHT],
             foods: vec![vec![false; WIDTH]; HEIGHT],
-            rng: rand::rngs::StdRng::seed_from_u64(seed),
+            terrain: terrain::generate(seed, &params.terrain),
+            rngs: RngStreams::new(seed),
+            seed,
             next_id: 0,
+            params,
+            peak_population: 0,
+            peak_step: 0,
+            extinction_step: None,
+            smoothed_energy: None,
+            genome_diversity: None,
+            converged: false,
+            activity: None,
+            stagnant: false,
+            window_positions: HashMap::new(),
+            events: Vec::new(),
+            order_buf: Vec::new(),
+            shuffles_this_step: 0,
+            action_tally: ActionTally::default(),
+            timings: StepTimings::default(),
+            total_timings: StepTimings::default(),
+        }
+    }
+
+    /// `steps` ステップ進めて、最後の統計を返す。
+    /// `halted()` にならないうちは `step()` を `steps` 回呼ぶのとまったく同じ。
+    /// 全滅して止まる設定（`ExtinctionPolicy::Halt`）で全滅したら、そこで打ち切るので
+    /// 残りのステップは進まない（`step()` を直接呼ぶと、誰もいない世界がそのまま進む）
+    pub fn run(&mut self, steps: usize) -> WorldStats {
+        for _ in 0..steps {
+            if self.halted() {
+                break;
+            }
+            self.step();
         }
+        self.stats()
+    }
+
+    /// 全滅して止まっているか（`ExtinctionPolicy::Halt` のときだけ true になる）
+    pub fn halted(&self) -> bool {
+        self.params.on_extinction == ExtinctionPolicy::Halt && self.agents.is_empty()
     }
 
     pub fn step(&mut self) {
         self.step += 1;
+        self.events.clear();
+        self.shuffles_this_step = 0;
+        self.action_tally = ActionTally::default();
+        let mut timings = StepTimings::default();
+        let mut clock = PhaseClock::start();
+
+        // エネルギーがしきい値以下のまま猶予を過ぎたら死ぬ。寿命の個体は猶予なし
+        let threshold = self.params.death_energy_threshold;
+        let grace = self.params.death_grace_steps;
+        let mut dead: Vec<(AgentId, DeathCause)> = Vec::new();
+        for agent in self.agents.values_mut() {
+            if agent.energy <= threshold {
+                agent.starving_steps += 1;
+            } else {
+                agent.starving_steps = 0;
+            }
+            if agent.age >= agent.lifespan && agent.energy == 0 {
+                dead.push((agent.id, DeathCause::OldAge));
+            } else if agent.starving_steps > grace {
+                dead.push((agent.id, DeathCause::Starvation));
+            }
+        }
 
-        let dead_ids: Vec<usize> = self
-            .agents
-            .values()
-            .filter(|a| a.energy == 0)
-            .map(|a| a.id)
-            .collect();
-
-        for id in dead_ids {
+        // HashMap の並びに依存しないように、ID順で取り除く
+        dead.sort_unstable_by_key(|&(id, _)| id);
+        for (id, cause) in dead {
+            let agent = &self.agents[&id];
+            let pos = agent.pos;
+            let corpse = self
+                .params
+                .corpse_drop
+                .map(|drop| drop.amount.count(agent.energy, agent.max_energy));
             self.remove_agent(id);
+            if let Some(count) = corpse {
+                self.drop_corpse_food(pos, count);
+            }
+            self.events.push(StepEvent::Death { id, pos, cause });
+        }
+
+        if self.agents.is_empty() {
+            if self.extinction_step.is_none() {
+                self.extinction_step = Some(self.step);
+            }
+            if let ExtinctionPolicy::Reseed { count } = self.params.on_extinction {
+                let placement = self.params.initial_placement;
+                self.seed_agents_with(placement, count);
+            }
         }
 
+        clock.lap(&mut timings.deaths);
+
         self.spawn_foods();
+        clock.lap(&mut timings.food_spawn);
+
+        // 更新順のバッファは使い回す（毎ステップ確保し直さないように）
+        let mut agent_ids = std::mem::take(&mut self.order_buf);
+        match self.params.update_order {
+            // エネルギーが少ない順。同じならID順（HashMapの並びに依存しないように）
+            UpdateOrder::LowestEnergyFirst => {
+                self.agents_sorted_into(&mut agent_ids, |a| a.energy);
+            }
+            // ID順に並べてから混ぜる（HashMapの並びに依存しないように）
+            UpdateOrder::Random => {
+                self.agents_sorted_into(&mut agent_ids, |_| ());
+                self.shuffle_deterministic(&mut agent_ids);
+            }
+        }
 
-        let mut agent_ids: Vec<usize> = self.agents.keys().cloned().collect();
-        agent_ids.sort_by_key(|id| self.agents[id].energy);
+        // 並列モードなら、ステップ開始時点の視界で先に全員の脳を計算しておく
+        let precomputed = if self.params.threads > 1 {
+            self.precompute_decisions(&agent_ids, self.params.threads)
+        } else {
+            Vec::new()
+        };
 
-        for id in agent_ids {
+        let mut births = 0;
+        for (i, &id) in agent_ids.iter().enumerate() {
             debug_assert!(self.agents.contains_key(&id));
 
             let (action, new_color) = {
                 let input = self.get_input(id);
                 let agent = self.agents.get(&id).unwrap();
-                let output = agent.brain.forward(&input);
+                // 先に計算したときから視界が変わっていなければ、その結果を使う。
+                // 変わっていたら（前の個体が動いたなど）計算し直すので、結果は逐次と同じ
+                let output = match precomputed.get(i) {
+                    Some((pre_input, pre_output)) if *pre_input == input => {
+                        pre_output.clone()
+                    }
+                    _ => agent.brain.forward(&input, self.params.relu_slope),
+                };
 
                 // 出力から行動と色を決定
-                let act = Action::from_output(output.as_slice().unwrap());
-                let r = output[7].clamp(0.0, 1.0);
-                let g = output[8].clamp(0.0, 1.0);
-                let b = output[9].clamp(0.0, 1.0);
-                (act, [r, g, b])
+                let layout = self.params.output_layout();
+                let act = Action::from_output(
+                    output.as_slice().unwrap(),
+                    &layout,
+                    self.params.stay_bias,
+                    &self.params.disabled_actions,
+                );
+                // 色を出力しない設定なら、今の色のまま
+                let color = match layout.color() {
+                    Some(range) => {
+                        let rgb = &output.as_slice().unwrap()[range];
+                        [
+                            rgb[0].clamp(0.0, 1.0),
+                            rgb[1].clamp(0.0, 1.0),
+                            rgb[2].clamp(0.0, 1.0),
+                        ]
+                    }
+                    None => agent.color,
+                };
+                (act, color)
             };
+            clock.lap(&mut timings.decide);
 
+            let role_decay = self.params.roles.decay();
             if let Some(agent) = self.agents.get_mut(&id) {
                 agent.last_action = Some(action);
 
+                // 最近の行動のカウンタを少しずつ忘れる
+                agent.recent_attacks *= role_decay;
+                agent.recent_food *= role_decay;
+
+                agent.draw_reserve(&self.params.reserve);
+
                 agent.age += 1;
                 if agent.age >= agent.lifespan {
                     agent.energy = 0;
@@ -109,103 +345,777 @@ impl World {
 
             self.apply_action(id, action, new_color);
 
-            self.try_reproduce(id);
+            // クールダウンで行動が変わることもあるので、適用した後の行動を履歴に残す
+            if let Some(agent) = self.agents.get_mut(&id)
+                && let Some(action) = agent.last_action
+            {
+                agent.push_history(action);
+                self.action_tally.add(action);
+            }
+            clock.lap(&mut timings.apply);
+
+            // 出生の予算を使い切ったら、残りの個体は産まない
+            if self
+                .params
+                .birth_budget
+                .is_none_or(|budget| births < budget)
+                && self.try_reproduce(id).is_some()
+            {
+                births += 1;
+            }
+            clock.lap(&mut timings.reproduce);
+        }
+        self.order_buf = agent_ids;
+        self.timings = timings;
+        self.total_timings += timings;
+
+        if self.agents.len() > self.peak_population {
+            self.peak_population = self.agents.len();
+            self.peak_step = self.step;
+        }
+
+        // 平均エネルギーはステップごとのブレが大きいので、なめらかにしたものも持っておく
+        let avg_energy = self.avg_energy();
+        let alpha = self.params.energy_smoothing.clamp(0.0, 1.0);
+        self.smoothed_energy = Some(match self.smoothed_energy {
+            Some(prev) => prev + alpha * (avg_energy - prev),
+            None => avg_energy,
+        });
+
+        let interval = self.params.convergence.interval;
+        if interval > 0 && self.step.is_multiple_of(interval) {
+            self.check_convergence();
+        }
+
+        let window = self.params.stagnation.window;
+        if window > 0 && self.step.is_multiple_of(window) {
+            self.check_stagnation();
+        }
+
+        #[cfg(debug_assertions)]
+        self.assert_consistent();
+    }
+
+    /// `ids` の全員について、今の世界での視界と脳の出力を並列に計算する（読むだけ）。
+    /// 返す順番は `ids` と同じ
+    fn precompute_decisions(
+        &self,
+        ids: &[AgentId],
+        threads: usize,
+    ) -> Vec<(Array1<f32>, Array1<f32>)> {
+        let chunk_size = ids.len().div_ceil(threads).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = ids
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|&id| {
+                                let input = self.get_input(id);
+                                let output = self.agents[&id]
+                                    .brain
+                                    .forward(&input, self.params.relu_slope);
+                                (input, output)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("decision thread panicked"))
+                .collect()
+        })
+    }
+
+    /// 脳の多様性を測って、収束していたら設定に応じて新しい個体を放り込む
+    fn check_convergence(&mut self) {
+        let check = &self.params.convergence;
+
+        // 乱数を使うと他の挙動がズレるので、ID順に等間隔で取り出す
+        let ids = self.agents_sorted_by(|a| a.id);
+        let stride = (ids.len() / check.sample_size.max(1)).max(1);
+        let sample: Vec<&Agent> = ids
+            .iter()
+            .step_by(stride)
+            .take(check.sample_size)
+            .map(|id| &self.agents[id])
+            .collect();
+
+        if sample.len() < 2 {
+            self.genome_diversity = None;
+            self.converged = false;
+            return;
+        }
+
+        let mut total = 0.0;
+        let mut pairs = 0;
+        for (i, a) in sample.iter().enumerate() {
+            for b in &sample[i + 1..] {
+                total += a.brain.distance(&b.brain);
+                pairs += 1;
+            }
+        }
+        let diversity = total / pairs as f32;
+
+        self.genome_diversity = Some(diversity);
+        self.converged = diversity < check.threshold;
+
+        if self.converged && check.inject_count > 0 {
+            let count = check.inject_count;
+            self.inject_random_agents(count);
+        }
+    }
+
+    /// 前に測ったときから場所が変わった個体の割合を測って、停滞しているか調べる
+    fn check_stagnation(&mut self) {
+        // 前にも今もいる個体だけ比べる（生まれたて・死んだ個体は数えない）
+        let (mut moved, mut total) = (0, 0);
+        for (id, pos) in &self.window_positions {
+            if let Some(agent) = self.agents.get(id) {
+                total += 1;
+                if agent.pos != *pos {
+                    moved += 1;
+                }
+            }
+        }
+
+        let was_stagnant = self.stagnant;
+        if total > 0 {
+            let activity = moved as f32 / total as f32;
+            self.activity = Some(activity);
+            self.stagnant = activity < self.params.stagnation.threshold;
+        } else {
+            self.activity = None;
+            self.stagnant = false;
+        }
+        if self.stagnant && !was_stagnant {
+            self.events.push(StepEvent::Stagnation {
+                activity: self.activity.unwrap_or(0.0),
+            });
+        }
+
+        self.window_positions.clear();
+        self.window_positions
+            .extend(self.agents.values().map(|a| (a.id, a.pos)));
+    }
+
+    /// 空いている場所にランダムな個体を最大 `count` 匹置く。置けた数を返す
+    pub fn inject_random_agents(&mut self, count: usize) -> usize {
+        self.seed_agents_with(Placement::Uniform, count)
+    }
+
+    /// `placement` の撒き方で、新しいランダムな個体を count 匹置く。
+    /// 置けた数を返す（混みすぎていると count より少なくなる）
+    pub fn seed_agents_with(&mut self, placement: Placement, count: usize) -> usize {
+        let center = (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+
+        // 群れの中心は最初に決めておく
+        let cluster_centers: Vec<Position> = match placement {
+            Placement::Clustered { clusters, .. } => {
+                let rng = self.rngs.get_mut(RngStream::Placement);
+                (0..clusters.max(1))
+                    .map(|_| Position {
+                        x: rng.random_range(0..WIDTH),
+                        y: rng.random_range(0..HEIGHT),
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let mut added = 0;
+        // 混みすぎていて置けないときに無限ループしないよう、試す回数に上限をつける
+        for attempt in 0..count * 10 {
+            if added == count {
+                break;
+            }
+            let rng = self.rngs.get_mut(RngStream::Placement);
+            let (x, y) = match placement {
+                Placement::Uniform => (
+                    rng.random_range(0..WIDTH) as isize,
+                    rng.random_range(0..HEIGHT) as isize,
+                ),
+                Placement::Clustered { radius, .. } => {
+                    // 群れに順番に割り振る
+                    let c = cluster_centers[attempt % cluster_centers.len()];
+                    let r = radius as i64;
+                    (
+                        c.x as isize + rng.random_range(-r..=r) as isize,
+                        c.y as isize + rng.random_range(-r..=r) as isize,
+                    )
+                }
+                Placement::CenterRing { radius, thickness } => {
+                    let angle = rng.random_range(0.0..std::f32::consts::TAU);
+                    let half = thickness.max(0.0) / 2.0;
+                    let distance = radius + rng.random_range(-half..=half);
+                    (
+                        (center.0 + distance * angle.cos()).floor() as isize,
+                        (center.1 + distance * angle.sin()).floor() as isize,
+                    )
+                }
+            };
+
+            // 範囲外に出たら置かない（群れの半径を守るため、端に寄せたりはしない）
+            if !self.in_bounds(x, y) {
+                continue;
+            }
+            let pos = Position {
+                x: x as usize,
+                y: y as usize,
+            };
+            if self.add_new_agent(pos).is_some() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// グリッドとエージェントの位置の二重管理がズレていないか、
+    /// エネルギーや餌の数が上限を超えていないか確認する。
+    /// おかしかったら panic する（デバッグ用）。
+    pub fn assert_consistent(&self) {
+        // エージェント側から: 自分の位置のマスに自分のIDが入っているか
+        for (&id, agent) in &self.agents {
+            assert_eq!(agent.id, id, "Agent id mismatch");
+            assert!(
+                agent.energy <= agent.max_energy,
+                "Agent {id} has more energy than it can hold ({}/{})",
+                agent.energy,
+                agent.max_energy
+            );
+            assert!(
+                self.in_bounds(agent.pos.x as isize, agent.pos.y as isize),
+                "Agent {id} is out of bounds: {:?}",
+                agent.pos
+            );
+            assert!(
+                self.grid[agent.pos.y][agent.pos.x].contains(&id),
+                "Grid does not contain agent {id} at {:?}",
+                agent.pos
+            );
+        }
+
+        // グリッド側から: 入っているIDのエージェントが実在して、そこにいるか
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                assert!(
+                    cell.len() <= self.params.max_agents_per_cell.max(1),
+                    "Grid ({x}, {y}) has too many agents: {cell:?}"
+                );
+                assert!(
+                    cell.is_sorted_by(|a, b| a < b),
+                    "Grid ({x}, {y}) is not sorted or has duplicates: {cell:?}"
+                );
+                for id in cell {
+                    let agent = self
+                        .agents
+                        .get(id)
+                        .unwrap_or_else(|| panic!("Grid ({x}, {y}) has dead agent {id}"));
+                    assert!(
+                        agent.pos.x == x && agent.pos.y == y,
+                        "Agent {id} is at {:?} but grid has it at ({x}, {y})",
+                        agent.pos
+                    );
+                }
+            }
+        }
+
+        // 餌の上限は個体数で変わるので、一番ゆるいとき（0匹のとき）の上限と比べる
+        let food_limit = self.params.food_cap(0).max(self.params.max_foods);
+        assert!(
+            self.food_count() <= food_limit,
+            "Too much food: {} > {food_limit}",
+            self.food_count()
+        );
+    }
+
+    /// (x, y) がフィールドの中か。はみ出した座標（負の値も）は false。
+    /// 範囲のチェックはここに寄せておく（大きさを変えられるようにしたときに直す場所を1つにする）
+    pub fn in_bounds(&self, x: isize, y: isize) -> bool {
+        (0..WIDTH as isize).contains(&x) && (0..HEIGHT as isize).contains(&y)
+    }
+
+    /// `center` を中心とした一辺 `2 * radius + 1` の正方形のうち、
+    /// 中心を除いたフィールド内のマスを返す。
+    /// 並びは上の行から順に、各行は左から右。
+    pub fn neighbors(
+        &self,
+        center: Position,
+        radius: usize,
+    ) -> impl Iterator<Item = Position> + '_ {
+        let radius = radius as isize;
+        let (cx, cy) = (center.x as isize, center.y as isize);
+
+        (-radius..=radius)
+            .flat_map(move |dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| !(dx == 0 && dy == 0)) // 自分自身の場所はスキップ
+            .map(move |(dx, dy)| (cx + dx, cy + dy))
+            .filter(|&(nx, ny)| self.in_bounds(nx, ny))
+            .map(|(nx, ny)| Position {
+                x: nx as usize,
+                y: ny as usize,
+            })
+    }
+
+    /// そのマスにいるエージェントのID（ID順）
+    pub fn agents_at(&self, pos: Position) -> &[AgentId] {
+        &self.grid[pos.y][pos.x]
+    }
+
+    /// そのマスにまだエージェントが入れるか
+    pub fn has_room(&self, pos: Position) -> bool {
+        self.terrain[pos.y][pos.x].is_passable()
+            && self.agents_at(pos).len() < self.params.max_agents_per_cell.max(1)
+    }
+
+    /// 生きているエージェントのID（順番は決まっていない）
+    pub fn living_ids(&self) -> impl Iterator<Item = AgentId> + '_ {
+        self.agents.keys().copied()
+    }
+
+    /// `key` の小さい順（昇順）に並べたエージェントのID。
+    /// `key` が同じならID順なので、いつも同じ並びになる
+    pub fn agents_sorted_by<K: Ord>(&self, key: impl Fn(&Agent) -> K) -> Vec<AgentId> {
+        let mut ids = Vec::with_capacity(self.agents.len());
+        self.agents_sorted_into(&mut ids, key);
+        ids
+    }
+
+    /// `agents_sorted_by` と同じだけど、結果を `buf` に入れる（毎回確保し直さないように）
+    pub fn agents_sorted_into<K: Ord>(
+        &self,
+        buf: &mut Vec<AgentId>,
+        key: impl Fn(&Agent) -> K,
+    ) {
+        buf.clear();
+        buf.extend(self.living_ids());
+        buf.sort_unstable_by_key(|id| (key(&self.agents[id]), *id));
+    }
+
+    /// 決まった並びに混ぜる。シード・ステップ・そのステップで何回目の呼び出しか、だけで決まるので、
+    /// 同じシードで同じステップまで進めた世界なら（保存して読み込み直しても）同じ混ぜ方になる。
+    /// 乱数で順番を混ぜたい機能は、自前で乱数を使わずにこれを使う
+    pub fn shuffle_deterministic<T>(&mut self, items: &mut [T]) {
+        let mut rng = keyed_rng(self.seed, self.step, self.shuffles_this_step);
+        self.shuffles_this_step += 1;
+        items.shuffle(&mut rng);
+    }
+
+    /// 今の乱数列の状態とステップ数を保存する
+    pub fn rng_checkpoint(&self) -> RngCheckpoint {
+        RngCheckpoint::new(self.step, self.shuffles_this_step, self.rngs.clone())
+    }
+
+    /// `rng_checkpoint` で保存した乱数列とステップ数（と、そのステップで混ぜた回数）に戻す。
+    /// 乱数以外（エージェントや餌）は戻らないので、
+    /// チェックポイントを取ったときと同じ状態の世界に使うこと
+    pub fn restore_rng(&mut self, checkpoint: &RngCheckpoint) {
+        self.step = checkpoint.step;
+        self.shuffles_this_step = checkpoint.shuffles_this_step();
+        self.rngs = checkpoint.streams().clone();
+    }
+
+    /// 用途ごとの乱数列を取り出す
+    pub fn rng_for(&mut self, stream: RngStream) -> &mut WorldRng {
+        self.rngs.get_mut(stream)
+    }
+
+    /// 今の統計データを集計する
+    pub fn stats(&self) -> WorldStats {
+        let population = self.agents.len();
+        let max_generation = self
+            .agents
+            .values()
+            .map(|a| a.generation)
+            .max()
+            .unwrap_or(0);
+        let avg_energy = self.avg_energy();
+
+        let predators = self
+            .agents
+            .values()
+            .filter(|a| a.role(&self.params.roles) == Role::Predator)
+            .count();
+
+        // 同じ指紋の脳を数える
+        let mut genomes: HashMap<u64, usize> = HashMap::new();
+        for agent in self.agents.values() {
+            *genomes.entry(agent.brain.fingerprint()).or_default() += 1;
+        }
+        let dominant_genome = genomes.values().copied().max().unwrap_or(0);
+
+        WorldStats {
+            step: self.step,
+            population,
+            food_count: self.food_count(),
+            food_energy: self.food_energy(),
+            food_eaten: self
+                .events
+                .iter()
+                .filter(|e| matches!(e, StepEvent::Eat { .. }))
+                .count(),
+            max_generation,
+            avg_energy,
+            avg_armor: if population > 0 {
+                self.agents.values().map(|a| a.armor).sum::<f32>() / population as f32
+            } else {
+                0.0
+            },
+            peak_population: self.peak_population,
+            peak_step: self.peak_step,
+            extinction_step: self.extinction_step,
+            smoothed_energy: self.smoothed_energy.unwrap_or(avg_energy),
+            predators,
+            prey: population - predators,
+            distinct_genomes: genomes.len(),
+            dominant_genome_share: if population > 0 {
+                dominant_genome as f32 / population as f32
+            } else {
+                0.0
+            },
+            timings: cfg!(feature = "timings").then_some(self.timings),
+            action_tally: self.action_tally,
+            strategy: Strategy::classify(&self.action_tally, &self.params.strategy),
+            generation_bands: GenerationBand::tally(
+                self.agents.values().map(|a| a.generation),
+                self.params.generation_band_width,
+            ),
+        }
+    }
+
+    /// これまでの step() の処理ごとの時間の合計（`timings` フィーチャーがないときは None）
+    pub fn total_timings(&self) -> Option<StepTimings> {
+        cfg!(feature = "timings").then_some(self.total_timings)
+    }
+
+    /// 今生きている個体の家系のまとめ
+    pub fn lineages(&self) -> LineageStats {
+        let mut sizes: HashMap<AgentId, usize> = HashMap::new();
+        for agent in self.agents.values() {
+            *sizes.entry(agent.founder).or_default() += 1;
+        }
+
+        // 同じ数なら、IDが小さい（古い）家系を選ぶ
+        let dominant = sizes
+            .iter()
+            .max_by_key(|&(&founder, &size)| (size, std::cmp::Reverse(founder)));
+
+        LineageStats {
+            surviving_lineages: sizes.len(),
+            deepest_lineage: self
+                .agents
+                .values()
+                .map(|a| a.generation)
+                .max()
+                .unwrap_or(0),
+            dominant_founder: dominant.map(|(&founder, _)| founder),
+            dominant_share: dominant
+                .map_or(0.0, |(_, &size)| size as f32 / self.agents.len() as f32),
+        }
+    }
+
+    /// 個体数が上限（`Params::max_population`）に達しているか
+    pub fn at_capacity(&self) -> bool {
+        self.params
+            .max_population
+            .is_some_and(|max| self.agents.len() >= max)
+    }
+
+    /// 今の平均エネルギー（誰もいなければ 0）
+    fn avg_energy(&self) -> f32 {
+        if self.agents.is_empty() {
+            return 0.0;
+        }
+        let total_energy: u64 = self.agents.values().map(|a| a.energy as u64).sum();
+        total_energy as f32 / self.agents.len() as f32
+    }
+
+    /// フィールドにある餌の総数
+    pub fn food_count(&self) -> usize {
+        self.foods
+            .iter()
+            .map(|row| row.iter().filter(|&&has_food| has_food).count())
+            .sum()
+    }
+
+    /// デバッグ用に、フィールドを文字で描く（1行が1列分、上の行から）。
+    /// `.` は何もない、`*` は餌、`@` は個体。何匹も重なっているマスはその数（10匹以上は `#`）
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        for (cells, foods) in self.grid.iter().zip(&self.foods) {
+            for (cell, &has_food) in cells.iter().zip(foods) {
+                out.push(match cell.len() {
+                    0 if has_food => '*',
+                    0 => '.',
+                    1 => '@',
+                    n => char::from_digit(n as u32, 10).unwrap_or('#'),
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// フィールドにある餌を全部食べたときの回復量の合計。
+    /// 餌の栄養はゾーンで違うので、数だけだと環境の豊かさが分からない
+    pub fn food_energy(&self) -> u64 {
+        self.foods
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|&(_, &has_food)| has_food)
+                    .map(move |(x, _)| (x, y))
+            })
+            .map(|(x, y)| self.params.food_value_at(x, y) as u64)
+            .sum()
+    }
+
+    /// 今の季節。SEASON_LENGTH ごとに夏と冬が入れ替わる
+    pub fn season(&self) -> Season {
+        if (self.step / SEASON_LENGTH) % 2 == 1 {
+            Season::Winter
+        } else {
+            Season::Summer
+        }
+    }
+
+    /// 手で殺す（エネルギーを 0 にする）。
+    /// その場では取り除かず、次のステップの頭で飢え死にと同じように死ぬ（猶予があればその分は生きる）。
+    /// いなければ false
+    pub fn kill_agent(&mut self, id: AgentId) -> bool {
+        match self.agents.get_mut(&id) {
+            Some(agent) => {
+                agent.energy = 0;
+                true
+            }
+            None => false,
         }
     }
 
+    /// 手でエネルギーを `amount` 足す（最大エネルギーまで）。
+    /// 実際に増えた量を返す。いなければ None
+    pub fn feed_agent(&mut self, id: AgentId, amount: u32) -> Option<u32> {
+        let agent = self.agents.get_mut(&id)?;
+        let before = agent.energy;
+        agent.energy = agent.energy.saturating_add(amount).min(agent.max_energy);
+        Some(agent.energy - before)
+    }
+
     /// エージェントを世界に追加するヘルパー
+    /// 置けたら新しい個体のIDを返す
     #[must_use]
-    pub fn add_new_agent(&mut self, pos: Position) -> Option<()> {
-        if self.grid[pos.y][pos.x].is_some() {
+    pub fn add_new_agent(&mut self, pos: Position) -> Option<AgentId> {
+        self.try_add_agent(pos, AgentSpec::default())
+    }
+
+    /// `spec` で決めた部分だけ上書きした個体を `pos` に置く。置けたら新しい個体のIDを返す。
+    /// フィールドの外、もう入れないマス、脳の形が設定と合わないときは None。
+    /// 上書きしてもしなくても乱数は同じだけ使うので、そのあとの展開は `spec` で変わらない
+    pub fn try_add_agent(&mut self, pos: Position, spec: AgentSpec) -> Option<AgentId> {
+        if !self.in_bounds(pos.x as isize, pos.y as isize) || !self.has_room(pos) {
+            return None;
+        }
+        if let Some(brain) = &spec.brain
+            && brain.shape() != self.params.brain_shape()
+        {
             return None;
         }
 
         let id = self.next_id;
         self.next_id += 1;
 
-        let agent = Agent::new_random(id, pos, &mut self.rng);
+        let name = generate_name(id, self.seed);
+        let mut agent = Agent::new_random(
+            id,
+            name,
+            pos,
+            &self.params,
+            self.rngs.get_mut(RngStream::Mutation),
+        );
+        if let Some(color) = spec.color {
+            agent.color = color;
+        }
+        if let Some(energy) = spec.energy {
+            agent.energy = energy.min(agent.max_energy);
+        }
+        if let Some(brain) = spec.brain {
+            agent.brain = brain;
+        }
+        if let Some(facing) = spec.facing {
+            agent.facing = facing;
+        }
 
         // 空間と実体の両方に登録
         self.add_agent(agent, pos);
 
-        Some(())
+        Some(id)
     }
 
     fn add_agent(&mut self, agent: Agent, pos: Position) {
-        self.grid[pos.y][pos.x] = Some(agent.id);
+        self.grid_insert(agent.id, pos);
         self.agents.insert(agent.id, agent);
     }
 
     fn remove_agent(&mut self, id: AgentId) {
         let agent = self.agents.remove(&id).unwrap();
-        self.grid[agent.pos.y][agent.pos.x] = None;
+        self.grid_remove(id, agent.pos);
+    }
+
+    /// マスにIDを入れる（ID順を保つ）
+    fn grid_insert(&mut self, id: AgentId, pos: Position) {
+        let cell = &mut self.grid[pos.y][pos.x];
+        if let Err(i) = cell.binary_search(&id) {
+            cell.insert(i, id);
+        }
+    }
+
+    fn grid_remove(&mut self, id: AgentId, pos: Position) {
+        self.grid[pos.y][pos.x].retain(|&other| other != id);
+    }
+
+    /// 自分のマスの他の個体と、周囲 radius マスの個体。
+    /// 自分のマスが先で、あとは `neighbors` の順（マスの中はID順）
+    fn nearby_agents(&self, id: AgentId, radius: usize) -> Vec<AgentId> {
+        let pos = self.agents[&id].pos;
+        std::iter::once(pos)
+            .chain(self.neighbors(pos, radius))
+            .flat_map(|p| self.agents_at(p).iter().copied())
+            .filter(|&other| other != id)
+            .collect()
     }
 
     // 餌を生成する処理
     /// - 中央に近いほど湧きやすい
-    /// - MAX_FOODSを超えたら湧かない
+    /// - 餌の上限（`Params::food_cap`）を超えたら湧かない
     pub fn spawn_foods(&mut self) {
         // 1. 現在の餌の総数を数える (Maxチェック用)
-        let current_food_count: usize = self
-            .foods
-            .iter()
-            .map(|row| row.iter().filter(|&&has_food| has_food).count())
-            .sum();
+        let mut current_food_count = self.food_count();
+        let food_cap = self.params.food_cap(self.agents.len());
 
         // 既に満タンなら何もしない
-        if current_food_count >= MAX_FOODS {
+        if current_food_count >= food_cap {
             return;
         }
 
         // --- 設定値 ---
         // let population = self.agents.len();
         // let spawn_count = 50 + (population / 2);
-        let base_probability = 0.2; // チャンスが来た時の基本確率 (20%)
-
-        // 中心座標と、中心から角までの最大距離 (正規化用)
-        let center_x = WIDTH as f32 / 2.0;
-        let center_y = HEIGHT as f32 / 2.0;
-        let max_dist = (center_x.powi(2) + center_y.powi(2)).sqrt();
-
-        let is_winter = (self.step / 2000) % 2 == 1;
-
-        let spawn_count = if is_winter {
-            FOOD_SPAWN_COUNT_WINTER
-        } else {
-            FOOD_SPAWN_COUNT_SUMMER
+        let (spawn_count, spread) = match self.season() {
+            Season::Summer => (FOOD_SPAWN_COUNT_SUMMER, self.params.summer_food_spread),
+            Season::Winter => (FOOD_SPAWN_COUNT_WINTER, self.params.winter_food_spread),
         };
 
+        // 早めに打ち切るための上限（書かなければ打ち切らない）
+        let target = self.params.food_spawn_target.unwrap_or(usize::MAX);
+        let max_misses = self.params.food_spawn_max_misses.unwrap_or(usize::MAX);
+        let (mut placed, mut misses) = (0, 0);
+
+        let rng = self.rngs.get_mut(RngStream::Food);
         for _ in 0..spawn_count {
             // ランダムな座標を選ぶ
-            let x = self.rng.random_range(0..WIDTH);
-            let y = self.rng.random_range(0..HEIGHT);
+            let x = rng.random_range(0..WIDTH);
+            let y = rng.random_range(0..HEIGHT);
 
             // 既に餌がある場所はスキップ
             if self.foods[y][x] {
+                misses += 1;
+                if misses >= max_misses {
+                    break;
+                }
                 continue;
             }
 
-            // 2. 確率計算：中心に近いほど高確率にする
-            let dx = x as f32 - center_x;
-            let dy = y as f32 - center_y;
-            let dist = (dx.powi(2) + dy.powi(2)).sqrt();
-
-            // 距離スコア (0.0 ~ 1.0)
-            // 中心(dist=0)なら 1.0, 一番遠い角(dist=max)なら 0.0
-            let score = (1.0 - (dist / max_dist)).max(0.0);
+            // どのゾーンにも入っていない場所と、壁には湧かない
+            let Some(zone) = self.params.food_zone_at(x, y) else {
+                continue;
+            };
+            if !self.terrain[y][x].is_passable() {
+                continue;
+            }
 
-            // 最終確率 = 基本確率 * スコアの2乗
-            // (2乗することで、中心付近に急激に集まる分布になる！)
-            let probability = base_probability * score.powi(2);
+            // 2. 確率計算
+            let probability = match zone.distribution {
+                FoodDistribution::Uniform => zone.spawn_probability,
+                FoodDistribution::Centered => {
+                    // ゾーンの中心座標と、中心から餌が湧く範囲の端までの距離 (正規化用)
+                    let center_x = zone.x as f32 + zone.width as f32 / 2.0;
+                    let center_y = zone.y as f32 + zone.height as f32 / 2.0;
+                    let half_width = zone.width as f32 / 2.0;
+                    let half_height = zone.height as f32 / 2.0;
+                    let max_dist = (half_width.powi(2) + half_height.powi(2)).sqrt()
+                        * spread.radius_scale;
+
+                    // 中心に近いほど高確率にする
+                    let dx = x as f32 - center_x;
+                    let dy = y as f32 - center_y;
+                    let dist = (dx.powi(2) + dy.powi(2)).sqrt();
+
+                    // 距離スコア (0.0 ~ 1.0)
+                    // 中心(dist=0)なら 1.0, 範囲の端(dist=max)より遠ければ 0.0
+                    let score = (1.0 - (dist / max_dist)).max(0.0);
+
+                    // 最終確率 = 基本確率 * スコアの exponent 乗
+                    // (累乗することで、中心付近に急激に集まる分布になる！)
+                    zone.spawn_probability * score.powf(spread.exponent)
+                }
+            };
 
             // 3. 乱数で判定
-            if self.rng.random::<f32>() < probability {
+            if rng.random::<f32>() < probability {
                 self.foods[y][x] = true;
+
+                // 上限ちょうどで止める
+                current_food_count += 1;
+                placed += 1;
+                if current_food_count >= food_cap || placed >= target {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 死んだ個体の餌（死骸）を `count` 個、`pos` に落とす。
+    /// 死んだマスにもう餌があれば、`CorpseDrop::spread_radius` マス以内の近いマスから順に置く。
+    /// 壁と餌があるマスには置かず、餌の上限（`Params::food_cap`）を超える分は捨てる
+    fn drop_corpse_food(&mut self, pos: Position, count: usize) {
+        let Some(drop) = self.params.corpse_drop else {
+            return;
+        };
+        if count == 0 {
+            return;
+        }
+        let room = self
+            .params
+            .food_cap(self.agents.len())
+            .saturating_sub(self.food_count());
+        let mut left = count.min(room);
+
+        // 死んだマス、そのすぐ周り、その外側…の順に見る
+        let spots: Vec<Position> = std::iter::once(pos)
+            .chain((1..=drop.spread_radius).flat_map(|r| {
+                self.neighbors(pos, r)
+                    .filter(move |p| p.x.abs_diff(pos.x).max(p.y.abs_diff(pos.y)) == r)
+            }))
+            .collect();
+        for spot in spots {
+            if left == 0 {
+                break;
+            }
+            if self.foods[spot.y][spot.x] || !self.terrain[spot.y][spot.x].is_passable() {
+                continue;
             }
+            self.foods[spot.y][spot.x] = true;
+            left -= 1;
         }
     }
 
-    /// エージェントIDを受け取り、その視界データ(150次元)を返す
+    /// エージェントIDを受け取り、その視界データ（普通は294次元）を返す
+    /// 並びは `InputLayout` を参照
     pub fn get_input(&self, id: AgentId) -> Array1<f32> {
         let agent = self.agents.get(&id).expect("Agent not found");
         let (center_x, center_y): (isize, isize) = (
@@ -213,18 +1123,36 @@ impl World {
             agent.pos.y.try_into().unwrap(),
         );
 
-        let mut input = Vec::with_capacity(INPUT_SIZE);
+        let mut input = Vec::with_capacity(self.params.input_size());
 
         let radius = (INPUT_FIELD_LENGTH / 2) as isize;
 
+        // 視線をさえぎるマス（壁か、自分以外の個体がいるマス）
+        let blocks_sight = |dx: isize, dy: isize| {
+            let (nx, ny) = (center_x + dx, center_y + dy);
+            !self.in_bounds(nx, ny)
+                || !self.terrain[ny as usize][nx as usize].is_passable()
+                || self.grid[ny as usize][nx as usize]
+                    .iter()
+                    .any(|&other| other != id)
+        };
+
         for dy in -radius..=radius {
             for dx in -radius..=radius {
                 let nx = center_x + dx;
                 let ny = center_y + dy;
 
-                // 1. 壁判定 (範囲外なら壁)
-                let is_wall =
-                    nx < 0 || ny < 0 || nx >= WIDTH as isize || ny >= HEIGHT as isize;
+                // 遮蔽ありなら、途中でさえぎられたマスは何も分からない（全部 0）
+                if self.params.vision_occlusion
+                    && cells_between(dx, dy).any(|(x, y)| blocks_sight(x, y))
+                {
+                    input.extend([0.0; InputLayout::CHANNELS]);
+                    continue;
+                }
+
+                // 1. 壁判定 (範囲外か、壁の地形なら壁)
+                let is_wall = !self.in_bounds(nx, ny)
+                    || !self.terrain[ny as usize][nx as usize].is_passable();
 
                 // 範囲内の情報を取得
                 let mut is_food = false;
@@ -235,14 +1163,17 @@ impl World {
                     let (ux, uy) = (nx as usize, ny as usize);
                     is_food = self.foods[uy][ux];
 
-                    if let Some(target_id) = self.grid[uy][ux]
-                        && target_id != id
+                    // 何匹か重なっているときは、自分以外で一番IDが小さい（古株の）個体が見える。
+                    // 見分けられない相手（設定による）はいないのと同じ
+                    if let Some(target) = self.grid[uy][ux]
+                        .iter()
+                        .filter(|&&other| other != id)
+                        .filter_map(|other| self.agents.get(other))
+                        .find(|target| self.params.perception.can_see(agent, target))
                     {
                         is_agent = true;
                         // 相手の色を取得
-                        if let Some(target) = self.agents.get(&target_id) {
-                            color = target.color;
-                        }
+                        color = target.color;
                     }
                 }
 
@@ -257,143 +1188,430 @@ impl World {
         }
 
         // 入力ベクトルの長さを確認
-        debug_assert_eq!(input.len(), INPUT_SIZE);
+        // 設定によっては自分の色も見る
+        if self.params.self_color_input {
+            input.extend(agent.color);
+        }
+
+        debug_assert_eq!(input.len(), self.params.input_size());
 
         Array1::from(input)
     }
 
     /// 行動を適用する
     fn apply_action(&mut self, id: AgentId, action: Action, new_color: Color) {
+        let step = self.step;
+        let cooldown = self.params.attack_cooldown;
         let Some(agent) = self.agents.get_mut(&id) else {
             panic!("Agent not found");
         };
 
+        // 攻撃のクールダウン中なら、攻撃できずに待機になる
+        let action = match (action, agent.last_attack_step) {
+            (Action::Attack, Some(last)) if step - last <= cooldown => {
+                agent.last_action = Some(Action::Stay);
+                Action::Stay
+            }
+            (Action::Attack, _) => {
+                agent.last_attack_step = Some(step);
+                agent.recent_attacks += 1.0;
+                Action::Attack
+            }
+            (action, _) => action,
+        };
+
         agent.color = new_color;
-        // 基礎代謝コスト
-        agent.energy = agent.energy.saturating_sub(1);
+        // 基礎代謝コスト（休息中は代謝を抑えるのでかからない）
+        let energy_before = agent.energy;
+        agent.energy = agent
+            .energy
+            .saturating_sub(self.params.action_costs.basal(action));
+        let basal_paid = energy_before - agent.energy;
+        // 鎧の維持費（休息中はかからない）。待機の割引の対象にしないので、行動のあとで別に払う
+        let upkeep = match (action, &self.params.armor) {
+            (Action::Rest, _) | (_, None) => 0,
+            (_, Some(armor)) => armor.upkeep(agent.armor),
+        };
 
         match action {
             Action::Up | Action::Down | Action::Left | Action::Right => {
-                self.move_agent(id, action);
+                if let Some(direction) = action.direction() {
+                    self.move_agent(id, direction);
+                }
             }
             Action::Stay => {
-                // 待機ボーナス（何もしないなら少し消費が減る等のルールを入れてもいい）
+                // 待機ボーナス：払った基礎代謝から stay_metabolism_discount だけ返す
+                let refund = self.params.stay_metabolism_discount.min(basal_paid);
+                let cost = self.params.action_costs.of(Action::Stay);
+                if let Some(agent) = self.agents.get_mut(&id) {
+                    agent.energy = (agent.energy + refund).saturating_sub(cost);
+                }
             }
             Action::Attack => {
-                self.interact_area(id, ATTACK_AMOUNT); // 周囲にダメージ
+                // 周囲にダメージ
+                self.interact_area(id, Action::Attack, self.params.attack_damage);
             }
             Action::Heal => {
-                self.interact_area(id, HEAL_AMOUNT as i32); // 周囲を回復（自分はコスト消費）
+                // 周囲を回復（自分はコスト消費）
+                self.interact_area(id, Action::Heal, HEAL_AMOUNT);
+            }
+            Action::Rest => {
+                // 基礎代謝を払わないだけ。ひとりぼっちでも生き延びる手段
+                let cost = self.params.action_costs.of(Action::Rest);
+                if let Some(agent) = self.agents.get_mut(&id) {
+                    agent.energy = agent.energy.saturating_sub(cost);
+                }
+            }
+            Action::Sprint => {
+                self.sprint_agent(id);
+            }
+            Action::Gift => {
+                let cost = self.params.action_costs.of(Action::Gift);
+                if let Some(agent) = self.agents.get_mut(&id) {
+                    agent.energy = agent.energy.saturating_sub(cost);
+                }
+                self.give_gift(id);
+            }
+            Action::Eat => {
+                let cost = self.params.action_costs.of(Action::Eat);
+                let Some(agent) = self.agents.get_mut(&id) else {
+                    return;
+                };
+                agent.energy = agent.energy.saturating_sub(cost);
+                let pos = agent.pos;
+                self.eat_food(id, pos);
             }
         }
+
+        if upkeep > 0
+            && let Some(agent) = self.agents.get_mut(&id)
+        {
+            agent.energy = agent.energy.saturating_sub(upkeep);
+        }
     }
 
     /// 移動ロジック
-    fn move_agent(&mut self, id: AgentId, action: Action) {
-        // 現在位置と移動先を計算
-        let Position { x: cx, y: cy } = self.agents.get(&id).map(|a| a.pos).unwrap();
-        let (dx, dy) = match action {
-            Action::Up => (0, -1),
-            Action::Down => (0, 1),
-            Action::Left => (-1, 0),
-            Action::Right => (1, 0),
-            _ => (0, 0),
-        };
-
+    fn move_agent(&mut self, id: AgentId, direction: Direction) {
         // 移動コスト消費
+        let move_cost = self.params.action_costs.movement;
+        let mut paid = 0;
         if let Some(agent) = self.agents.get_mut(&id) {
-            agent.energy = agent.energy.saturating_sub(1); // 移動は疲れる
+            paid = agent.energy.min(move_cost);
+            agent.energy -= paid; // 移動は疲れる
+            agent.facing = direction;
         }
 
-        let nx = cx as isize + dx;
-        let ny = cy as isize + dy;
-
-        // 壁チェック
-        if nx < 0 || ny < 0 || nx >= WIDTH as isize || ny >= HEIGHT as isize {
-            return; // 範囲外なので移動キャンセル
+        if self.step_forward(id, direction) {
+            return;
         }
 
-        let (nx, ny) = (nx as usize, ny as usize);
-
-        // 衝突チェック (誰もいないか？)
-        if self.grid[ny][nx].is_none() {
-            // 移動処理：グリッドを更新
-            self.grid[cy][cx] = None;
-            self.grid[ny][nx] = Some(id);
-
-            // エージェントの座標更新
-            if let Some(agent) = self.agents.get_mut(&id) {
-                agent.pos = Position { x: nx, y: ny };
-
-                // 餌チェック & 自動食事
-                if self.foods[ny][nx] {
-                    self.foods[ny][nx] = false; // 餌消滅
-                    let gain = FOOD_ENERGY; // 回復量
-                    agent.energy = (agent.energy + gain).min(agent.max_energy);
+        // 動けなかったのが壁（フィールドの端か壁の地形）のせいなら、
+        // 設定に合わせて払い戻すか罰を与える
+        let Some(pos) = self.agents.get(&id).map(|a| a.pos) else {
+            return;
+        };
+        let (dx, dy) = direction.delta();
+        let (nx, ny) = (pos.x as isize + dx, pos.y as isize + dy);
+        let hit_wall = !self.in_bounds(nx, ny)
+            || !self.terrain[ny as usize][nx as usize].is_passable();
+        if hit_wall && let Some(agent) = self.agents.get_mut(&id) {
+            match self.params.wall_bump {
+                WallBump::Pay => {}
+                WallBump::Refund => {
+                    agent.energy += paid;
                 }
+                WallBump::Penalty(penalty) => {
+                    agent.energy = agent.energy.saturating_sub(penalty);
+                }
+            }
+        }
+    }
+
+    /// ダッシュ。向いている方向に sprint_distance マスまで一気に進む
+    fn sprint_agent(&mut self, id: AgentId) {
+        let sprint_cost = self.params.action_costs.sprint;
+        let Some(agent) = self.agents.get_mut(&id) else {
+            return;
+        };
+        agent.energy = agent.energy.saturating_sub(sprint_cost); // 普通の移動より疲れる
+        let direction = agent.facing;
+
+        // 1マスずつ進んで、壁や他の個体にぶつかったらそこで止まる
+        for _ in 0..self.params.sprint_distance {
+            if !self.step_forward(id, direction) {
+                break;
             }
         }
     }
 
-    /// 周囲への干渉（攻撃・回復）
-    fn interact_area(&mut self, id: AgentId, effect: i32) {
+    /// `direction` に1マス進む。進めたら true。
+    /// 進んだ先に餌があれば食べる。
+    fn step_forward(&mut self, id: AgentId, direction: Direction) -> bool {
+        // 現在位置と移動先を計算
         let Position { x: cx, y: cy } = self.agents.get(&id).map(|a| a.pos).unwrap();
+        let (dx, dy) = direction.delta();
+
+        let nx = cx as isize + dx;
+        let ny = cy as isize + dy;
+
+        // 壁チェック
+        if !self.in_bounds(nx, ny) {
+            return false; // 範囲外なので移動キャンセル
+        }
+
+        let (nx, ny) = (nx as usize, ny as usize);
+
+        // 衝突チェック (まだ入れるか？)
+        let next = Position { x: nx, y: ny };
+        if !self.has_room(next) {
+            return false;
+        }
+
+        // 移動処理：グリッドを更新
+        self.grid_remove(id, Position { x: cx, y: cy });
+        self.grid_insert(id, next);
+
+        // エージェントの座標更新
+        if let Some(agent) = self.agents.get_mut(&id) {
+            agent.pos = Position { x: nx, y: ny };
+
+            // 泥や荒地に入るのは余分に疲れる
+            let extra_cost = self.terrain[ny][nx].extra_cost(&self.params.terrain);
+            agent.energy = agent.energy.saturating_sub(extra_cost);
+        }
+
+        // 餌チェック & 自動食事（自分で食べる設定なら、入っただけでは食べない）
+        if self.params.eat_mode == EatMode::Auto {
+            self.eat_food(id, next);
+        }
+
+        true
+    }
+
+    /// `pos` に餌があれば、`id` の個体が食べる
+    fn eat_food(&mut self, id: AgentId, pos: Position) {
+        if !self.foods[pos.y][pos.x] {
+            return;
+        }
+        let Some(agent) = self.agents.get_mut(&id) else {
+            return;
+        };
+        self.foods[pos.y][pos.x] = false; // 餌消滅
+        let food_value = self.params.food_value_at(pos.x, pos.y);
+        let gained = agent.eat(food_value, &self.params.reserve);
+        agent.recent_food += 1.0;
+        self.events.push(StepEvent::Eat {
+            id,
+            pos,
+            food_value,
+            gained,
+        });
+    }
+
+    /// 周囲への干渉（攻撃・回復）。
+    /// `action` は `Attack` か `Heal`。`amount` は基本のダメージか回復量。
+    /// 攻撃のダメージが（弱っていて）0 になっても、攻撃は攻撃として扱う
+    fn interact_area(&mut self, id: AgentId, action: Action, amount: u32) {
+        debug_assert!(matches!(action, Action::Attack | Action::Heal));
+        let attack = action == Action::Attack;
+        let cost = self.params.action_costs.of(action);
+
+        // 攻撃の強さは、設定によっては自分の元気さで変わる
+        let amount = if attack {
+            let me = &self.agents[&id];
+            self.params
+                .attack_scaling
+                .scale(amount, me.energy, me.max_energy)
+        } else {
+            amount
+        };
 
         if let Some(me) = self.agents.get_mut(&id) {
-            me.energy = me.energy.saturating_sub(INTERACT_COST);
+            me.energy = me.energy.saturating_sub(cost);
         }
 
-        // 周囲8マスに作用
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                if dx == 0 && dy == 0 {
-                    continue;
-                } // 自分は除外
+        // 同じマスと周囲 interact_radius マスに作用（設定によっては1匹だけ選ぶ）
+        let targeting = if attack {
+            self.params.attack_targeting
+        } else {
+            self.params.heal_targeting
+        };
+        let nearby = self.nearby_agents(id, self.params.interact_radius);
+        let chosen: Vec<AgentId> = match targeting {
+            InteractTargeting::All => nearby,
+            InteractTargeting::WeakestOnly => nearby
+                .into_iter()
+                .min_by_key(|target_id| self.agents[target_id].energy)
+                .into_iter()
+                .collect(),
+            // max_by_key だと同じときに後のほうになるので、向きを逆にして min_by_key で選ぶ
+            InteractTargeting::StrongestOnly => nearby
+                .into_iter()
+                .min_by_key(|target_id| Reverse(self.agents[target_id].energy))
+                .into_iter()
+                .collect(),
+        };
 
-                let nx = cx as isize + dx;
-                let ny = cy as isize + dy;
+        let mut hits = 0;
+        let mut targets = Vec::new();
+        let mut kills = Vec::new();
+        for target_id in chosen {
+            if let Some(target) = self.agents.get_mut(&target_id) {
+                targets.push(target.pos);
+                if attack {
+                    // 攻撃：相手の体力を減らす
+                    hits += 1;
+                    let damage = target.armored(amount);
+                    let actual_damage = target.energy.min(damage); // 相手が持ってる分しか奪えない
+
+                    target.energy = target.energy.saturating_sub(actual_damage);
+                    if actual_damage > 0 && target.energy == 0 {
+                        kills.push((target_id, target.pos));
+                    }
 
-                if nx >= 0
-                    && ny >= 0
-                    && nx < WIDTH as isize
-                    && ny < HEIGHT as isize
-                    && let Some(target_id) = self.grid[ny as usize][nx as usize]
-                    && let Some(target) = self.agents.get_mut(&target_id)
-                {
-                    if effect < 0 {
-                        // 攻撃：相手の体力を減らす
-                        let damage = effect.unsigned_abs();
-                        let actual_damage = target.energy.min(damage); // 相手が持ってる分しか奪えない
-
-                        target.energy = target.energy.saturating_sub(actual_damage);
-
-                        let absorb = (actual_damage as f32 * 0.8) as u32;
-
-                        // ※奪い取るルールにするなら、ここで自分のenergyを増やす
-                        if let Some(me) = self.agents.get_mut(&id) {
-                            me.energy = (me.energy + absorb).min(me.max_energy);
-                        }
-                    } else {
-                        // 回復：相手の体力を増やす
-                        target.energy =
-                            (target.energy + effect as u32).min(target.max_energy);
+                    let absorb = self.params.attack_gain.gained(actual_damage);
+
+                    // 奪った分を自分のエネルギーにする（最大エネルギーまで）
+                    if let Some(me) = self.agents.get_mut(&id) {
+                        me.energy = me.energy.saturating_add(absorb).min(me.max_energy);
+                    }
+                } else {
+                    // 回復：相手の体力を増やす（溢れる分は渡さない）
+                    let room = target.max_energy - target.energy.min(target.max_energy);
+                    let mut amount = amount.min(room);
+                    if self.params.heal_mode == HealMode::Transfer {
+                        // 自分の手持ちから渡すので、持っている分まで
+                        let me = self.agents.get_mut(&id).expect("healer is alive");
+                        amount = amount.min(me.energy);
+                        me.energy -= amount;
+                    }
+                    if let Some(target) = self.agents.get_mut(&target_id) {
+                        target.energy += amount;
                     }
                 }
             }
         }
+
+        // 反動：巻き込んだ相手の数だけ自分も傷つく（群れの中で攻撃を乱発しないように）
+        let recoil = self.params.attack_recoil.saturating_mul(hits);
+        if recoil > 0
+            && let Some(me) = self.agents.get_mut(&id)
+        {
+            me.energy = me.energy.saturating_sub(recoil);
+        }
+
+        self.events.push(StepEvent::Interaction {
+            actor: id,
+            action,
+            from: self.agents[&id].pos,
+            targets,
+        });
+        for (victim, pos) in kills {
+            self.events.push(StepEvent::Kill {
+                killer: id,
+                victim,
+                pos,
+            });
+        }
     }
 
-    pub fn try_reproduce(&mut self, id: AgentId) {
-        let (pos, can_reproduce) = {
-            if let Some(agent) = self.agents.get(&id) {
-                (agent.pos, agent.energy >= agent.max_energy)
-            } else {
-                return;
+    /// 贈り物。周囲 interact_radius マスで一番エネルギーが少ない個体に、
+    /// 自分のエネルギーの gift_fraction 分を渡す。
+    /// 回復と違って、渡した分だけ自分が減るので合計は変わらない
+    fn give_gift(&mut self, id: AgentId) {
+        let Some(me) = self.agents.get(&id) else {
+            return;
+        };
+
+        // 一番弱っている相手（同じなら先に見つけたほう）
+        let Some(target_id) = self
+            .nearby_agents(id, self.params.interact_radius)
+            .into_iter()
+            .min_by_key(|target_id| self.agents[target_id].energy)
+        else {
+            return;
+        };
+
+        // 相手の空き容量を超えた分は渡さない（溢れて消えないように）
+        let target = &self.agents[&target_id];
+        let room = target.max_energy - target.energy.min(target.max_energy);
+        let fraction = self.params.gift_fraction.clamp(0.0, 1.0);
+        let amount = ((me.energy as f32 * fraction) as u32).min(room);
+
+        let (from, to) = (me.pos, target.pos);
+        if let Some(me) = self.agents.get_mut(&id) {
+            me.energy -= amount;
+        }
+        if let Some(target) = self.agents.get_mut(&target_id) {
+            target.energy += amount;
+        }
+
+        self.events.push(StepEvent::Interaction {
+            actor: id,
+            action: Action::Gift,
+            from,
+            targets: vec![to],
+        });
+    }
+
+    /// 空いている場所を、設定の好みで絞り込む。好みに合う場所がなければそのまま返す
+    fn preferred_birth_spots(&self, free_spots: Vec<Position>) -> Vec<Position> {
+        let preferred: Vec<Position> = match self.params.birth_placement {
+            BirthPlacement::Random => return free_spots,
+            BirthPlacement::TowardFood => free_spots
+                .iter()
+                .copied()
+                .filter(|&p| {
+                    self.foods[p.y][p.x]
+                        || self.neighbors(p, 1).any(|n| self.foods[n.y][n.x])
+                })
+                .collect(),
+            BirthPlacement::AwayFromCrowd => {
+                let crowd = |p: Position| {
+                    self.neighbors(p, 1)
+                        .map(|n| self.grid[n.y][n.x].len())
+                        .sum::<usize>()
+                };
+                let fewest = free_spots.iter().map(|&p| crowd(p)).min();
+                free_spots
+                    .iter()
+                    .copied()
+                    .filter(|&p| Some(crowd(p)) == fewest)
+                    .collect()
             }
         };
 
+        if preferred.is_empty() {
+            free_spots
+        } else {
+            preferred
+        }
+    }
+
+    /// エネルギーがたまっていれば子供を産む。産んだら子供のID
+    pub fn try_reproduce(&mut self, id: AgentId) -> Option<AgentId> {
+        let (pos, can_reproduce) = {
+            let agent = self.agents.get(&id)?;
+            (
+                agent.pos,
+                self.params
+                    .reproduce_threshold
+                    .reached(agent.energy, agent.max_energy),
+            )
+        };
+
         if !can_reproduce {
-            return;
+            return None;
+        }
+
+        // 個体数が上限に達していたら産めない
+        if self.at_capacity() {
+            if self.params.charge_at_capacity
+                && let Some(parent) = self.agents.get_mut(&id)
+            {
+                parent.energy = parent.energy.saturating_sub(REPRODUCE_COST);
+            }
+            return None;
         }
 
         // 2. 繁殖コストの支払い（書き込み）
@@ -403,44 +1621,1675 @@ impl World {
         }
 
         // 3. 産む場所を探す
-        // 周囲8マスの空き地リストを作成
-        let mut free_spots = Vec::new();
-        let Position { x: cx, y: cy } = pos;
-        let (cx, cy) = (cx as isize, cy as isize);
-
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                if dx == 0 && dy == 0 {
-                    continue;
-                } // 自分自身の場所はスキップ
+        // 周囲 reproduce_radius マスの空き地リストを作成
+        let free_spots: Vec<Position> = self
+            .neighbors(pos, self.params.reproduce_radius)
+            .filter(|&p| self.has_room(p))
+            .collect();
+        let spots = self.preferred_birth_spots(free_spots);
 
-                let nx = cx + dx;
-                let ny = cy + dy;
+        // 4. 子供の生成
+        let child_pos = spots
+            .choose(self.rngs.get_mut(RngStream::Placement))
+            .copied()?;
+
+        let mutation = self.params.mutation.at(self.step);
+        let child = {
+            let parent = self.agents.get(&id).unwrap();
+            let new_id = self.next_id;
+            self.next_id += 1;
+            let new_name = generate_name(new_id, self.seed);
+
+            // 親の脳を引き継いだ子供を作る
+            parent.new_child(
+                new_id,
+                new_name,
+                child_pos,
+                mutation,
+                self.params.armor.as_ref(),
+                self.rngs.get_mut(RngStream::Mutation),
+            )
+        };
 
-                // 範囲内かチェック
-                if nx >= 0 && ny >= 0 && nx < WIDTH as isize && ny < HEIGHT as isize {
-                    let (ux, uy) = (nx as usize, ny as usize);
-                    // グリッドが空(None)なら候補に入れる
-                    if self.grid[uy][ux].is_none() {
-                        free_spots.push(Position { x: ux, y: uy });
-                    }
-                }
+        // 世界に登録
+        let child_id = child.id;
+        self.add_agent(child, child_pos);
+        self.events.push(StepEvent::Birth {
+            parent: id,
+            child: child_id,
+            pos: child_pos,
+        });
+        Some(child_id)
+    }
+}
+
+/// (0, 0) から (dx, dy) へまっすぐ引いた線が通るマス（両端は含まない）。
+/// ブレゼンハムのアルゴリズムで、線に一番近いマスを選ぶ。
+fn cells_between(dx: isize, dy: isize) -> impl Iterator<Item = (isize, isize)> {
+    let (sx, sy) = (dx.signum(), dy.signum());
+    let (ax, ay) = (dx.abs(), dy.abs());
+    let mut err = ax - ay;
+    let (mut x, mut y) = (0, 0);
+
+    // 長い方の軸の長さ - 1 マスだけ、途中のマスがある
+    (1..ax.max(ay)).map(move |_| {
+        let e2 = 2 * err;
+        if e2 > -ay {
+            err -= ay;
+            x += sx;
+        }
+        if e2 < ax {
+            err += ax;
+            y += sy;
+        }
+        (x, y)
+    })
+}
+
+/// テスト用の世界。`main` の `create_world` を小さくしたもの（テストが重くならないように）。
+/// ほかのモジュールのテストでも使う
+#[cfg(test)]
+pub(crate) fn test_world(seed: u64, params: Params) -> World {
+    let mut world = World::with_params(seed, params);
+    let placement = world.params.initial_placement;
+    world.seed_agents_with(placement, 40);
+    for _ in 0..20 {
+        world.spawn_foods();
+    }
+    world
+}
+
+/// 個体数が増えすぎないようにした設定
+#[cfg(test)]
+pub(crate) fn small_params() -> Params {
+    Params {
+        max_population: Some(60),
+        ..Params::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::{
+        brain::{Brain, INPUT_SIZE, RGB_COLOR_SIZE},
+        params::{
+            ActionCosts, ArmorParams, AttackGain, AttackScaling, CorpseAmount,
+            CorpseDrop, DynamicFoodCap, FounderColors, Perception, ReproduceThreshold,
+        },
+    };
+
+    #[test]
+    fn parallel_step_matches_serial() {
+        let mut serial = test_world(7, small_params());
+        let mut parallel = test_world(
+            7,
+            Params {
+                threads: 4,
+                ..small_params()
+            },
+        );
+        for _ in 0..500 {
+            serial.step();
+            parallel.step();
+            assert_eq!(
+                serial.state_hash(),
+                parallel.state_hash(),
+                "diverged at step {}",
+                serial.step
+            );
+        }
+        assert!(
+            !serial.agents.is_empty(),
+            "everyone died, so nothing was compared"
+        );
+    }
+
+    /// ランダムな（でもおかしくはない）設定。いろいろな機能の組み合わせを試す用
+    fn random_params(rng: &mut impl Rng) -> Params {
+        use crate::params::{
+            BirthPlacement, EatMode, InteractTargeting, TerrainZone, WallBump,
+        };
+
+        let mut params = small_params();
+        params.max_agents_per_cell = rng.random_range(1..=3);
+        params.interact_radius = rng.random_range(1..=2);
+        params.reproduce_radius = rng.random_range(1..=2);
+        params.birth_placement = [
+            BirthPlacement::Random,
+            BirthPlacement::TowardFood,
+            BirthPlacement::AwayFromCrowd,
+        ][rng.random_range(0..3)];
+        params.attack_targeting = [
+            InteractTargeting::All,
+            InteractTargeting::WeakestOnly,
+            InteractTargeting::StrongestOnly,
+        ][rng.random_range(0..3)];
+        params.attack_damage = rng.random_range(0..=60);
+        params.attack_gain = if rng.random_bool(0.5) {
+            AttackGain::Fraction(rng.random_range(0.0..3.0))
+        } else {
+            AttackGain::Fixed(rng.random_range(0..=200))
+        };
+        if rng.random_bool(0.5) {
+            params.attack_scaling = AttackScaling::Energy {
+                min_factor: rng.random_range(0.0..1.0),
+                max_factor: rng.random_range(1.0..2.0),
+            };
+        }
+        if rng.random_bool(0.5) {
+            params.armor = Some(ArmorParams {
+                initial: rng.random_range(0.0..1.0),
+                ..ArmorParams::default()
+            });
+        }
+        params.wall_bump = match rng.random_range(0..3) {
+            0 => WallBump::Pay,
+            1 => WallBump::Refund,
+            _ => WallBump::Penalty(rng.random_range(0..=5)),
+        };
+        params.eat_mode = if rng.random_bool(0.5) {
+            EatMode::Auto
+        } else {
+            EatMode::Active
+        };
+        params.update_order = if rng.random_bool(0.5) {
+            UpdateOrder::LowestEnergyFirst
+        } else {
+            UpdateOrder::Random
+        };
+        params.sprint_distance = rng.random_range(1..=4);
+        params.death_energy_threshold = rng.random_range(0..=5);
+        params.death_grace_steps = rng.random_range(0..=3);
+        params.max_foods = rng.random_range(0..=MAX_FOODS);
+        if rng.random_bool(0.5) {
+            params.dynamic_food_cap = Some(DynamicFoodCap::default());
+        }
+        if rng.random_bool(0.5) {
+            params.corpse_drop = Some(CorpseDrop {
+                amount: CorpseAmount::MaxEnergy {
+                    fraction: rng.random_range(0.0..2.0),
+                },
+                spread_radius: rng.random_range(0..=2),
+            });
+        }
+        params.reserve.store_overflow = rng.random_bool(0.5);
+        params.birth_budget = rng.random_bool(0.5).then(|| rng.random_range(0..=5));
+        params.on_extinction = match rng.random_range(0..3) {
+            0 => ExtinctionPolicy::Continue,
+            1 => ExtinctionPolicy::Halt,
+            _ => ExtinctionPolicy::Reseed { count: 10 },
+        };
+        params.terrain.mud_patches = rng.random_range(0..=3);
+        params.terrain.rough_patches = rng.random_range(0..=3);
+        // 壁の帯で区切ることもある
+        if rng.random_bool(0.5) {
+            params.terrain.zones.push(TerrainZone {
+                x: rng.random_range(0..WIDTH),
+                y: 0,
+                width: 1,
+                height: HEIGHT,
+                terrain: Terrain::Wall,
+            });
+        }
+        params.threads = rng.random_range(1..=3);
+        params
+    }
+
+    /// いろいろな設定・シードで少しずつ回して、毎ステップ中身が食い違っていないか見る
+    #[test]
+    fn random_configs_stay_consistent() {
+        let mut rng = keyed_rng(0xf022, 0, 0);
+        for _ in 0..64 {
+            let seed = rng.random();
+            let params = random_params(&mut rng);
+            let steps = rng.random_range(20..=200);
+            let mut world = test_world(seed, params.clone());
+            world.assert_consistent();
+            for _ in 0..steps {
+                world.step();
+                world.assert_consistent();
             }
         }
+    }
 
-        // 4. 子供の生成
-        if let Some(child_pos) = free_spots.choose(&mut self.rng).copied() {
-            let child = {
-                let parent = self.agents.get(&id).unwrap();
-                let new_id = self.next_id;
-                self.next_id += 1;
-
-                // 親の脳を引き継いだ子供を作る
-                parent.new_child(new_id, child_pos, &mut self.rng)
+    /// `agents` の位置とエネルギーで個体を置いた世界（餌はなし）
+    fn placed_world(params: Params, agents: &[(Position, u32)]) -> (World, Vec<AgentId>) {
+        let mut world = World::with_params(1, params);
+        let ids = agents
+            .iter()
+            .map(|&(pos, energy)| {
+                let spec = AgentSpec {
+                    energy: Some(energy),
+                    ..AgentSpec::default()
+                };
+                world.try_add_agent(pos, spec).unwrap()
+            })
+            .collect();
+        (world, ids)
+    }
+
+    /// `pos` に1匹だけ置いた世界（餌も地形もなし）
+    fn lone_agent(params: Params, pos: Position, energy: u32) -> (World, AgentId) {
+        let (world, ids) = placed_world(params, &[(pos, energy)]);
+        (world, ids[0])
+    }
+
+    /// 左に動こうとして、フィールドの端・壁の地形・他の個体にぶつかったあとのエネルギー
+    fn bump_energy(wall_bump: WallBump) -> [u32; 3] {
+        let params = Params {
+            wall_bump,
+            ..Params::default()
+        };
+
+        // フィールドの左端
+        let (mut edge, id) = lone_agent(params.clone(), Position { x: 0, y: 10 }, 50);
+        edge.move_agent(id, Direction::Left);
+        assert_eq!(edge.agents[&id].pos, Position { x: 0, y: 10 });
+
+        // 左が壁の地形
+        let (mut wall, id) = lone_agent(params.clone(), Position { x: 10, y: 10 }, 50);
+        wall.terrain[10][9] = Terrain::Wall;
+        wall.move_agent(id, Direction::Left);
+        assert_eq!(wall.agents[&id].pos, Position { x: 10, y: 10 });
+
+        // 左に他の個体
+        let (mut crowd, id) = lone_agent(params, Position { x: 10, y: 10 }, 50);
+        crowd.add_new_agent(Position { x: 9, y: 10 }).unwrap();
+        crowd.move_agent(id, Direction::Left);
+        assert_eq!(crowd.agents[&id].pos, Position { x: 10, y: 10 });
+
+        [edge, wall, crowd].map(|world| world.agents[&id].energy)
+    }
+
+    #[test]
+    fn wall_bump_pay_keeps_movement_cost() {
+        assert_eq!(bump_energy(WallBump::Pay), [49, 49, 49]);
+    }
+
+    #[test]
+    fn wall_bump_refund_applies_to_edges_and_wall_terrain() {
+        // 他の個体にぶつかったときは払い戻さない
+        assert_eq!(bump_energy(WallBump::Refund), [50, 50, 49]);
+    }
+
+    #[test]
+    fn wall_bump_penalty_applies_to_edges_and_wall_terrain() {
+        assert_eq!(bump_energy(WallBump::Penalty(5)), [44, 44, 49]);
+    }
+
+    /// 隣り合った2匹（左が `actor`、右が `target`）だけの世界
+    fn adjacent_pair(
+        params: Params,
+        actor_energy: u32,
+        target_energy: u32,
+    ) -> (World, [AgentId; 2]) {
+        let (world, ids) = placed_world(
+            params,
+            &[
+                (Position { x: 10, y: 10 }, actor_energy),
+                (Position { x: 11, y: 10 }, target_energy),
+            ],
+        );
+        (world, [ids[0], ids[1]])
+    }
+
+    /// `actor_energy` の個体が、満タンの相手を1回攻撃したときのダメージ
+    fn damage_dealt(params: Params, actor_energy: u32) -> u32 {
+        let max = MAX_ENERGY;
+        let (mut world, [actor, target]) = adjacent_pair(params, actor_energy, max);
+        let damage = world.params.attack_damage;
+        world.interact_area(actor, Action::Attack, damage);
+        max - world.agents[&target].energy
+    }
+
+    #[test]
+    fn energy_scaled_attack_is_stronger_when_healthy() {
+        let params = Params {
+            attack_damage: 20,
+            attack_scaling: AttackScaling::Energy {
+                min_factor: 0.5,
+                max_factor: 1.5,
+            },
+            ..Params::default()
+        };
+        let healthy = damage_dealt(params.clone(), MAX_ENERGY);
+        let starving = damage_dealt(params, 5);
+        assert_eq!(healthy, 30);
+        assert_eq!(starving, 11);
+
+        // 設定しなければ元気さに関係なく同じ
+        let flat = Params {
+            attack_damage: 20,
+            ..Params::default()
+        };
+        assert_eq!(damage_dealt(flat.clone(), MAX_ENERGY), 20);
+        assert_eq!(damage_dealt(flat, 5), 20);
+    }
+
+    #[test]
+    fn attack_scaled_to_zero_is_still_an_attack() {
+        let params = Params {
+            attack_scaling: AttackScaling::Energy {
+                min_factor: 0.0,
+                max_factor: 1.0,
+            },
+            // 回復だったら全員に効く設定にしておく
+            heal_targeting: InteractTargeting::All,
+            attack_targeting: InteractTargeting::WeakestOnly,
+            ..Params::default()
+        };
+        let (mut world, [actor, target]) = adjacent_pair(params, 0, 50);
+        let damage = world.params.attack_damage;
+        world.interact_area(actor, Action::Attack, damage);
+
+        // 回復されてもいないし、ダメージもない
+        assert_eq!(world.agents[&target].energy, 50);
+        assert!(matches!(
+            world.events.as_slice(),
+            [StepEvent::Interaction {
+                action: Action::Attack,
+                ..
+            }]
+        ));
+    }
+
+    /// エネルギー 50 の個体が、満タンの相手を1回攻撃したあとの自分と相手のエネルギー
+    fn after_attack(params: Params) -> [u32; 2] {
+        let (mut world, ids) = adjacent_pair(params, 50, MAX_ENERGY);
+        let damage = world.params.attack_damage;
+        world.interact_area(ids[0], Action::Attack, damage);
+        ids.map(|id| world.agents[&id].energy)
+    }
+
+    #[test]
+    fn attack_without_absorb_only_costs_the_attacker() {
+        let params = Params {
+            attack_damage: 20,
+            attack_gain: AttackGain::Fraction(0.0),
+            ..Params::default()
+        };
+        assert_eq!(after_attack(params), [50 - INTERACT_COST, MAX_ENERGY - 20]);
+    }
+
+    #[test]
+    fn attack_with_overkill_absorb_gains_more_than_damage() {
+        let params = Params {
+            attack_damage: 10,
+            attack_gain: AttackGain::Fraction(2.0),
+            ..Params::default()
+        };
+        assert_eq!(
+            after_attack(params),
+            [50 - INTERACT_COST + 20, MAX_ENERGY - 10]
+        );
+
+        // 最大エネルギーより多くは持てない
+        let params = Params {
+            attack_damage: 40,
+            attack_gain: AttackGain::Fraction(3.0),
+            ..Params::default()
+        };
+        assert_eq!(after_attack(params), [MAX_ENERGY, MAX_ENERGY - 40]);
+    }
+
+    #[test]
+    fn huge_attack_gain_does_not_overflow() {
+        let params = Params {
+            attack_gain: AttackGain::Fixed(u32::MAX),
+            ..Params::default()
+        };
+        assert_eq!(after_attack(params)[0], MAX_ENERGY);
+    }
+
+    #[test]
+    fn zero_damage_attack_is_still_an_attack() {
+        let params = Params {
+            attack_damage: 0,
+            attack_gain: AttackGain::Fixed(30),
+            ..Params::default()
+        };
+        // ダメージがないので、固定の吸収もない
+        assert_eq!(after_attack(params), [50 - INTERACT_COST, MAX_ENERGY]);
+    }
+
+    #[test]
+    fn in_bounds_accepts_corners_and_rejects_just_outside() {
+        let world = World::new(1);
+        let (w, h) = (WIDTH as isize, HEIGHT as isize);
+        for (x, y) in [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)] {
+            assert!(world.in_bounds(x, y), "({x}, {y}) should be inside");
+        }
+        for (x, y) in [
+            (-1, 0),
+            (0, -1),
+            (w, 0),
+            (0, h),
+            (w, h),
+            (isize::MIN, isize::MAX),
+        ] {
+            assert!(!world.in_bounds(x, y), "({x}, {y}) should be outside");
+        }
+    }
+
+    #[test]
+    fn neighbors_are_clipped_at_the_edges() {
+        let world = World::new(1);
+        let count = |x, y, radius| world.neighbors(Position { x, y }, radius).count();
+        assert_eq!(count(25, 25, 1), 8);
+        assert_eq!(count(0, 0, 1), 3);
+        assert_eq!(count(WIDTH - 1, HEIGHT - 1, 1), 3);
+        assert_eq!(count(0, 25, 1), 5);
+        assert_eq!(count(0, 0, 2), 8);
+        assert_eq!(
+            world
+                .neighbors(Position { x: 0, y: 0 }, 1)
+                .collect::<Vec<_>>(),
+            [
+                Position { x: 1, y: 0 },
+                Position { x: 0, y: 1 },
+                Position { x: 1, y: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn agents_cannot_step_off_any_edge() {
+        let (w, h) = (WIDTH - 1, HEIGHT - 1);
+        for (pos, direction) in [
+            (Position { x: 3, y: 0 }, Direction::Up),
+            (Position { x: 3, y: h }, Direction::Down),
+            (Position { x: 0, y: 3 }, Direction::Left),
+            (Position { x: w, y: 3 }, Direction::Right),
+        ] {
+            let (mut world, id) = lone_agent(Params::default(), pos, 50);
+            assert!(
+                !world.step_forward(id, direction),
+                "{direction:?} from {pos:?}"
+            );
+            assert_eq!(world.agents[&id].pos, pos);
+            world.assert_consistent();
+        }
+    }
+
+    /// 同じ行動を 10 回したあとのエネルギー（餌はないので減る一方）
+    fn energy_after_repeating(params: Params, action: Action) -> u32 {
+        let (mut world, id) = lone_agent(params, Position { x: 10, y: 10 }, 50);
+        for _ in 0..10 {
+            let color = world.agents[&id].color;
+            world.apply_action(id, action, color);
+        }
+        world.agents[&id].energy
+    }
+
+    #[test]
+    fn staying_pays_basal_minus_discount() {
+        let costs = ActionCosts {
+            basal: 3,
+            ..ActionCosts::default()
+        };
+        let with_discount = |stay_metabolism_discount| Params {
+            action_costs: costs.clone(),
+            stay_metabolism_discount,
+            ..Params::default()
+        };
+
+        assert_eq!(energy_after_repeating(with_discount(0), Action::Stay), 20);
+        assert_eq!(energy_after_repeating(with_discount(2), Action::Stay), 40);
+        // 基礎代謝以上に割り引くと、待機はタダ（余った分で増えたりはしない）
+        assert_eq!(energy_after_repeating(with_discount(3), Action::Stay), 50);
+        assert_eq!(energy_after_repeating(with_discount(10), Action::Stay), 50);
+        // 割引は待機だけ。休息はもともと基礎代謝がかからない
+        assert_eq!(energy_after_repeating(with_discount(10), Action::Rest), 50);
+    }
+
+    #[test]
+    fn run_matches_calling_step_repeatedly() {
+        let mut by_run = test_world(11, small_params());
+        let mut by_step = test_world(11, small_params());
+        let stats = by_run.run(200);
+        for _ in 0..200 {
+            by_step.step();
+        }
+        assert_eq!(by_run.step, 200);
+        assert_eq!(by_run.state_hash(), by_step.state_hash());
+        assert_eq!(stats.population, by_step.stats().population);
+    }
+
+    #[test]
+    fn run_stops_once_halted() {
+        let params = Params {
+            on_extinction: ExtinctionPolicy::Halt,
+            ..Params::default()
+        };
+        let mut world = World::with_params(1, params);
+        assert!(world.halted());
+        world.run(10);
+        assert_eq!(world.step, 0);
+
+        // step() は止まらない
+        world.step();
+        assert_eq!(world.step, 1);
+    }
+
+    #[test]
+    fn rng_checkpoint_round_trip_replays_identically() {
+        let mut world = test_world(13, small_params());
+        world.run(30);
+        // ステップの途中で取ったときのように、混ぜた回数が 0 でない状態にしておく
+        world.shuffle_deterministic(&mut [0; 4]);
+
+        let mut snapshot = Vec::new();
+        world.save_compact(&mut snapshot).unwrap();
+        let mut checkpoint = Vec::new();
+        world.rng_checkpoint().write_to(&mut checkpoint).unwrap();
+
+        let replay = |world: &mut World| {
+            let mut order: Vec<u32> = (0..20).collect();
+            world.shuffle_deterministic(&mut order);
+            let hashes: Vec<u64> = (0..50)
+                .map(|_| {
+                    world.step();
+                    world.state_hash()
+                })
+                .collect();
+            (order, hashes)
+        };
+        let expected = replay(&mut world);
+
+        let mut resumed =
+            World::load_compact(&mut &snapshot[..], world.params.clone()).unwrap();
+        // 読み込んだあとに乱数を進めてしまっても、チェックポイントで戻る
+        resumed.shuffle_deterministic(&mut [0; 4]);
+        resumed.shuffle_deterministic(&mut [0; 4]);
+        resumed.rng_for(RngStream::Food).random::<u64>();
+        let restored = RngCheckpoint::read_from(&mut &checkpoint[..]).unwrap();
+        resumed.restore_rng(&restored);
+        assert_eq!(replay(&mut resumed), expected);
+    }
+
+    /// 5000 匹のときの、更新順のバッファを使い回す効果を測る。
+    /// `cargo test --release --features bench-test -- --nocapture` で速さが出る
+    #[cfg(feature = "bench-test")]
+    #[test]
+    fn reused_order_buffer_at_5000_agents() {
+        const AGENTS: usize = 5000;
+        const ROUNDS: u32 = 500;
+        let params = Params {
+            max_agents_per_cell: 4,
+            ..Params::default()
+        };
+        let mut world = World::with_params(1, params);
+        let placement = world.params.initial_placement;
+        assert_eq!(world.seed_agents_with(placement, AGENTS), AGENTS);
+
+        let started = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            std::hint::black_box(world.agents_sorted_by(|a| a.energy));
+        }
+        let fresh = started.elapsed();
+
+        let mut buf = Vec::new();
+        world.agents_sorted_into(&mut buf, |a| a.energy);
+        let ptr = buf.as_ptr();
+        let started = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            world.agents_sorted_into(&mut buf, |a| a.energy);
+            std::hint::black_box(&buf);
+        }
+        let reused = started.elapsed();
+
+        // 使い回したほうは確保し直していないし、並びも同じ
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf, world.agents_sorted_by(|a| a.energy));
+        println!(
+            "{AGENTS} agents x {ROUNDS}: fresh Vec {fresh:?}, reused buffer {reused:?}"
+        );
+    }
+
+    #[test]
+    fn interact_radius_two_reaches_the_outer_ring() {
+        let healed = |interact_radius| {
+            let params = Params {
+                interact_radius,
+                ..Params::default()
             };
+            let agents = [
+                (Position { x: 10, y: 10 }, 50),
+                (Position { x: 11, y: 10 }, 50),
+                (Position { x: 12, y: 12 }, 50),
+                (Position { x: 13, y: 10 }, 50),
+            ];
+            let (mut world, ids) = placed_world(params, &agents);
+            world.interact_area(ids[0], Action::Heal, HEAL_AMOUNT);
+            [1, 2, 3].map(|i| world.agents[&ids[i]].energy)
+        };
+        assert_eq!(healed(1), [50 + HEAL_AMOUNT, 50, 50]);
+        assert_eq!(healed(2), [50 + HEAL_AMOUNT, 50 + HEAL_AMOUNT, 50]);
+    }
+
+    /// `step` の季節で餌を何度か湧かせたときの、餌の数と中心からの平均距離
+    fn food_pattern(step: u64) -> (usize, f32) {
+        let params = Params {
+            max_foods: WIDTH * HEIGHT,
+            ..Params::default()
+        };
+        let mut world = World::with_params(5, params);
+        world.step = step;
+        for _ in 0..40 {
+            world.spawn_foods();
+        }
+        let center = (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+        let dists: Vec<f32> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| world.foods[y][x])
+            .map(|(x, y)| (x as f32 - center.0).hypot(y as f32 - center.1))
+            .collect();
+        (dists.len(), dists.iter().sum::<f32>() / dists.len() as f32)
+    }
+
+    #[test]
+    fn winter_food_is_scarcer_and_more_concentrated() {
+        let (summer_count, summer_dist) = food_pattern(0);
+        let (winter_count, winter_dist) = food_pattern(SEASON_LENGTH);
+        assert!(winter_count < summer_count);
+        assert!(winter_dist < summer_dist * 0.9);
+    }
+
+    #[test]
+    fn input_layout_matches_get_input() {
+        let params = Params {
+            self_color_input: true,
+            ..Params::default()
+        };
+        let me = Position { x: 1, y: 10 };
+        let other = Position { x: 1, y: 8 };
+        let (mut world, ids) = placed_world(params, &[(me, 50), (other, 50)]);
+        world.agents.get_mut(&ids[0]).unwrap().color = [0.1, 0.2, 0.3];
+        world.agents.get_mut(&ids[1]).unwrap().color = [0.4, 0.5, 0.6];
+        world.foods[11][3] = true;
+        world.terrain[12][2] = Terrain::Wall;
+
+        let input = world.get_input(ids[0]);
+        assert_eq!(input.len(), world.params.input_size());
+        let at = |dx, dy, channel| input[InputLayout::index(dx, dy, channel)];
+
+        // 左端の外・壁の地形・餌・他の個体（とその色）
+        assert_eq!(at(-2, 0, InputLayout::WALL), 1.0);
+        assert_eq!(at(1, 2, InputLayout::WALL), 1.0);
+        assert_eq!(at(2, 1, InputLayout::FOOD), 1.0);
+        assert_eq!(at(0, -2, InputLayout::AGENT), 1.0);
+        assert_eq!(at(0, -2, InputLayout::GREEN), 0.5);
+        // 自分のマスに自分は見えない
+        assert_eq!(at(0, 0, InputLayout::AGENT), 0.0);
+        let self_color = InputLayout::SELF_COLOR..InputLayout::SELF_COLOR + 3;
+        assert_eq!(input.as_slice().unwrap()[self_color], [0.1, 0.2, 0.3]);
+
+        // decode しても同じマスに同じものが出てくる
+        let cells = InputLayout::decode(input.as_slice().unwrap());
+        let r = InputLayout::RADIUS;
+        let cell = |dx: isize, dy: isize| cells[(dy + r) as usize][(dx + r) as usize];
+        assert_eq!(cells.len(), INPUT_FIELD_LENGTH);
+        assert!(cells.iter().all(|row| row.len() == INPUT_FIELD_LENGTH));
+        assert!(cell(-2, 0).wall && cell(1, 2).wall && !cell(0, 0).wall);
+        assert!(cell(2, 1).food);
+        assert!(cell(0, -2).agent);
+        assert_eq!(cell(0, -2).color, [0.4, 0.5, 0.6]);
+        let seen = cells
+            .iter()
+            .flatten()
+            .filter(|cell| cell.agent || cell.food)
+            .count();
+        assert_eq!(seen, 2);
+    }
+
+    #[test]
+    fn stay_discount_only_refunds_the_basal_part() {
+        let params = |stay_metabolism_discount| Params {
+            action_costs: ActionCosts {
+                basal: 1,
+                stay: 2,
+                ..ActionCosts::default()
+            },
+            stay_metabolism_discount,
+            ..Params::default()
+        };
+        // 待機のコストそのものは割り引かない
+        assert_eq!(energy_after_repeating(params(0), Action::Stay), 50 - 10 * 3);
+        assert_eq!(energy_after_repeating(params(1), Action::Stay), 50 - 10 * 2);
+        assert_eq!(energy_after_repeating(params(5), Action::Stay), 50 - 10 * 2);
+        // 動いたら割引はない（基礎代謝 1 + 移動 1）
+        assert_eq!(energy_after_repeating(params(5), Action::Down), 50 - 10 * 2);
+
+        // 鎧の維持費（鎧 0.5 で 1）も割り引かない（待機 2 + 維持費 1）
+        let armored = Params {
+            armor: armored_params(0.5).armor,
+            ..params(5)
+        };
+        assert_eq!(energy_after_repeating(armored, Action::Stay), 50 - 10 * 3);
+    }
+
+    #[test]
+    fn food_spawning_resumes_when_population_crashes() {
+        let params = Params {
+            max_foods: 30,
+            dynamic_food_cap: Some(DynamicFoodCap {
+                low_population: 20,
+                max_boost: 3.0,
+            }),
+            ..Params::default()
+        };
+        let mut world = World::with_params(4, params);
+        world.seed_agents_with(Placement::Uniform, 40);
+        for _ in 0..50 {
+            world.spawn_foods();
+        }
+        assert_eq!(world.food_count(), 30);
+
+        // 全滅すると上限が 3 倍になって、また湧き始める
+        for id in world.living_ids().collect::<Vec<_>>() {
+            world.remove_agent(id);
+        }
+        assert_eq!(world.params.food_cap(0), 90);
+        for _ in 0..50 {
+            world.spawn_foods();
+        }
+        assert_eq!(world.food_count(), 90);
+    }
+
+    fn total_energy(world: &World) -> u32 {
+        world.agents.values().map(|a| a.energy).sum()
+    }
+
+    #[test]
+    fn gift_moves_energy_to_the_weakest_neighbor() {
+        let agents = [
+            (Position { x: 10, y: 10 }, 80),
+            (Position { x: 11, y: 10 }, 40),
+            (Position { x: 10, y: 11 }, 20),
+            (Position { x: 13, y: 10 }, 5),
+        ];
+        let (mut world, ids) = placed_world(Params::default(), &agents);
+        let before = total_energy(&world);
+        world.give_gift(ids[0]);
+        assert_eq!(total_energy(&world), before);
+        // 半分が、周りで一番弱っている個体へ（範囲外の個体には行かない）
+        let energies: Vec<u32> = ids.iter().map(|id| world.agents[id].energy).collect();
+        assert_eq!(energies, [40, 40, 60, 5]);
+    }
+
+    #[test]
+    fn gift_does_not_overflow_the_receiver() {
+        let agents = [
+            (Position { x: 10, y: 10 }, 80),
+            (Position { x: 11, y: 10 }, MAX_ENERGY - 10),
+        ];
+        let (mut world, ids) = placed_world(Params::default(), &agents);
+        world.give_gift(ids[0]);
+        assert_eq!(world.agents[&ids[0]].energy, 70);
+        assert_eq!(world.agents[&ids[1]].energy, MAX_ENERGY);
+
+        // 相手がいなければ何も起きない
+        let (mut world, ids) = placed_world(Params::default(), &agents[..1]);
+        world.give_gift(ids[0]);
+        assert_eq!(world.agents[&ids[0]].energy, 80);
+    }
+
+    #[test]
+    fn clustered_agents_stay_near_their_cluster_centers() {
+        let (clusters, radius) = (3, 2);
+        let mut world = World::with_params(8, Params::default());
+        // seed_agents_with と同じ乱数で、先に群れの中心を引いておく
+        let mut rng = world.rng_for(RngStream::Placement).clone();
+        let centers: Vec<(isize, isize)> = (0..clusters)
+            .map(|_| {
+                let x = rng.random_range(0..WIDTH) as isize;
+                (x, rng.random_range(0..HEIGHT) as isize)
+            })
+            .collect();
+
+        let added = world.seed_agents_with(Placement::Clustered { clusters, radius }, 30);
+        assert_eq!(added, 30);
+        for agent in world.agents.values() {
+            let (x, y) = (agent.pos.x as isize, agent.pos.y as isize);
+            assert!(
+                centers.iter().any(|&(cx, cy)| {
+                    (x - cx).abs() <= radius as isize && (y - cy).abs() <= radius as isize
+                }),
+                "{:?} is far from every cluster",
+                agent.pos
+            );
+        }
+        world.assert_consistent();
+    }
+
+    #[test]
+    fn center_ring_agents_sit_on_the_ring() {
+        let (radius, thickness) = (15.0, 2.0);
+        let mut world = World::with_params(8, Params::default());
+        let added =
+            world.seed_agents_with(Placement::CenterRing { radius, thickness }, 40);
+        assert_eq!(added, 40);
+        for agent in world.agents.values() {
+            // マスの左上に切り捨てるので、1マス分の余裕をみる
+            let dx = agent.pos.x as f32 + 0.5 - WIDTH as f32 / 2.0;
+            let dy = agent.pos.y as f32 + 0.5 - HEIGHT as f32 / 2.0;
+            let distance = dx.hypot(dy);
+            assert!(
+                (distance - radius).abs() <= thickness / 2.0 + 1.0,
+                "{distance}"
+            );
+        }
+    }
+
+    #[test]
+    fn reproduction_is_blocked_exactly_at_the_cap() {
+        let full = [
+            (Position { x: 10, y: 10 }, MAX_ENERGY),
+            (Position { x: 30, y: 30 }, MAX_ENERGY),
+        ];
+        let capped = |charge_at_capacity| Params {
+            max_population: Some(3),
+            charge_at_capacity,
+            ..Params::default()
+        };
+
+        let (mut world, ids) = placed_world(capped(true), &full);
+        assert!(!world.at_capacity());
+        assert!(world.try_reproduce(ids[0]).is_some());
+        assert_eq!(world.agents.len(), 3);
+        assert!(world.at_capacity());
+        // 上限ちょうどなので産めない。混雑のコストは払う
+        assert!(world.try_reproduce(ids[1]).is_none());
+        assert_eq!(world.agents.len(), 3);
+        assert_eq!(world.agents[&ids[1]].energy, MAX_ENERGY - REPRODUCE_COST);
+
+        // 払わない設定
+        let (mut world, ids) = placed_world(capped(false), &full);
+        world.try_reproduce(ids[0]).unwrap();
+        assert!(world.try_reproduce(ids[1]).is_none());
+        assert_eq!(world.agents[&ids[1]].energy, MAX_ENERGY);
+    }
+
+    /// 周り 8 マスを囲まれた個体が1回攻撃したあとの、自分のエネルギー
+    fn surrounded_attacker_energy(attack_recoil: u32, energy: u32) -> u32 {
+        let params = Params {
+            attack_recoil,
+            attack_gain: AttackGain::Fraction(0.0),
+            ..Params::default()
+        };
+        let center = Position { x: 10, y: 10 };
+        let mut agents = vec![(center, energy)];
+        let world = World::with_params(1, Params::default());
+        agents.extend(world.neighbors(center, 1).map(|pos| (pos, 50)));
+        assert_eq!(agents.len(), 9);
+
+        let (mut world, ids) = placed_world(params, &agents);
+        let damage = world.params.attack_damage;
+        world.interact_area(ids[0], Action::Attack, damage);
+        world.agents[&ids[0]].energy
+    }
+
+    #[test]
+    fn attack_recoil_scales_with_targets_hit() {
+        assert_eq!(surrounded_attacker_energy(0, 50), 50 - INTERACT_COST);
+        assert_eq!(
+            surrounded_attacker_energy(2, 50),
+            50 - INTERACT_COST - 8 * 2
+        );
+        // 払いきれなくても 0 で止まる
+        assert_eq!(surrounded_attacker_energy(5, 30), 0);
+        assert_eq!(surrounded_attacker_energy(u32::MAX, 50), 0);
+    }
+
+    #[test]
+    fn entering_mud_costs_extra() {
+        let params = Params::default();
+        let terrain = params.terrain.clone();
+        let (mut world, id) = lone_agent(params, Position { x: 10, y: 10 }, 50);
+        world.terrain[10][11] = Terrain::Mud;
+        world.terrain[10][12] = Terrain::Rough;
+        world.terrain[10][13] = Terrain::Plains;
+
+        let movement = world.params.action_costs.movement;
+        let mut expected = 50;
+        for extra in [terrain.mud_cost, terrain.rough_cost, 0] {
+            world.move_agent(id, Direction::Right);
+            expected -= movement + extra;
+            assert_eq!(world.agents[&id].energy, expected);
+        }
+        assert_eq!(world.agents[&id].pos, Position { x: 13, y: 10 });
+        // かかるのは入るマスの分だけ（平地から荒地に戻る）
+        world.move_agent(id, Direction::Left);
+        assert_eq!(
+            world.agents[&id].energy,
+            expected - movement - terrain.rough_cost
+        );
+    }
+
+    #[test]
+    fn own_species_perception_hides_distant_colors() {
+        let seen = |perception| {
+            let params = Params {
+                perception,
+                ..Params::default()
+            };
+            let agents = [
+                (Position { x: 10, y: 10 }, 50),
+                (Position { x: 11, y: 10 }, 50),
+                (Position { x: 9, y: 10 }, 50),
+            ];
+            let (mut world, ids) = placed_world(params, &agents);
+            for (id, color) in
+                ids.iter()
+                    .zip([[1.0, 0.0, 0.0], [0.9, 0.1, 0.0], [0.0, 0.0, 1.0]])
+            {
+                world.agents.get_mut(id).unwrap().color = color;
+            }
+            let input = world.get_input(ids[0]);
+            [1, -1].map(|dx| {
+                let agent = input[InputLayout::index(dx, 0, InputLayout::AGENT)];
+                let blue = input[InputLayout::index(dx, 0, InputLayout::BLUE)];
+                (agent, blue)
+            })
+        };
+
+        assert_eq!(seen(Perception::All), [(1.0, 0.0), (1.0, 1.0)]);
+        // 青い個体は色が遠いので、いないように見える（色も 0）
+        let own = Perception::OwnSpecies {
+            max_color_distance: 0.5,
+        };
+        assert_eq!(seen(own), [(1.0, 0.0), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn agents_sorted_by_breaks_ties_by_id() {
+        let agents = [
+            (Position { x: 1, y: 1 }, 30),
+            (Position { x: 2, y: 1 }, 10),
+            (Position { x: 3, y: 1 }, 30),
+            (Position { x: 4, y: 1 }, 10),
+            (Position { x: 5, y: 1 }, 20),
+        ];
+        let (world, ids) = placed_world(Params::default(), &agents);
+        let sorted = world.agents_sorted_by(|a| a.energy);
+        assert_eq!(sorted, [ids[1], ids[3], ids[4], ids[0], ids[2]]);
+
+        // 降順にしたいときは Reverse。同じ値ならやっぱりID順
+        let sorted = world.agents_sorted_by(|a| Reverse(a.energy));
+        assert_eq!(sorted, [ids[0], ids[2], ids[4], ids[1], ids[3]]);
+
+        // バッファに入れるほうも同じ並び（前の中身は消える）
+        let mut buf = vec![usize::MAX; 8];
+        world.agents_sorted_into(&mut buf, |a| a.energy);
+        assert_eq!(buf, world.agents_sorted_by(|a| a.energy));
+    }
+
+    /// 餌のない世界に1匹だけ置いて、何ステップ目に死ぬか
+    fn death_step(
+        death_energy_threshold: u32,
+        death_grace_steps: u32,
+        energy: u32,
+    ) -> u64 {
+        let params = Params {
+            death_energy_threshold,
+            death_grace_steps,
+            max_foods: 0,
+            ..Params::default()
+        };
+        let (mut world, id) = lone_agent(params, Position { x: 10, y: 10 }, energy);
+        while world.agents.contains_key(&id) {
+            world.step();
+            assert!(world.step < 1000, "never died");
+        }
+        world.step
+    }
+
+    #[test]
+    fn death_threshold_and_grace_decide_when_agents_die() {
+        // しきい値 0 なら、エネルギーが残っているうちは死なない
+        assert!(death_step(0, 0, 10) > 1);
+        // しきい値以下なら、次のステップの頭で死ぬ
+        assert_eq!(death_step(10, 0, 10), 1);
+        assert_eq!(death_step(10, 0, 0), 1);
+        // 猶予があれば、その分だけ持ちこたえる
+        assert_eq!(death_step(10, 2, 10), 3);
+    }
+
+    #[test]
+    fn founders_start_with_init_energy_but_children_do_not() {
+        let founders = |init_energy| {
+            let params = Params {
+                init_energy,
+                ..Params::default()
+            };
+            let mut world = World::with_params(2, params);
+            world.seed_agents_with(Placement::Uniform, 10);
+            world
+        };
+
+        let mut world = founders(20);
+        assert!(world.agents.values().all(|a| a.energy == 20));
+        // 最大より多くは持てない
+        assert!(
+            founders(500)
+                .agents
+                .values()
+                .all(|a| a.energy == MAX_ENERGY)
+        );
+
+        // 子供は CHILD_INIT_ENERGY のまま
+        let parent = world.living_ids().min().unwrap();
+        world.agents.get_mut(&parent).unwrap().energy = MAX_ENERGY;
+        let child = world.try_reproduce(parent).unwrap();
+        assert_eq!(world.agents[&child].energy, CHILD_INIT_ENERGY);
+    }
+
+    #[test]
+    fn fractional_threshold_lets_agents_breed_at_eighty_percent() {
+        let params = |reproduce_threshold| Params {
+            reproduce_threshold,
+            ..Params::default()
+        };
+        let at_eighty = [(Position { x: 10, y: 10 }, MAX_ENERGY * 8 / 10)];
+
+        let (mut world, ids) = placed_world(params(ReproduceThreshold::Full), &at_eighty);
+        assert!(world.try_reproduce(ids[0]).is_none());
+        assert_eq!(world.agents[&ids[0]].energy, MAX_ENERGY * 8 / 10);
+
+        let fraction = ReproduceThreshold::Fraction(0.8);
+        let (mut world, ids) = placed_world(params(fraction), &at_eighty);
+        assert!(world.try_reproduce(ids[0]).is_some());
+        assert_eq!(
+            world.agents[&ids[0]].energy,
+            MAX_ENERGY * 8 / 10 - REPRODUCE_COST
+        );
+
+        let just_below = [(Position { x: 10, y: 10 }, MAX_ENERGY * 8 / 10 - 1)];
+        let (mut world, ids) = placed_world(params(fraction), &just_below);
+        assert!(world.try_reproduce(ids[0]).is_none());
+    }
 
-            // 世界に登録
-            self.add_agent(child, child_pos);
+    /// 空の世界で `calls` 回餌を湧かせたあとの餌
+    fn spawned_foods(params: Params, calls: usize) -> Vec<Vec<bool>> {
+        let mut world = World::with_params(6, params);
+        for _ in 0..calls {
+            world.spawn_foods();
         }
+        world.foods
+    }
+
+    #[test]
+    fn early_stop_does_not_change_unsaturated_food_pattern() {
+        let limited = Params {
+            food_spawn_target: Some(10_000),
+            food_spawn_max_misses: Some(10_000),
+            ..Params::default()
+        };
+        assert_eq!(
+            spawned_foods(limited, 5),
+            spawned_foods(Params::default(), 5)
+        );
+
+        // 目安の数で打ち切っても、置いた場所はいつもと同じ並びの最初のほう
+        let full = spawned_foods(Params::default(), 1);
+        let stopped = spawned_foods(
+            Params {
+                food_spawn_target: Some(10),
+                ..Params::default()
+            },
+            1,
+        );
+        let placed: Vec<(usize, usize)> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| stopped[y][x])
+            .collect();
+        assert_eq!(placed.len(), 10);
+        assert!(placed.iter().all(|&(x, y)| full[y][x]));
+    }
+
+    #[test]
+    fn palette_founders_only_use_palette_colors() {
+        let palette = vec![
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0],
+        ];
+        let params = Params {
+            founder_colors: FounderColors::Palette(palette.clone()),
+            ..Params::default()
+        };
+        let mut world = World::with_params(3, params);
+        world.seed_agents_with(Placement::Uniform, 20);
+        assert!(world.agents.values().all(|a| palette.contains(&a.color)));
+        // ID順に順番に割り当てるので、全部の色が出てくる
+        for color in &palette {
+            assert!(world.agents.values().any(|a| a.color == *color));
+        }
+
+        // 空のパレットはランダムと同じ
+        let params = Params {
+            founder_colors: FounderColors::Palette(Vec::new()),
+            ..Params::default()
+        };
+        let mut world = World::with_params(3, params);
+        world.seed_agents_with(Placement::Uniform, 20);
+        let first = world.agents.values().next().unwrap().color;
+        assert!(world.agents.values().any(|a| a.color != first));
+    }
+
+    #[test]
+    fn agents_with_a_mismatched_brain_are_rejected() {
+        let mut world = World::with_params(1, Params::default());
+        let (input, hidden, output) = world.params.brain_shape();
+        let brain = Brain::new(
+            Array2::zeros((hidden, input + 3)),
+            Array1::zeros(hidden),
+            Array2::zeros((output, hidden)),
+            Array1::zeros(output),
+        );
+        let spec = AgentSpec {
+            brain: Some(brain),
+            ..AgentSpec::default()
+        };
+        assert!(world.try_add_agent(Position { x: 5, y: 5 }, spec).is_none());
+        assert!(world.agents.is_empty());
+        assert_eq!(world.next_id, 0);
+    }
+
+    #[test]
+    fn moving_onto_food_reports_the_energy_gained() {
+        assert_eq!((FOOD_ENERGY, MAX_ENERGY), (60, 100));
+        let (mut world, id) =
+            lone_agent(Params::default(), Position { x: 10, y: 10 }, 20);
+        world.foods[10][11] = true;
+        world.move_agent(id, Direction::Right);
+
+        // 移動コストを払ってから食べる（20 - 1 + 60）
+        assert_eq!(
+            world.events,
+            [StepEvent::Eat {
+                id,
+                pos: Position { x: 11, y: 10 },
+                food_value: 60,
+                gained: 60,
+            }]
+        );
+        assert_eq!(world.agents[&id].energy, 79);
+        assert!(!world.foods[10][11]);
+
+        // 満腹に近いと、実際に増えた分しか報告しない（78 + 22 で満タン）
+        world.events.clear();
+        world.foods[10][12] = true;
+        world.move_agent(id, Direction::Right);
+        let [StepEvent::Eat { gained, .. }] = world.events[..] else {
+            panic!("expected one Eat event, got {:?}", world.events);
+        };
+        assert_eq!(gained, 22);
+        assert_eq!(world.agents[&id].energy, MAX_ENERGY);
+    }
+
+    #[test]
+    fn ascii_dump_shows_agents_and_food() {
+        let params = Params {
+            max_agents_per_cell: 2,
+            ..Params::default()
+        };
+        let agents = [
+            (Position { x: 2, y: 1 }, 50),
+            (Position { x: 4, y: 3 }, 50),
+            (Position { x: 4, y: 3 }, 50),
+        ];
+        let (mut world, _) = placed_world(params, &agents);
+        world.foods[0][0] = true;
+        world.foods[1][2] = true;
+
+        let dump = world.to_ascii();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), HEIGHT);
+        assert!(lines.iter().all(|line| line.chars().count() == WIDTH));
+        assert!(lines[0].starts_with("*...."));
+        // 餌の上にいる個体は個体のほうが見える
+        assert!(lines[1].starts_with("..@.."));
+        assert!(lines[3].starts_with("....2"));
+        assert_eq!(dump.matches(['*', '@', '2']).count(), 3);
+    }
+
+    #[test]
+    fn each_action_deducts_its_configured_cost() {
+        // 全部違う値にして、取り違えたら分かるようにする
+        let costs = ActionCosts {
+            basal: 1,
+            movement: 2,
+            sprint: 3,
+            attack: 4,
+            heal: 5,
+            gift: 6,
+            stay: 7,
+            rest: 8,
+            eat: 9,
+        };
+        for action in Action::ALL {
+            let params = Params {
+                action_costs: costs.clone(),
+                ..Params::default()
+            };
+            // 周りに誰もいない・餌もない真ん中で1回だけ
+            let (mut world, id) = lone_agent(params, Position { x: 25, y: 25 }, 50);
+            let color = world.agents[&id].color;
+            world.apply_action(id, action, color);
+            let expected = 50 - costs.basal(action) - costs.of(action);
+            assert_eq!(world.agents[&id].energy, expected, "{action:?}");
+        }
+    }
+
+    #[test]
+    fn default_action_costs_match_the_old_constants() {
+        let costs = ActionCosts::default();
+        assert_eq!(costs.basal(Action::Up) + costs.of(Action::Up), 2);
+        assert_eq!(costs.basal(Action::Stay) + costs.of(Action::Stay), 1);
+        assert_eq!(costs.of(Action::Attack), INTERACT_COST);
+        assert_eq!(costs.of(Action::Heal), INTERACT_COST);
+        assert_eq!(costs.basal(Action::Rest), 0);
+    }
+
+    /// エネルギー 0 の1匹だけの世界（最初のステップで死ぬ）を `steps` ステップ回す。
+    /// 撒き直した個体が食べて増えないように、餌は湧かせない
+    fn run_doomed_world(on_extinction: ExtinctionPolicy, steps: usize) -> World {
+        let params = Params {
+            on_extinction,
+            max_foods: 0,
+            ..Params::default()
+        };
+        let (mut world, _) = lone_agent(params, Position { x: 10, y: 10 }, 0);
+        world.run(steps);
+        world
+    }
+
+    #[test]
+    fn extinction_is_reported_under_every_policy() {
+        let world = run_doomed_world(ExtinctionPolicy::Continue, 10);
+        assert_eq!(world.extinction_step, Some(1));
+        assert_eq!(world.stats().extinction_step, Some(1));
+        assert!(world.agents.is_empty() && !world.halted());
+        assert_eq!(world.step, 10);
+
+        let world = run_doomed_world(ExtinctionPolicy::Halt, 10);
+        assert_eq!(world.extinction_step, Some(1));
+        assert!(world.halted());
+        assert_eq!(world.step, 1);
+
+        let world = run_doomed_world(ExtinctionPolicy::Reseed { count: 5 }, 1);
+        assert_eq!(world.extinction_step, Some(1));
+        assert_eq!(world.agents.len(), 5);
+        assert!(!world.halted());
+        world.assert_consistent();
+    }
+
+    #[test]
+    fn self_color_inputs_carry_own_color_and_children_match() {
+        let params = Params {
+            self_color_input: true,
+            ..Params::default()
+        };
+        assert_eq!(params.input_size(), INPUT_SIZE + RGB_COLOR_SIZE);
+        let (mut world, ids) =
+            placed_world(params, &[(Position { x: 10, y: 10 }, MAX_ENERGY)]);
+        let parent = ids[0];
+        world.agents.get_mut(&parent).unwrap().color = [0.25, 0.5, 0.75];
+
+        let input = world.get_input(parent);
+        assert_eq!(input.len(), INPUT_SIZE + RGB_COLOR_SIZE);
+        assert_eq!(input.as_slice().unwrap()[INPUT_SIZE..], [0.25, 0.5, 0.75]);
+
+        // 子供の脳も、増えた入力の分だけ大きい
+        let child = world.try_reproduce(parent).unwrap();
+        let shape = world.params.brain_shape();
+        assert_eq!(world.agents[&parent].brain.shape(), shape);
+        assert_eq!(world.agents[&child].brain.shape(), shape);
+        let output = world.agents[&child]
+            .brain
+            .forward(&world.get_input(child), 0.0);
+        assert_eq!(output.len(), world.params.output_layout().size());
+    }
+
+    /// 真ん中の個体が、エネルギーの違う3匹に囲まれて攻撃・回復したあとの、周りのエネルギー
+    fn after_targeted(action: Action, targeting: InteractTargeting) -> [u32; 3] {
+        let params = Params {
+            attack_damage: 10,
+            attack_targeting: targeting,
+            heal_targeting: targeting,
+            ..Params::default()
+        };
+        let agents = [
+            (Position { x: 10, y: 10 }, 50),
+            (Position { x: 11, y: 10 }, 40),
+            (Position { x: 9, y: 10 }, 20),
+            (Position { x: 10, y: 11 }, 70),
+        ];
+        let (mut world, ids) = placed_world(params, &agents);
+        let amount = match action {
+            Action::Attack => world.params.attack_damage,
+            _ => HEAL_AMOUNT,
+        };
+        world.interact_area(ids[0], action, amount);
+        [1, 2, 3].map(|i| world.agents[&ids[i]].energy)
+    }
+
+    #[test]
+    fn targeting_picks_weakest_strongest_or_all() {
+        let attacked = |targeting| after_targeted(Action::Attack, targeting);
+        assert_eq!(attacked(InteractTargeting::All), [30, 10, 60]);
+        assert_eq!(attacked(InteractTargeting::WeakestOnly), [40, 10, 70]);
+        assert_eq!(attacked(InteractTargeting::StrongestOnly), [40, 20, 60]);
+        // 回復は一番弱っている個体だけに
+        let healed = after_targeted(Action::Heal, InteractTargeting::WeakestOnly);
+        assert_eq!(healed, [40, 20 + HEAL_AMOUNT, 70]);
+    }
+
+    /// 2匹に囲まれて1回回復したあとの [自分, 周り, 周り] のエネルギー
+    fn after_heal(heal_mode: HealMode, healer_energy: u32) -> [u32; 3] {
+        let params = Params {
+            heal_mode,
+            ..Params::default()
+        };
+        let agents = [
+            (Position { x: 10, y: 10 }, healer_energy),
+            (Position { x: 11, y: 10 }, 40),
+            (Position { x: 9, y: 10 }, MAX_ENERGY - 5),
+        ];
+        let (mut world, ids) = placed_world(params, &agents);
+        world.interact_area(ids[0], Action::Heal, HEAL_AMOUNT);
+        [0, 1, 2].map(|i| world.agents[&ids[i]].energy)
+    }
+
+    #[test]
+    fn transfer_heal_costs_the_healer_exactly_what_others_gain() {
+        // 今まで通り：回復した分は湧いてくる
+        let free = after_heal(HealMode::Free, 50);
+        assert_eq!(free, [50 - INTERACT_COST, 40 + HEAL_AMOUNT, MAX_ENERGY]);
+
+        // 渡した分（8 と、溢れない 5）とコストだけ減る
+        let transfer = after_heal(HealMode::Transfer, 50);
+        let given = HEAL_AMOUNT + 5;
+        assert_eq!(
+            transfer,
+            [50 - INTERACT_COST - given, 40 + HEAL_AMOUNT, MAX_ENERGY]
+        );
+        assert_eq!(
+            transfer.iter().sum::<u32>(),
+            50 + 40 + MAX_ENERGY - 5 - INTERACT_COST
+        );
+
+        // 手持ちより多くは渡せない
+        let poor = after_heal(HealMode::Transfer, INTERACT_COST + 3);
+        assert_eq!(poor[0], 0);
+        assert_eq!(poor[1] + poor[2], 40 + MAX_ENERGY - 5 + 3);
+    }
+
+    /// (10, 10) の満タンの個体が、(12, 10) に餌がある状態で産んだ子供の位置（シードごと）
+    fn birth_spots(birth_placement: BirthPlacement) -> Vec<Position> {
+        (0..20)
+            .map(|seed| {
+                let params = Params {
+                    birth_placement,
+                    ..Params::default()
+                };
+                let mut world = World::with_params(seed, params);
+                let spec = AgentSpec {
+                    energy: Some(MAX_ENERGY),
+                    ..AgentSpec::default()
+                };
+                let parent = world
+                    .try_add_agent(Position { x: 10, y: 10 }, spec)
+                    .unwrap();
+                world.foods[10][12] = true;
+                let child = world.try_reproduce(parent).unwrap();
+                world.agents[&child].pos
+            })
+            .collect()
+    }
+
+    #[test]
+    fn toward_food_places_children_next_to_food() {
+        // 餌のとなりは右の列の3マスだけ
+        for pos in birth_spots(BirthPlacement::TowardFood) {
+            assert_eq!(pos.x, 11, "child at {pos:?} is not next to the food");
+        }
+        // ランダムなら左や上下にも産まれる
+        assert!(
+            birth_spots(BirthPlacement::Random)
+                .iter()
+                .any(|p| p.x != 11)
+        );
+    }
+
+    /// 離れて並んだ満タンの10体を1ステップ動かしたときの出生数。
+    /// 行動のコストを払っても産めるように、しきい値は下げておく
+    fn births_in_one_step(birth_budget: Option<usize>) -> usize {
+        let params = Params {
+            birth_budget,
+            reproduce_threshold: ReproduceThreshold::Absolute(REPRODUCE_COST),
+            ..Params::default()
+        };
+        let agents: Vec<_> = (0..10)
+            .map(|i| {
+                (
+                    Position {
+                        x: 4 * i + 2,
+                        y: 10,
+                    },
+                    MAX_ENERGY,
+                )
+            })
+            .collect();
+        let (mut world, _) = placed_world(params, &agents);
+        world.step();
+        world
+            .events
+            .iter()
+            .filter(|e| matches!(e, StepEvent::Birth { .. }))
+            .count()
+    }
+
+    #[test]
+    fn birth_budget_bounds_births_per_step() {
+        assert_eq!(births_in_one_step(None), 10);
+        assert_eq!(births_in_one_step(Some(3)), 3);
+        assert_eq!(births_in_one_step(Some(0)), 0);
+    }
+
+    #[test]
+    fn same_seed_and_step_shuffle_identically() {
+        let shuffled = |world: &mut World| {
+            let mut items: Vec<u32> = (0..50).collect();
+            world.shuffle_deterministic(&mut items);
+            items
+        };
+        // 個体や設定が違っても、シードとステップが同じなら同じ混ぜ方
+        let mut a = test_world(5, small_params());
+        let mut b = World::with_params(5, Params::default());
+        a.run(3);
+        b.run(3);
+        let first = shuffled(&mut a);
+        assert_eq!(first, shuffled(&mut b));
+        assert_ne!(first, (0..50).collect::<Vec<_>>());
+
+        // 同じステップの2回目は別の混ぜ方で、それもそろう
+        let second = shuffled(&mut a);
+        assert_ne!(first, second);
+        assert_eq!(second, shuffled(&mut b));
+
+        // シードかステップが違えば別の混ぜ方
+        let mut other_seed = World::with_params(6, Params::default());
+        other_seed.run(3);
+        assert_ne!(first, shuffled(&mut other_seed));
+        a.step();
+        assert_ne!(first, shuffled(&mut a));
+    }
+
+    /// (10, 10) で死んだ個体が落とした餌の場所。`max_foods` が餌の上限
+    fn corpse_foods(drop: CorpseDrop, count: usize, max_foods: usize) -> Vec<Position> {
+        let params = Params {
+            corpse_drop: Some(drop),
+            max_foods,
+            ..Params::default()
+        };
+        let (mut world, id) = lone_agent(params, Position { x: 10, y: 10 }, 0);
+        world.remove_agent(id);
+        world.drop_corpse_food(Position { x: 10, y: 10 }, count);
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| Position { x, y }))
+            .filter(|p| world.foods[p.y][p.x])
+            .collect()
+    }
+
+    #[test]
+    fn corpse_drop_yields_the_configured_amount_within_the_food_cap() {
+        assert_eq!(CorpseAmount::Fixed { count: 4 }.count(0, MAX_ENERGY), 4);
+        let by_max = CorpseAmount::MaxEnergy { fraction: 1.2 };
+        assert_eq!(by_max.count(0, MAX_ENERGY), 2);
+        let by_rest = CorpseAmount::RemainingEnergy { fraction: 0.6 };
+        assert_eq!(by_rest.count(MAX_ENERGY, MAX_ENERGY), 1);
+        assert_eq!(by_rest.count(0, MAX_ENERGY), 0);
+
+        let spread = CorpseDrop {
+            amount: CorpseAmount::Fixed { count: 5 },
+            spread_radius: 1,
+        };
+        let foods = corpse_foods(spread, 5, 100);
+        assert_eq!(foods.len(), 5);
+        assert!(foods.contains(&Position { x: 10, y: 10 }));
+        assert!(
+            foods
+                .iter()
+                .all(|p| p.x.abs_diff(10) <= 1 && p.y.abs_diff(10) <= 1)
+        );
+
+        // 広げなければ死んだマスの1個だけ
+        assert_eq!(corpse_foods(CorpseDrop::default(), 5, 100).len(), 1);
+        // 餌の上限を超えては置かない
+        assert_eq!(corpse_foods(spread, 5, 3).len(), 3);
+        assert!(corpse_foods(spread, 5, 0).is_empty());
+    }
+
+    #[test]
+    fn killed_agents_die_at_the_next_step() {
+        let (mut world, id) =
+            lone_agent(Params::default(), Position { x: 10, y: 10 }, 80);
+        assert!(world.kill_agent(id));
+        // その場ではまだいる
+        assert_eq!(world.agents[&id].energy, 0);
+
+        world.step();
+        assert!(!world.agents.contains_key(&id));
+        assert!(world.events.contains(&StepEvent::Death {
+            id,
+            pos: Position { x: 10, y: 10 },
+            cause: DeathCause::Starvation,
+        }));
+        assert!(!world.kill_agent(id));
+    }
+
+    #[test]
+    fn feeding_adds_energy_up_to_the_max() {
+        let (mut world, id) =
+            lone_agent(Params::default(), Position { x: 10, y: 10 }, 30);
+        assert_eq!(world.feed_agent(id, 50), Some(50));
+        assert_eq!(world.agents[&id].energy, 80);
+        // 満タンを超える分は捨てる
+        assert_eq!(world.feed_agent(id, 50), Some(MAX_ENERGY - 80));
+        assert_eq!(world.agents[&id].energy, MAX_ENERGY);
+        assert_eq!(world.feed_agent(id + 1, 50), None);
+    }
+
+    #[test]
+    fn agent_spec_overrides_only_what_it_sets() {
+        let mut plain = World::with_params(1, Params::default());
+        let mut custom = World::with_params(1, Params::default());
+        let pos = Position { x: 5, y: 5 };
+        let base = plain.add_new_agent(pos).unwrap();
+        // 別の個体の脳を借りる
+        let donor = plain.add_new_agent(Position { x: 6, y: 5 }).unwrap();
+        let brain = plain.agents[&donor].brain.clone();
+
+        let spec = AgentSpec {
+            color: Some([0.1, 0.2, 0.3]),
+            energy: Some(MAX_ENERGY + 50),
+            brain: Some(brain.clone()),
+            facing: Some(Direction::Left),
+        };
+        let id = custom.try_add_agent(pos, spec).unwrap();
+        assert_eq!(id, base);
+        let (agent, random) = (&custom.agents[&id], &plain.agents[&base]);
+        assert_eq!(agent.color, [0.1, 0.2, 0.3]);
+        // 最大エネルギーまでしか入らない
+        assert_eq!(agent.energy, agent.max_energy);
+        assert_eq!(agent.brain, brain);
+        assert_eq!(agent.facing, Direction::Left);
+        // 書かなかった部分はおまかせのときと同じ
+        assert_eq!(agent.max_energy, random.max_energy);
+        assert_eq!(agent.lifespan, random.lifespan);
+
+        // 上書きしても乱数の進みは同じなので、次の個体はそろう
+        let next = custom.add_new_agent(Position { x: 6, y: 5 }).unwrap();
+        assert_eq!(custom.agents[&next], plain.agents[&donor]);
+
+        // フィールドの外や、もういっぱいのマスには置けない
+        let full = custom.params.max_agents_per_cell.max(1);
+        for _ in 1..full {
+            custom.add_new_agent(pos).unwrap();
+        }
+        assert!(custom.try_add_agent(pos, AgentSpec::default()).is_none());
+        let outside = Position { x: WIDTH, y: 0 };
+        assert!(
+            custom
+                .try_add_agent(outside, AgentSpec::default())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn armor_reduces_damage_from_a_fixed_attack() {
+        // 20 ダメージの攻撃を1回受けたときに減ったエネルギー
+        let taken = |armor: f32| {
+            let (mut world, [actor, target]) =
+                adjacent_pair(Params::default(), 50, MAX_ENERGY);
+            world.agents.get_mut(&target).unwrap().armor = armor;
+            world.interact_area(actor, Action::Attack, 20);
+            MAX_ENERGY - world.agents[&target].energy
+        };
+        assert_eq!(taken(0.0), 20);
+        assert_eq!(taken(0.25), 15);
+        assert_eq!(taken(0.5), 10);
+        assert_eq!(taken(1.0), 0);
+    }
+
+    /// 鎧 `armor` の個体だけの設定
+    fn armored_params(armor: f32) -> Params {
+        Params {
+            armor: Some(ArmorParams {
+                initial: armor,
+                ..ArmorParams::default()
+            }),
+            ..Params::default()
+        }
+    }
+
+    #[test]
+    fn any_armor_costs_more_than_none() {
+        let bare = energy_after_repeating(armored_params(0.0), Action::Stay);
+        for armor in [0.01, 0.1, 0.24, 0.5, 1.0] {
+            let armored = energy_after_repeating(armored_params(armor), Action::Stay);
+            assert!(armored < bare, "armor {armor}: {armored} vs {bare}");
+        }
+        // 休息中は鎧の分もかからない
+        assert_eq!(
+            energy_after_repeating(armored_params(1.0), Action::Rest),
+            energy_after_repeating(armored_params(0.0), Action::Rest)
+        );
     }
 }
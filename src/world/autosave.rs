@@ -0,0 +1,226 @@
+//! 長い実行のための自動保存。
+//!
+//! 中身は `save_compact` に、長さとチェックサムをつけたもの。
+//! ファイルは `<パス>.0` と `<パス>.1` を交互に使う。どちらも一時ファイルに書いてから
+//! 名前を変えるので、書いている途中で落ちても、前に書いた方は無事に残る。
+//! 読み込むときは、壊れていないもののうちステップが一番進んでいるものを使う。
+//!
+//! 形式（数値は全部リトルエンディアン）:
+//! - マジック `b"RKAS"`、ステップ (u64)、中身の長さ (u64)
+//! - 中身（`save_compact` の出力）
+//! - 中身の FNV-1a (u64)
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    World,
+    snapshot::{fnv1a, invalid},
+};
+use crate::params::Params;
+
+const MAGIC: &[u8; 4] = b"RKAS";
+
+/// 交互に使うファイルの数
+pub const AUTOSAVE_SLOTS: u64 = 2;
+
+/// 何ステップごとに、どこに自動保存するか
+#[derive(Debug, Clone)]
+pub struct Autosave {
+    pub path: PathBuf,
+    /// 何ステップごとに保存するか（0 にはしない）
+    pub every: u64,
+}
+
+impl Autosave {
+    pub fn new(path: PathBuf, every: u64) -> Self {
+        Self {
+            path,
+            every: every.max(1),
+        }
+    }
+
+    /// このステップで保存するか
+    pub fn is_due(&self, step: u64) -> bool {
+        step.is_multiple_of(self.every)
+    }
+
+    /// `slot` 番目のファイルのパス（`<パス>.<slot>`）
+    pub fn slot_path(path: &Path, slot: u64) -> PathBuf {
+        let mut name = OsString::from(path.as_os_str());
+        name.push(format!(".{slot}"));
+        PathBuf::from(name)
+    }
+
+    /// 今の世界を保存して、書いたファイルのパスを返す。
+    /// どのファイルに書くかはステップで決まるので、続けて保存すると交互になる
+    pub fn save(&self, world: &World) -> io::Result<PathBuf> {
+        let slot = (world.step / self.every) % AUTOSAVE_SLOTS;
+        let path = Self::slot_path(&self.path, slot);
+
+        let mut payload = Vec::new();
+        world.save_compact(&mut payload)?;
+
+        let mut tmp_name = OsString::from(path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp = PathBuf::from(tmp_name);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(MAGIC)?;
+            file.write_all(&world.step.to_le_bytes())?;
+            file.write_all(&(payload.len() as u64).to_le_bytes())?;
+            file.write_all(&payload)?;
+            file.write_all(&fnv1a(&payload).to_le_bytes())?;
+            // 名前を変える前に、中身がちゃんとディスクに載るのを待つ
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+
+    /// `path` の自動保存のうち、壊れていないものでステップが一番進んでいるものを読み込む。
+    /// 設定は保存していないので、保存したときと同じものを渡す。
+    /// 読めるものが一つもなければエラー（それぞれ読めなかった理由つき）
+    pub fn load_latest(path: &Path, params: Params) -> io::Result<World> {
+        let mut latest: Option<World> = None;
+        let mut errors = Vec::new();
+        for slot in 0..AUTOSAVE_SLOTS {
+            let slot_path = Self::slot_path(path, slot);
+            match read_slot(&slot_path, params.clone()) {
+                Ok(world) => {
+                    if latest.as_ref().is_none_or(|best| world.step > best.step) {
+                        latest = Some(world);
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {e}", slot_path.display())),
+            }
+        }
+        latest.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no usable autosave ({})", errors.join("; ")),
+            )
+        })
+    }
+}
+
+/// 自動保存のファイルを一つ読む。長さかチェックサムが合わなければエラー
+fn read_slot(path: &Path, params: Params) -> io::Result<World> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    let header = MAGIC.len() + 16;
+    if bytes.len() < header + 8 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a rikulife autosave"));
+    }
+    let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let step = read_u64(MAGIC.len());
+    let len = read_u64(MAGIC.len() + 8);
+    if len != (bytes.len() - header - 8) as u64 {
+        return Err(invalid("truncated autosave"));
+    }
+    let payload = &bytes[header..bytes.len() - 8];
+    if fnv1a(payload) != read_u64(bytes.len() - 8) {
+        return Err(invalid("autosave checksum mismatch"));
+    }
+
+    let world = World::load_compact(&mut &payload[..], params)?;
+    if world.step != step {
+        return Err(invalid("autosave step does not match its contents"));
+    }
+    Ok(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        terrain::Terrain,
+        world::{Position, Scenario, ScenarioAgent, small_params, test_world},
+    };
+
+    /// テストごとの作業フォルダの中の、自動保存のパス
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("rikulife-autosave-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("world")
+    }
+
+    #[test]
+    fn corrupt_newest_slot_falls_back_to_previous_save() {
+        let path = temp_path("corrupt");
+        let autosave = Autosave::new(path.clone(), 10);
+        let mut world = test_world(3, small_params());
+
+        world.run(10);
+        let older = autosave.save(&world).unwrap();
+        let older_hash = world.state_hash();
+        world.run(10);
+        let newer = autosave.save(&world).unwrap();
+        assert_ne!(older, newer, "saves should rotate between slots");
+
+        let latest = Autosave::load_latest(&path, world.params.clone()).unwrap();
+        assert_eq!(latest.step, 20);
+        assert_eq!(latest.state_hash(), world.state_hash());
+
+        // 新しいほうの真ん中を壊す
+        let mut bytes = fs::read(&newer).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        fs::write(&newer, &bytes).unwrap();
+        let recovered = Autosave::load_latest(&path, world.params.clone()).unwrap();
+        assert_eq!(recovered.step, 10);
+        assert_eq!(recovered.state_hash(), older_hash);
+
+        // 書いている途中で切れたのも同じ
+        fs::write(&newer, &bytes[..middle]).unwrap();
+        let recovered = Autosave::load_latest(&path, world.params.clone()).unwrap();
+        assert_eq!(recovered.state_hash(), older_hash);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn no_usable_slot_is_an_error() {
+        let path = temp_path("missing");
+        assert!(Autosave::load_latest(&path, Params::default()).is_err());
+
+        fs::write(Autosave::slot_path(&path, 0), b"junk").unwrap();
+        assert!(Autosave::load_latest(&path, Params::default()).is_err());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn resume_keeps_scenario_walls_and_continues_identically() {
+        let path = temp_path("resume");
+        let scenario = Scenario {
+            agents: (0..10)
+                .map(|i| ScenarioAgent::at(Position { x: 2 * i, y: 5 }))
+                .collect(),
+            foods: (0..10).map(|i| Position { x: 2 * i, y: 8 }).collect(),
+            walls: (0..30).map(|y| Position { x: 25, y }).collect(),
+        };
+        let mut world = World::from_scenario(&scenario, 9, Params::default()).unwrap();
+        let autosave = Autosave::new(path.clone(), 5);
+        world.run(5);
+        autosave.save(&world).unwrap();
+
+        let mut resumed = Autosave::load_latest(&path, world.params.clone()).unwrap();
+        assert_eq!(resumed.terrain[0][25], Terrain::Wall);
+        assert_eq!(resumed.terrain, world.terrain);
+        assert_eq!(resumed.state_hash(), world.state_hash());
+
+        world.run(30);
+        resumed.run(30);
+        assert_eq!(resumed.state_hash(), world.state_hash());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+//! 2つの世界の差分。
+//!
+//! 巻き戻し用にたくさんの時点を持っておいたり、少しずつ送ったりするときに、
+//! 毎回 `World` を丸ごとクローンすると重い（特に脳）。
+//! なので変わったマスと、変わった・増えた・減った個体だけを持つ。
+//! 脳は一生変わらないので、両方にいる個体は脳を抜いて持つ
+//! （脳が違ったら、いなくなって新しく増えたことにする）。
+
+use std::collections::HashMap;
+
+use super::{Position, World};
+use crate::{
+    agent::Agent,
+    brain::Brain,
+    event::StepEvent,
+    params::Params,
+    rng::RngStreams,
+    stats::{ActionTally, StepTimings},
+    terrain::Terrain,
+    world::{AgentId, HEIGHT, WIDTH},
+};
+
+/// `before` から `after` への差分。
+/// `apply` で前の状態から後の状態に、`revert` で後の状態から前の状態に戻せる
+#[derive(Debug, Clone)]
+pub struct WorldDiff {
+    before: Globals,
+    after: Globals,
+    /// 餌があるかどうかが入れ替わったマス
+    food_flips: Vec<Position>,
+    /// 地形が変わったマスと、前と後の地形
+    terrain_changes: Vec<(Position, Terrain, Terrain)>,
+    /// いなくなった個体（脳ごと、いなくなる前の状態。ID順）
+    removed: Vec<Agent>,
+    /// 増えた個体（脳ごと。ID順）
+    added: Vec<Agent>,
+    /// 両方にいて、脳以外の何かが変わった個体の前と後（脳は抜いてある。ID順）
+    changed: Vec<(Agent, Agent)>,
+}
+
+impl WorldDiff {
+    /// `before` から `after` への差分を取る
+    pub fn between(before: &World, after: &World) -> Self {
+        let mut food_flips = Vec::new();
+        let mut terrain_changes = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let pos = Position { x, y };
+                if before.foods[y][x] != after.foods[y][x] {
+                    food_flips.push(pos);
+                }
+                if before.terrain[y][x] != after.terrain[y][x] {
+                    terrain_changes.push((
+                        pos,
+                        before.terrain[y][x],
+                        after.terrain[y][x],
+                    ));
+                }
+            }
+        }
+
+        // HashMap の並びに依存しないように、ID順で見る
+        let mut ids: Vec<AgentId> = before
+            .agents
+            .keys()
+            .chain(after.agents.keys())
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let (mut removed, mut added, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+        for id in ids {
+            match (before.agents.get(&id), after.agents.get(&id)) {
+                (Some(old), Some(new)) if old.brain != new.brain => {
+                    removed.push(old.clone());
+                    added.push(new.clone());
+                }
+                (Some(old), Some(new)) => {
+                    let (old, new) =
+                        (old.clone_without_brain(), new.clone_without_brain());
+                    if old != new {
+                        changed.push((old, new));
+                    }
+                }
+                (Some(old), None) => removed.push(old.clone()),
+                (None, Some(new)) => added.push(new.clone()),
+                (None, None) => unreachable!(),
+            }
+        }
+
+        Self {
+            before: Globals::of(before),
+            after: Globals::of(after),
+            food_flips,
+            terrain_changes,
+            removed,
+            added,
+            changed,
+        }
+    }
+
+    /// 差分の前のステップ
+    pub fn from_step(&self) -> u64 {
+        self.before.step
+    }
+
+    /// 差分の後のステップ
+    pub fn to_step(&self) -> u64 {
+        self.after.step
+    }
+
+    /// 前の状態の `world` を、後の状態にする
+    pub fn apply(&self, world: &mut World) {
+        debug_assert_eq!(world.step, self.before.step, "diff applied to another step");
+        self.swap(world, Side::After);
+    }
+
+    /// 後の状態の `world` を、前の状態に戻す
+    pub fn revert(&self, world: &mut World) {
+        debug_assert_eq!(
+            world.step, self.after.step,
+            "diff reverted from another step"
+        );
+        self.swap(world, Side::Before);
+    }
+
+    /// `to` の側の状態にする
+    fn swap(&self, world: &mut World, to: Side) {
+        let (gone, arrived, globals) = match to {
+            Side::After => (&self.removed, &self.added, &self.after),
+            Side::Before => (&self.added, &self.removed, &self.before),
+        };
+
+        for agent in gone {
+            world.remove_agent(agent.id);
+        }
+        for (before, after) in &self.changed {
+            let target = match to {
+                Side::After => after,
+                Side::Before => before,
+            };
+            let agent = world
+                .agents
+                .get_mut(&target.id)
+                .unwrap_or_else(|| panic!("diff expects agent {}", target.id));
+            // 脳はそのまま残して、ほかを入れ替える
+            let brain = std::mem::replace(&mut agent.brain, Brain::empty());
+            let old_pos = agent.pos;
+            *agent = target.clone();
+            agent.brain = brain;
+            if old_pos != target.pos {
+                world.grid_remove(target.id, old_pos);
+                world.grid_insert(target.id, target.pos);
+            }
+        }
+        for agent in arrived {
+            world.grid_insert(agent.id, agent.pos);
+            world.agents.insert(agent.id, agent.clone());
+        }
+
+        for &pos in &self.food_flips {
+            world.foods[pos.y][pos.x] = !world.foods[pos.y][pos.x];
+        }
+        for &(pos, before, after) in &self.terrain_changes {
+            world.terrain[pos.y][pos.x] = match to {
+                Side::After => after,
+                Side::Before => before,
+            };
+        }
+
+        globals.restore(world);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Before,
+    After,
+}
+
+/// 個体とマス以外の世界の状態。小さいので丸ごと持つ
+#[derive(Debug, Clone)]
+struct Globals {
+    step: u64,
+    rngs: RngStreams,
+    seed: u64,
+    next_id: usize,
+    params: Params,
+    peak_population: usize,
+    peak_step: u64,
+    extinction_step: Option<u64>,
+    smoothed_energy: Option<f32>,
+    genome_diversity: Option<f32>,
+    converged: bool,
+    activity: Option<f32>,
+    stagnant: bool,
+    window_positions: HashMap<AgentId, Position>,
+    events: Vec<StepEvent>,
+    shuffles_this_step: u64,
+    action_tally: ActionTally,
+    timings: StepTimings,
+    total_timings: StepTimings,
+}
+
+impl Globals {
+    /// `world` から取り出す。
+    /// `World` にフィールドを増やしたら、ここでコンパイルエラーになって気づけるようにしてある
+    fn of(world: &World) -> Self {
+        let World {
+            step,
+            agents: _,
+            grid: _,
+            foods: _,
+            terrain: _,
+            rngs,
+            seed,
+            next_id,
+            params,
+            peak_population,
+            peak_step,
+            extinction_step,
+            smoothed_energy,
+            genome_diversity,
+            converged,
+            activity,
+            stagnant,
+            window_positions,
+            events,
+            // 更新順の作業用のバッファなので、中身は状態じゃない
+            order_buf: _,
+            shuffles_this_step,
+            action_tally,
+            timings,
+            total_timings,
+        } = world;
+        Self {
+            step: *step,
+            rngs: rngs.clone(),
+            seed: *seed,
+            next_id: *next_id,
+            params: params.clone(),
+            peak_population: *peak_population,
+            peak_step: *peak_step,
+            extinction_step: *extinction_step,
+            smoothed_energy: *smoothed_energy,
+            genome_diversity: *genome_diversity,
+            converged: *converged,
+            activity: *activity,
+            stagnant: *stagnant,
+            window_positions: window_positions.clone(),
+            events: events.clone(),
+            shuffles_this_step: *shuffles_this_step,
+            action_tally: *action_tally,
+            timings: *timings,
+            total_timings: *total_timings,
+        }
+    }
+
+    /// `world` に書き戻す
+    fn restore(&self, world: &mut World) {
+        let this = self.clone();
+        world.step = this.step;
+        world.rngs = this.rngs;
+        world.seed = this.seed;
+        world.next_id = this.next_id;
+        world.params = this.params;
+        world.peak_population = this.peak_population;
+        world.peak_step = this.peak_step;
+        world.extinction_step = this.extinction_step;
+        world.smoothed_energy = this.smoothed_energy;
+        world.genome_diversity = this.genome_diversity;
+        world.converged = this.converged;
+        world.activity = this.activity;
+        world.stagnant = this.stagnant;
+        world.window_positions = this.window_positions;
+        world.events = this.events;
+        world.shuffles_this_step = this.shuffles_this_step;
+        world.action_tally = this.action_tally;
+        world.timings = this.timings;
+        world.total_timings = this.total_timings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{small_params, test_world};
+
+    /// 見た目（グリッド・餌・地形）と中身（ハッシュ）が同じか
+    fn assert_same(world: &World, expected: &World) {
+        assert_eq!(world.step, expected.step);
+        assert_eq!(world.grid, expected.grid);
+        assert_eq!(world.foods, expected.foods);
+        assert_eq!(world.terrain, expected.terrain);
+        assert_eq!(world.state_hash(), expected.state_hash());
+    }
+
+    #[test]
+    fn apply_and_revert_reproduce_both_states_exactly() {
+        let mut world = test_world(4, small_params());
+        world.run(5);
+        let start = world.clone();
+
+        // 1ステップずつの差分を取っておく
+        let mut states = vec![world.clone()];
+        let mut diffs = Vec::new();
+        for _ in 0..30 {
+            world.step();
+            diffs.push(WorldDiff::between(states.last().unwrap(), &world));
+            states.push(world.clone());
+        }
+        assert!(
+            diffs
+                .iter()
+                .any(|d| !d.added.is_empty() || !d.removed.is_empty())
+        );
+
+        // 前の状態に当てると次の状態に、戻すと前の状態に
+        for (i, diff) in diffs.iter().enumerate() {
+            assert_eq!(
+                (diff.from_step(), diff.to_step()),
+                (5 + i as u64, 6 + i as u64)
+            );
+            let mut next = states[i].clone();
+            diff.apply(&mut next);
+            assert_same(&next, &states[i + 1]);
+            diff.revert(&mut next);
+            assert_same(&next, &states[i]);
+        }
+
+        // 全部戻してから全部当て直しても同じで、そこから先も同じように進む
+        let mut replay = world.clone();
+        for diff in diffs.iter().rev() {
+            diff.revert(&mut replay);
+        }
+        assert_same(&replay, &start);
+        for diff in &diffs {
+            diff.apply(&mut replay);
+        }
+        assert_same(&replay, &world);
+        replay.run(20);
+        world.run(20);
+        assert_same(&replay, &world);
+    }
+}
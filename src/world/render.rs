@@ -0,0 +1,141 @@
+//! フィールドを画像にする。
+//!
+//! 1マスが1ピクセルで、色の対応は読み込み（`World::from_image`）と同じ `ImageMapping`。
+//! なので書き出した画像は、そのまま初期配置として読み込み直せる。
+//! 長い実行のコマ撮りを GIF にしたいときのために、256色に減らした書き出しもできる。
+
+use std::collections::BTreeSet;
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+use super::{ImageMapping, World};
+use crate::world::{HEIGHT, WIDTH};
+
+impl World {
+    /// 今の様子を画像にする。
+    /// 個体がいるマスはその個体の色（重なっていたら先にいた個体）、いなければ餌・壁・何もない、の色
+    pub fn render_to_image(&self, mapping: &ImageMapping) -> RgbImage {
+        let mut image = RgbImage::new(WIDTH as u32, HEIGHT as u32);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let color = if let Some(id) = self.grid[y][x].first() {
+                    self.agents[id]
+                        .color
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+                } else if !self.terrain[y][x].is_passable() {
+                    mapping.wall
+                } else if self.foods[y][x] {
+                    mapping.food
+                } else {
+                    mapping.empty
+                };
+                image.put_pixel(x as u32, y as u32, Rgb(color));
+            }
+        }
+        image
+    }
+}
+
+/// 256色までのパレット。GIF みたいな、色に番号をふって描く形式に書き出す用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// パレットに入れられる色の数
+    pub const MAX_COLORS: usize = 256;
+
+    /// 画像で使われている色から作る（色の値の順に並べるので、同じ画像なら同じパレット）。
+    /// 256色に収まらなければ、決まったパレット（`Palette::fixed`）にする
+    pub fn from_image(image: &RgbImage) -> Self {
+        let used: BTreeSet<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+        if used.len() <= Self::MAX_COLORS {
+            Self {
+                colors: used.into_iter().collect(),
+            }
+        } else {
+            Self::fixed()
+        }
+    }
+
+    /// 決まった256色（赤8段階 x 緑8段階 x 青4段階）。
+    /// 白・黒・緑 (0,255,0) はちょうど入っているので、餌・壁・何もないマスの色はずれない
+    pub fn fixed() -> Self {
+        let level = |i: usize, levels: usize| (i * 255 / (levels - 1)) as u8;
+        let colors = (0..Self::MAX_COLORS)
+            .map(|i| [level(i >> 5, 8), level((i >> 2) & 7, 8), level(i & 3, 4)])
+            .collect();
+        Self { colors }
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// `color` の番号。パレットになければ一番近い色（RGB の距離で。同じなら番号が小さいほう）
+    pub fn index_of(&self, color: [u8; 3]) -> u8 {
+        let distance = |c: &[u8; 3]| {
+            (0..3)
+                .map(|i| (c[i] as i32 - color[i] as i32).pow(2))
+                .sum::<i32>()
+        };
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| distance(c))
+            .map_or(0, |(i, _)| i as u8)
+    }
+
+    /// 画像の各ピクセルを、パレットの番号に置き換える
+    pub fn quantize(&self, image: &RgbImage) -> GrayImage {
+        GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            Luma([self.index_of(image.get_pixel(x, y).0)])
+        })
+    }
+
+    /// RGB を並べたバイト列（色の数 x 3 バイト）。`gif` クレートなどにそのまま渡せる
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_from_image_indexes_colors_in_value_order() {
+        let mut image = RgbImage::from_pixel(4, 1, Rgb([255, 255, 255]));
+        image.put_pixel(1, 0, Rgb([0, 255, 0]));
+        image.put_pixel(2, 0, Rgb([10, 20, 30]));
+        let palette = Palette::from_image(&image);
+        assert_eq!(
+            palette.colors(),
+            [[0, 255, 0], [10, 20, 30], [255, 255, 255]]
+        );
+        assert_eq!(palette.to_bytes().len(), 9);
+
+        let indexed = palette.quantize(&image);
+        let indices: Vec<u8> = indexed.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(indices, [2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn fixed_palette_keeps_known_colors_and_snaps_the_rest() {
+        let palette = Palette::fixed();
+        assert_eq!(palette.colors().len(), Palette::MAX_COLORS);
+        assert_eq!(palette.index_of([0, 0, 0]), 0);
+        assert_eq!(palette.index_of([255, 255, 255]), 255);
+        assert_eq!(palette.index_of([0, 255, 0]), 28);
+        // パレットにない色は一番近い色に
+        assert_eq!(palette.index_of([250, 250, 250]), 255);
+        assert_eq!(palette.index_of([3, 250, 2]), 28);
+    }
+
+    #[test]
+    fn too_many_colors_fall_back_to_the_fixed_palette() {
+        let image = RgbImage::from_fn(20, 20, |x, y| Rgb([x as u8, y as u8, 7]));
+        assert_eq!(Palette::from_image(&image), Palette::fixed());
+    }
+}
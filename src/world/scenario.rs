@@ -0,0 +1,274 @@
+//! 決まった初期配置を作る。
+//!
+//! 画像から読む（`World::from_image`）なら、1ピクセルが1マスで、色で中身を決める（`ImageMapping`）。
+//! 餌・壁・何もない、のどれでもない色のピクセルには、その色の個体を置く。
+//! 個体の脳はランダム。
+//!
+//! 置くものを全部並べて作る（`World::from_scenario`）なら、色や脳も1匹ずつ決められる。
+//! 「餌をはさんで向かい合った2匹」みたいな、ぴったりの状況を作りたいとき用。
+
+use std::{io, path::Path};
+
+use super::{Position, World};
+use crate::{
+    agent::AgentSpec,
+    params::Params,
+    terrain::Terrain,
+    world::{HEIGHT, WIDTH},
+};
+
+/// 置くものを全部並べた初期配置。乱数で撒いたりはしない
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    /// 置く個体。ID はこの順に 0, 1, 2, ... になる
+    pub agents: Vec<ScenarioAgent>,
+    /// 餌を置くマス
+    pub foods: Vec<Position>,
+    /// 壁にするマス（ほかは全部平地）
+    pub walls: Vec<Position>,
+}
+
+/// `Scenario` に置く1匹
+#[derive(Debug, Clone)]
+pub struct ScenarioAgent {
+    pub pos: Position,
+    /// 色や脳など、決めておく部分（書かなかった部分はシードで決まるランダム）
+    pub spec: AgentSpec,
+}
+
+impl ScenarioAgent {
+    /// `pos` に置く、ほかは全部おまかせの個体
+    pub fn at(pos: Position) -> Self {
+        Self {
+            pos,
+            spec: AgentSpec::default(),
+        }
+    }
+}
+
+/// 画像の色と、マスの中身の対応
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageMapping {
+    /// 何もないマス
+    pub empty: [u8; 3],
+    pub food: [u8; 3],
+    pub wall: [u8; 3],
+}
+
+impl Default for ImageMapping {
+    /// 白が何もない、緑が餌、黒が壁
+    fn default() -> Self {
+        Self {
+            empty: [255, 255, 255],
+            food: [0, 255, 0],
+            wall: [0, 0, 0],
+        }
+    }
+}
+
+impl World {
+    /// 画像（PNG）から初期配置を作る。
+    /// 画像の大きさはフィールドと同じ（`WIDTH` x `HEIGHT`）でないといけない。
+    /// 半透明のピクセル（アルファが半分未満）は何もないマスとして扱う
+    pub fn from_image(
+        path: &Path,
+        mapping: &ImageMapping,
+        seed: u64,
+        params: Params,
+    ) -> io::Result<Self> {
+        let image = image::open(path)
+            .map_err(|e| invalid(format!("cannot read {}: {e}", path.display())))?
+            .into_rgba8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if (width, height) != (WIDTH, HEIGHT) {
+            return Err(invalid(format!(
+                "image {} is {width}x{height}, but the field is {WIDTH}x{HEIGHT}",
+                path.display()
+            )));
+        }
+
+        let mut world = World::with_params(seed, params);
+        // 地形は画像で決めるので、乱数で作った分は消す
+        world.terrain = vec![vec![Terrain::Plains; WIDTH]; HEIGHT];
+
+        // 先に壁と餌を置いてから、個体を置く（ID は左上から順になる）
+        let mut agents = Vec::new();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            let [r, g, b, a] = pixel.0;
+            let rgb = [r, g, b];
+            if a < 128 || rgb == mapping.empty {
+                continue;
+            }
+            if rgb == mapping.wall {
+                world.terrain[y][x] = Terrain::Wall;
+            } else if rgb == mapping.food {
+                world.foods[y][x] = true;
+            } else {
+                agents.push((Position { x, y }, rgb));
+            }
+        }
+
+        for (pos, rgb) in agents {
+            let spec = AgentSpec {
+                color: Some(rgb.map(|c| c as f32 / 255.0)),
+                ..AgentSpec::default()
+            };
+            world
+                .try_add_agent(pos, spec)
+                .ok_or_else(|| invalid(format!("cannot place an agent at {pos:?}")))?;
+        }
+
+        Ok(world)
+    }
+
+    /// 並べたとおりに初期配置を作る。壁、餌、個体の順に置く。
+    /// フィールドの外や壁の上、もう入れないマスに個体を置こうとしたり、
+    /// 脳の形が設定と合わなかったりしたらエラーにする
+    pub fn from_scenario(
+        scenario: &Scenario,
+        seed: u64,
+        params: Params,
+    ) -> io::Result<Self> {
+        let mut world = World::with_params(seed, params);
+        let check = |world: &World, pos: Position, what: &str| {
+            if world.in_bounds(pos.x as isize, pos.y as isize) {
+                Ok(())
+            } else {
+                Err(invalid(format!(
+                    "{what} at {pos:?} is outside the {WIDTH}x{HEIGHT} field"
+                )))
+            }
+        };
+
+        // 地形も並べたとおりにするので、乱数で作った分は消す
+        world.terrain = vec![vec![Terrain::Plains; WIDTH]; HEIGHT];
+        for &pos in &scenario.walls {
+            check(&world, pos, "wall")?;
+            world.terrain[pos.y][pos.x] = Terrain::Wall;
+        }
+        for &pos in &scenario.foods {
+            check(&world, pos, "food")?;
+            world.foods[pos.y][pos.x] = true;
+        }
+
+        let brain_shape = world.params.brain_shape();
+        for agent in &scenario.agents {
+            check(&world, agent.pos, "agent")?;
+            // 置けなかった理由が分かるように、脳の形は先に見ておく
+            if let Some(brain) = &agent.spec.brain
+                && brain.shape() != brain_shape
+            {
+                let (input, hidden, output) = brain.shape();
+                return Err(invalid(format!(
+                    "agent at {:?} has a {input}-{hidden}-{output} brain, \
+                     but the config needs {}-{}-{}",
+                    agent.pos, brain_shape.0, brain_shape.1, brain_shape.2
+                )));
+            }
+
+            world
+                .try_add_agent(agent.pos, agent.spec.clone())
+                .ok_or_else(|| {
+                    invalid(format!("cannot place an agent at {:?}", agent.pos))
+                })?;
+        }
+
+        Ok(world)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Direction;
+
+    #[test]
+    fn positions_just_outside_the_field_are_rejected() {
+        let corner = Position {
+            x: WIDTH - 1,
+            y: HEIGHT - 1,
+        };
+        let inside = Scenario {
+            walls: vec![corner],
+            ..Scenario::default()
+        };
+        assert!(World::from_scenario(&inside, 1, Params::default()).is_ok());
+
+        for outside in [Position { x: WIDTH, y: 0 }, Position { x: 0, y: HEIGHT }] {
+            let scenarios = [
+                Scenario {
+                    walls: vec![outside],
+                    ..Scenario::default()
+                },
+                Scenario {
+                    foods: vec![outside],
+                    ..Scenario::default()
+                },
+                Scenario {
+                    agents: vec![ScenarioAgent::at(outside)],
+                    ..Scenario::default()
+                },
+            ];
+            for scenario in scenarios {
+                let error = World::from_scenario(&scenario, 1, Params::default())
+                    .expect_err("outside the field");
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_scenario_is_placed_exactly() {
+        // 餌をはさんで向かい合った2匹と、その上の壁
+        let facing = |x, facing, color| ScenarioAgent {
+            pos: Position { x, y: 10 },
+            spec: AgentSpec {
+                color: Some(color),
+                energy: Some(30),
+                facing: Some(facing),
+                ..AgentSpec::default()
+            },
+        };
+        let scenario = Scenario {
+            agents: vec![
+                facing(10, Direction::Right, [1.0, 0.0, 0.0]),
+                facing(12, Direction::Left, [0.0, 0.0, 1.0]),
+            ],
+            foods: vec![Position { x: 11, y: 10 }],
+            walls: vec![Position { x: 11, y: 9 }],
+        };
+        let world = World::from_scenario(&scenario, 1, Params::default()).unwrap();
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let expected_agents: &[_] = match (x, y) {
+                    (10, 10) => &[0],
+                    (12, 10) => &[1],
+                    _ => &[],
+                };
+                assert_eq!(world.grid[y][x], expected_agents, "agents at ({x}, {y})");
+                assert_eq!(world.foods[y][x], (x, y) == (11, 10), "food at ({x}, {y})");
+                let terrain = if (x, y) == (11, 9) {
+                    Terrain::Wall
+                } else {
+                    Terrain::Plains
+                };
+                assert_eq!(world.terrain[y][x], terrain, "terrain at ({x}, {y})");
+            }
+        }
+
+        assert_eq!(world.agents.len(), 2);
+        for (id, agent) in scenario.agents.iter().enumerate() {
+            let placed = &world.agents[&(id as _)];
+            assert_eq!(placed.pos, agent.pos);
+            assert_eq!(Some(placed.color), agent.spec.color);
+            assert_eq!(Some(placed.energy), agent.spec.energy);
+            assert_eq!(Some(placed.facing), agent.spec.facing);
+        }
+    }
+}
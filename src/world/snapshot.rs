@@ -0,0 +1,399 @@
+//! 世界のコンパクトなバイナリ形式での保存・読み込み。
+//!
+//! 形式（数値は全部リトルエンディアン）:
+//! - マジック `b"RKLF"`、バージョン (u8)
+//! - フィールドの幅・高さ (u32 x2)
+//! - シード、ステップ、次のID、最大生存数とそのステップ (u64 x5)
+//! - 全滅したステップ（あるかどうか u8、あれば u64）
+//! - 平均エネルギーの移動平均（あるかどうか u8、あれば f32）
+//! - 乱数列の状態（`RngStream::ALL` の順に、シード 32バイト、ストリーム u64、位置 u128）
+//! - 餌の有無（1マス1ビット、行ごとに左から）
+//! - 地形（1マス1バイト、`Terrain::ALL` の番号、行ごとに左から）。
+//!   シナリオで置いた壁などはシードから作り直せないので、そのまま持つ
+//! - エージェントの数 (u32) と、ID順に並べたエージェント（脳の重みは生の f32）
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+};
+
+use super::{Position, World};
+use crate::{
+    agent::{ACTION_HISTORY_LEN, Action, Agent, Direction},
+    brain::Brain,
+    params::Params,
+    rng::RngStreams,
+    terrain::Terrain,
+    world::{HEIGHT, WIDTH},
+};
+
+const MAGIC: &[u8; 4] = b"RKLF";
+const VERSION: u8 = 11;
+
+/// FNV-1a（64ビット）の定数
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// `Option` のないことを表す値
+const NONE_U8: u8 = u8::MAX;
+
+impl World {
+    /// コンパクトなバイナリ形式で書き出す。
+    /// 設定（`Params`）は含まないので、読み込むときに渡す。
+    pub fn save_compact<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        write_u8(w, VERSION)?;
+        write_u32(w, WIDTH as u32)?;
+        write_u32(w, HEIGHT as u32)?;
+
+        write_u64(w, self.seed)?;
+        write_u64(w, self.step)?;
+        write_u64(w, self.next_id as u64)?;
+        write_u64(w, self.peak_population as u64)?;
+        write_u64(w, self.peak_step)?;
+        match self.extinction_step {
+            Some(step) => {
+                write_u8(w, 1)?;
+                write_u64(w, step)?;
+            }
+            None => write_u8(w, 0)?,
+        }
+        match self.smoothed_energy {
+            Some(energy) => {
+                write_u8(w, 1)?;
+                write_f32(w, energy)?;
+            }
+            None => write_u8(w, 0)?,
+        }
+
+        self.rngs.write_to(w)?;
+
+        // 餌は1マス1ビットに詰める
+        let mut bits = vec![0u8; (WIDTH * HEIGHT).div_ceil(8)];
+        for (i, &has_food) in self.foods.iter().flatten().enumerate() {
+            if has_food {
+                bits[i / 8] |= 1 << (i % 8);
+            }
+        }
+        w.write_all(&bits)?;
+
+        let terrain: Vec<u8> = self.terrain.iter().flatten().map(|&t| t as u8).collect();
+        w.write_all(&terrain)?;
+
+        let mut ids: Vec<_> = self.living_ids().collect();
+        ids.sort_unstable();
+        write_u32(w, ids.len() as u32)?;
+        for id in ids {
+            write_agent(w, &self.agents[&id])?;
+        }
+
+        Ok(())
+    }
+
+    /// 世界の状態のハッシュ（`save_compact` の中身の FNV-1a）。
+    /// 同じシード・同じ設定で同じだけ進めたら、どの環境でも同じ値になる。
+    /// 挙動が変わっていないかの確認用
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        self.save_compact(&mut bytes)
+            .expect("writing to a Vec never fails");
+        fnv1a(&bytes)
+    }
+
+    /// `save_compact` で書き出したものを読み込む。
+    /// マジック・バージョン・フィールドの大きさが合わなければエラー。
+    pub fn load_compact<R: Read>(r: &mut R, params: Params) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a rikulife world file"));
+        }
+        let version = read_u8(r)?;
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unsupported version {version} (expected {VERSION})"
+            )));
+        }
+        let (width, height) = (read_u32(r)? as usize, read_u32(r)? as usize);
+        if (width, height) != (WIDTH, HEIGHT) {
+            return Err(invalid(format!(
+                "world size {width}x{height} does not match {WIDTH}x{HEIGHT}"
+            )));
+        }
+
+        let seed = read_u64(r)?;
+        let mut world = World::with_params(seed, params);
+        world.step = read_u64(r)?;
+        world.next_id = read_u64(r)? as usize;
+        world.peak_population = read_u64(r)? as usize;
+        world.peak_step = read_u64(r)?;
+        world.extinction_step = match read_u8(r)? {
+            0 => None,
+            _ => Some(read_u64(r)?),
+        };
+        world.smoothed_energy = match read_u8(r)? {
+            0 => None,
+            _ => Some(read_f32(r)?),
+        };
+
+        world.rngs = RngStreams::read_from(r)?;
+
+        let mut bits = vec![0u8; (WIDTH * HEIGHT).div_ceil(8)];
+        r.read_exact(&mut bits)?;
+        for (i, cell) in world.foods.iter_mut().flatten().enumerate() {
+            *cell = bits[i / 8] & (1 << (i % 8)) != 0;
+        }
+
+        // 地形は個体より先に読む（壁の上にいないかを確かめるので）
+        let mut terrain = vec![0u8; WIDTH * HEIGHT];
+        r.read_exact(&mut terrain)?;
+        for (cell, &index) in world.terrain.iter_mut().flatten().zip(&terrain) {
+            *cell = *Terrain::ALL
+                .get(index as usize)
+                .ok_or_else(|| invalid(format!("unknown terrain {index}")))?;
+        }
+
+        let count = read_u32(r)?;
+        for _ in 0..count {
+            let agent = read_agent(r, &world.params)?;
+            if !world.in_bounds(agent.pos.x as isize, agent.pos.y as isize) {
+                return Err(invalid(format!("agent {} is out of bounds", agent.id)));
+            }
+            if !world.has_room(agent.pos) {
+                return Err(invalid(format!("agent {} overlaps another", agent.id)));
+            }
+            let pos = agent.pos;
+            world.add_agent(agent, pos);
+        }
+
+        Ok(world)
+    }
+}
+
+fn write_agent<W: Write>(w: &mut W, agent: &Agent) -> io::Result<()> {
+    write_u64(w, agent.id as u64)?;
+    write_u8(w, agent.name.len() as u8)?;
+    w.write_all(agent.name.as_bytes())?;
+    write_u32(w, agent.pos.x as u32)?;
+    write_u32(w, agent.pos.y as u32)?;
+    write_u32(w, agent.energy)?;
+    write_u32(w, agent.max_energy)?;
+    write_f32(w, agent.armor)?;
+    write_u32(w, agent.reserve)?;
+    write_u32(w, agent.generation)?;
+    write_u64(w, agent.founder as u64)?;
+    for c in agent.color {
+        write_f32(w, c)?;
+    }
+    write_u8(w, agent.last_action.map_or(NONE_U8, |a| a as u8))?;
+    write_u8(w, agent.action_history.len() as u8)?;
+    for &action in &agent.action_history {
+        write_u8(w, action as u8)?;
+    }
+    match agent.last_attack_step {
+        Some(step) => {
+            write_u8(w, 1)?;
+            write_u64(w, step)?;
+        }
+        None => write_u8(w, 0)?,
+    }
+    write_u8(w, agent.facing as u8)?;
+    write_f32(w, agent.recent_attacks)?;
+    write_f32(w, agent.recent_food)?;
+    write_u32(w, agent.starving_steps)?;
+    write_u32(w, agent.age)?;
+    write_u32(w, agent.lifespan)?;
+    agent.brain.write_to(w)
+}
+
+fn read_action(index: u8) -> io::Result<Action> {
+    Action::from_index(index as usize)
+        .ok_or_else(|| invalid(format!("unknown action {index}")))
+}
+
+/// `params` は今の設定（脳の入出力の数が合わない脳は読み込まない）
+fn read_agent<R: Read>(r: &mut R, params: &Params) -> io::Result<Agent> {
+    let id = read_u64(r)? as usize;
+    let mut name = vec![0; read_u8(r)? as usize];
+    r.read_exact(&mut name)?;
+    let name = String::from_utf8(name).map_err(invalid)?;
+    let pos = Position {
+        x: read_u32(r)? as usize,
+        y: read_u32(r)? as usize,
+    };
+    let energy = read_u32(r)?;
+    let max_energy = read_u32(r)?;
+    let armor = read_f32(r)?;
+    let reserve = read_u32(r)?;
+    let generation = read_u32(r)?;
+    let founder = read_u64(r)? as usize;
+    let color = [read_f32(r)?, read_f32(r)?, read_f32(r)?];
+    let last_action = match read_u8(r)? {
+        NONE_U8 => None,
+        index => Some(read_action(index)?),
+    };
+    let history_len = read_u8(r)? as usize;
+    if history_len > ACTION_HISTORY_LEN {
+        return Err(invalid(format!("action history too long ({history_len})")));
+    }
+    let mut action_history = VecDeque::with_capacity(ACTION_HISTORY_LEN);
+    for _ in 0..history_len {
+        action_history.push_back(read_action(read_u8(r)?)?);
+    }
+    let last_attack_step = match read_u8(r)? {
+        0 => None,
+        _ => Some(read_u64(r)?),
+    };
+    let facing = *Direction::ALL
+        .get(read_u8(r)? as usize)
+        .ok_or_else(|| invalid("unknown direction"))?;
+    let recent_attacks = read_f32(r)?;
+    let recent_food = read_f32(r)?;
+    let starving_steps = read_u32(r)?;
+    let age = read_u32(r)?;
+    let lifespan = read_u32(r)?;
+    // 今の脳と形が違うと、計算するときに落ちるので読み込まない
+    let brain =
+        Brain::read_from(r, params.brain_shape()).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => invalid(format!("agent {id} has a {e}")),
+            _ => e,
+        })?;
+
+    Ok(Agent {
+        id,
+        name,
+        pos,
+        energy,
+        max_energy,
+        armor,
+        reserve,
+        generation,
+        founder,
+        brain,
+        color,
+        last_action,
+        action_history,
+        last_attack_step,
+        facing,
+        recent_attacks,
+        recent_food,
+        starving_steps,
+        age,
+        lifespan,
+    })
+}
+
+/// FNV-1a（64ビット）
+pub(super) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+pub(super) fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn write_u8<W: Write>(w: &mut W, v: u8) -> io::Result<()> {
+    w.write_all(&[v])
+}
+
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_f32<W: Write>(w: &mut W, v: f32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{small_params, test_world};
+
+    fn populated_world() -> World {
+        let mut world = test_world(21, small_params());
+        world.terrain[3][4] = Terrain::Mud;
+        world.run(50);
+        world
+    }
+
+    fn saved(world: &World) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        world.save_compact(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_keeps_the_whole_world() {
+        let mut world = populated_world();
+        assert!(!world.agents.is_empty());
+        let bytes = saved(&world);
+
+        let mut loaded =
+            World::load_compact(&mut &bytes[..], world.params.clone()).unwrap();
+        assert_eq!(loaded.step, world.step);
+        assert_eq!(loaded.next_id, world.next_id);
+        assert_eq!(loaded.agents, world.agents);
+        assert_eq!(loaded.foods, world.foods);
+        assert_eq!(loaded.terrain, world.terrain);
+        assert_eq!(saved(&loaded), bytes);
+
+        // 乱数列も戻っているので、そのあとも同じように進む
+        world.run(20);
+        loaded.run(20);
+        assert_eq!(loaded.state_hash(), world.state_hash());
+    }
+
+    #[test]
+    fn load_rejects_wrong_header() {
+        let bytes = saved(&populated_world());
+        let load = |bytes: &[u8]| World::load_compact(&mut &bytes[..], Params::default());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(load(&bad_magic).is_err());
+
+        let mut bad_version = bytes.clone();
+        bad_version[MAGIC.len()] = VERSION + 1;
+        assert!(load(&bad_version).is_err());
+
+        let mut bad_width = bytes.clone();
+        bad_width[MAGIC.len() + 1] = WIDTH as u8 + 1;
+        assert!(load(&bad_width).is_err());
+
+        assert!(load(&bytes[..bytes.len() - 1]).is_err());
+        assert!(load(&bytes).is_ok());
+    }
+}